@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use log::error;
+
+use crate::{AccessRules, CommandMsg, Mount, Protocol, ProtocolStats, QuickServeError, QuickServeResult, Server, ServerAction, ServerRegistry, ServerStatus, Stats, SymlinkPolicy, Transfer};
+
+/// Entry point of the library API
+///
+/// See [`QuickServe::builder`].
+pub struct QuickServe;
+
+impl QuickServe {
+    /// Starts describing a set of servers to be spawned in-process
+    pub fn builder() -> QuickServeBuilder {
+        QuickServeBuilder::default()
+    }
+}
+
+/// Describes the servers to be spawned by [`QuickServeBuilder::spawn`]
+#[derive(Clone)]
+pub struct QuickServeBuilder {
+    dir: PathBuf,
+    bind_ip: String,
+    mounts: Vec<Mount>,
+    symlinks: SymlinkPolicy,
+    access: AccessRules,
+    servers: Vec<(Protocol, u16)>,
+    registry: ServerRegistry,
+}
+
+impl Default for QuickServeBuilder {
+    fn default() -> Self {
+        QuickServeBuilder {
+            dir: PathBuf::from("/tmp/"),
+            bind_ip: "127.0.0.1".to_string(),
+            mounts: Vec::new(),
+            symlinks: SymlinkPolicy::default(),
+            access: AccessRules::default(),
+            servers: Vec::new(),
+            registry: ServerRegistry::default(),
+        }
+    }
+}
+
+impl QuickServeBuilder {
+    /// Sets the directory to serve [default: /tmp/]
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Sets the IP the servers bind to [default: 127.0.0.1]
+    ///
+    /// Several comma separated addresses can be given, e.g. `0.0.0.0, ::`,
+    /// each one getting its own listener.
+    pub fn bind_ip(mut self, ip: impl Into<String>) -> Self {
+        self.bind_ip = ip.into();
+        self
+    }
+
+    /// Grafts a directory or file into the served tree
+    ///
+    /// e.g. `.mount("/fw=/opt/firmware".parse()?)`. Applies to all
+    /// file serving protocols alike.
+    pub fn mount(mut self, mount: Mount) -> Self {
+        self.mounts.retain(|m| m.at != mount.at);
+        self.mounts.push(mount);
+        self
+    }
+
+    /// Sets how links found in the served tree are handled [default: follow-within-root]
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets the rules deciding what may be served [default: hide dotfiles and system directories]
+    pub fn access(mut self, rules: AccessRules) -> Self {
+        self.access = rules;
+        self
+    }
+
+    /// Adds a server for any registered protocol
+    pub fn server(mut self, protocol: Protocol, port: u16) -> Self {
+        self.servers.retain(|(p, _)| *p != protocol);
+        self.servers.push((protocol, port));
+        self
+    }
+
+    /// Adds an HTTP server on the given port
+    pub fn http(self, port: u16) -> Self {
+        self.server(Protocol::Http, port)
+    }
+
+    /// Adds an FTP server on the given port
+    pub fn ftp(self, port: u16) -> Self {
+        self.server(Protocol::Ftp, port)
+    }
+
+    /// Adds a TFTP server on the given port
+    pub fn tftp(self, port: u16) -> Self {
+        self.server(Protocol::Tftp, port)
+    }
+
+    /// Adds a DHCP server on the given port
+    pub fn dhcp(self, port: u16) -> Self {
+        self.server(Protocol::Dhcp, port)
+    }
+
+    /// Replaces the registry the backends are created from
+    pub fn registry(mut self, registry: ServerRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Starts all requested servers
+    ///
+    /// Returns once every server is listening, so the handle can be used
+    /// right away to find where each one is bound.
+    ///
+    /// # Returns
+    /// * `Ok(QuickServeHandle)` - Once every server is listening
+    /// * `Err(QuickServeError)` - If any server failed to start. The ones
+    ///   already started are stopped again.
+    pub async fn spawn(self) -> QuickServeResult<QuickServeHandle> {
+        let mut handle = QuickServeHandle {
+            servers: Vec::new(),
+            stats: Stats::default(),
+        };
+
+        for (protocol, port) in self.servers {
+            let cmd = CommandMsg {
+                action: ServerAction::Start,
+                port,
+                protocol: protocol.clone(),
+                bind_ip: self.bind_ip.clone(),
+                path: self.dir.to_string_lossy().to_string(),
+                mounts: self.mounts.clone(),
+                symlinks: self.symlinks,
+                access: self.access.clone(),
+            };
+
+            let started = async {
+                let server = Server::new(self.registry.create(&cmd)?, handle.stats.clone());
+                server.start()?;
+                match server.wait_change_from(ServerStatus::Idle).await {
+                    ServerStatus::Listening(_) => Ok(server),
+                    ServerStatus::Failed(e) => Err(QuickServeError::server_lifecycle(e)),
+                    status => Err(QuickServeError::server_lifecycle(format!("Unexpected state {:?}", status))),
+                }
+            };
+
+            match started.await {
+                Ok(server) => handle.servers.push(server),
+                Err(e) => {
+                    error!("Failed to spawn {} server: {}", protocol.to_string(), e);
+                    handle.shutdown().await;
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(handle)
+    }
+}
+
+/// Controls the servers spawned by [`QuickServeBuilder::spawn`]
+///
+/// Dropping the handle stops the servers without waiting for them.
+pub struct QuickServeHandle {
+    servers: Vec<Server>,
+    stats: Stats,
+}
+
+impl QuickServeHandle {
+    /// Returns the (first) address a protocol's server is listening on
+    pub fn bound_addr(&self, protocol: &Protocol) -> Option<SocketAddr> {
+        self.bound_addrs(protocol).first().copied()
+    }
+
+    /// Returns all addresses a protocol's server is listening on
+    pub fn bound_addrs(&self, protocol: &Protocol) -> Vec<SocketAddr> {
+        self.servers.iter()
+            .find(|s| s.protocol == *protocol)
+            .map(|s| s.bound_addrs())
+            .unwrap_or_default()
+    }
+
+    /// Returns the statistics collected so far, per protocol
+    pub fn stats(&self) -> HashMap<Protocol, ProtocolStats> {
+        self.stats.snapshot()
+    }
+
+    /// Returns the transfers going on, oldest first
+    pub fn active_transfers(&self) -> Vec<Transfer> {
+        self.stats.active()
+    }
+
+    /// Returns the last finished transfers, oldest first
+    pub fn finished_transfers(&self) -> Vec<Transfer> {
+        self.stats.finished()
+    }
+
+    /// Stops all servers and waits for them to exit
+    pub async fn shutdown(self) {
+        for server in &self.servers {
+            let status = server.status();
+            if !matches!(status, ServerStatus::Listening(_)) {
+                continue;
+            }
+            server.stop();
+            server.finished().await;
+        }
+    }
+}
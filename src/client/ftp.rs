@@ -0,0 +1,209 @@
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+
+use crate::{QuickServeError, QuickServeResult};
+
+/// A reply of the server: its code and its text, lines joined
+type Reply = (u16, String);
+
+/// The control connection to a server
+struct Control {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    peer: SocketAddr,
+    /// The last command sent, the reply to a transfer coming after its own
+    last: String,
+}
+
+/// Reads a reply, following the lines of a multi-line one (`123-...` up to `123 ...`)
+async fn read_reply<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> QuickServeResult<Reply> {
+    let mut lines = Vec::new();
+    let mut code = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(QuickServeError::Network("Connection closed by the server".into()));
+        }
+        let line = line.trim_end().to_string();
+        let parsed = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+        let separator = line.as_bytes().get(3).copied();
+        match (code, parsed) {
+            (None, Some(c)) => {
+                code = Some(c);
+                lines.push(line[3..].trim_start_matches(['-', ' ']).to_string());
+                if separator != Some(b'-') {
+                    return Ok((c, lines.join("\n")));
+                }
+            }
+            (None, None) => return Err(QuickServeError::Network(format!("Invalid FTP reply: {}", line))),
+            (Some(c), Some(p)) if p == c && separator != Some(b'-') => {
+                lines.push(line[3..].trim_start().to_string());
+                return Ok((c, lines.join("\n")));
+            }
+            (Some(_), _) => lines.push(line),
+        }
+    }
+}
+
+/// The port of a `229 Entering Extended Passive Mode (|||6446|)` reply
+fn epsv_port(text: &str) -> Option<u16> {
+    let start = text.find("|||")? + 3;
+    let end = start + text[start..].find('|')?;
+    text[start..end].parse().ok()
+}
+
+/// The port of a `227 Entering Passive Mode (127,0,0,1,25,46)` reply
+fn pasv_port(text: &str) -> Option<u16> {
+    let start = text.find('(')? + 1;
+    let end = start + text[start..].find(')')?;
+    let numbers: Vec<u16> = text[start..end].split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+impl Control {
+    async fn connect(server: SocketAddr, login: Option<(&str, &str)>) -> QuickServeResult<Self> {
+        let stream = TcpStream::connect(server).await
+            .map_err(|e| QuickServeError::Network(format!("Failed to connect to {}: {}", server, e)))?;
+        let (reader, writer) = stream.into_split();
+        let mut control = Control { reader: BufReader::new(reader), writer, peer: server, last: String::new() };
+        control.expect(None, &[220]).await?;
+
+        let (user, password) = login.unwrap_or(("anonymous", "anonymous@"));
+        let (code, _) = control.expect(Some(&format!("USER {}", user)), &[230, 331]).await?;
+        if code == 331 {
+            control.expect(Some(&format!("PASS {}", password)), &[230, 202]).await?;
+        }
+        control.expect(Some("TYPE I"), &[200]).await?;
+        Ok(control)
+    }
+
+    /// Sends a command, if any, and reads the reply
+    async fn command(&mut self, command: Option<&str>) -> QuickServeResult<Reply> {
+        if let Some(command) = command {
+            self.writer.write_all(format!("{}\r\n", command).as_bytes()).await?;
+            // Not to show passwords
+            self.last = if command.starts_with("PASS ") { "PASS".into() } else { command.into() };
+        }
+        read_reply(&mut self.reader).await
+    }
+
+    /// Like `command`, failing unless the reply has one of the codes expected
+    async fn expect(&mut self, command: Option<&str>, codes: &[u16]) -> QuickServeResult<Reply> {
+        let (code, text) = self.command(command).await?;
+        if !codes.contains(&code) {
+            let command = if self.last.is_empty() { "connecting" } else { &self.last };
+            return Err(QuickServeError::Network(format!("{} replied {} to {}: {}", self.peer, code, command, text)));
+        }
+        Ok((code, text))
+    }
+
+    /// Opens a data connection in passive mode, trying EPSV first
+    ///
+    /// The address given in a PASV reply is ignored for the server's, as it may be
+    /// a private one behind NAT.
+    async fn passive(&mut self) -> QuickServeResult<TcpStream> {
+        let port = match self.command(Some("EPSV")).await? {
+            (229, text) => epsv_port(&text),
+            _ => pasv_port(&self.expect(Some("PASV"), &[227]).await?.1),
+        };
+        let port = port.ok_or_else(|| QuickServeError::Network(format!("{} replied an invalid passive port", self.peer)))?;
+        TcpStream::connect((self.peer.ip(), port)).await
+            .map_err(|e| QuickServeError::Network(format!("Failed to open a data connection to {}: {}", self.peer, e)))
+    }
+
+    /// Sends a transfer command, returning the data connection it goes over
+    async fn transfer(&mut self, command: &str) -> QuickServeResult<TcpStream> {
+        let data = self.passive().await?;
+        self.expect(Some(command), &[125, 150]).await?;
+        Ok(data)
+    }
+
+    /// Waits for the server to confirm a transfer, then logs out
+    async fn done(&mut self) -> QuickServeResult<()> {
+        self.expect(None, &[226, 250]).await?;
+        let _ = self.command(Some("QUIT")).await;
+        Ok(())
+    }
+}
+
+/// Lists a directory, the root if none is given
+///
+/// # Returns
+/// The listing, as the server formats it
+pub async fn list(server: SocketAddr, login: Option<(&str, &str)>, path: Option<&str>) -> QuickServeResult<String> {
+    let mut control = Control::connect(server, login).await?;
+    let command = path.map(|path| format!("LIST {}", path)).unwrap_or_else(|| "LIST".into());
+    let mut data = control.transfer(&command).await?;
+    let mut listing = String::new();
+    data.read_to_string(&mut listing).await?;
+    control.done().await?;
+    Ok(listing)
+}
+
+/// Downloads a file
+///
+/// # Returns
+/// The number of bytes downloaded
+pub async fn get<W>(server: SocketAddr, login: Option<(&str, &str)>, file: &str, out: &mut W) -> QuickServeResult<u64>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut control = Control::connect(server, login).await?;
+    let mut data = control.transfer(&format!("RETR {}", file)).await?;
+    let bytes = tokio::io::copy(&mut data, out).await?;
+    control.done().await?;
+    Ok(bytes)
+}
+
+/// Uploads a file
+///
+/// # Returns
+/// The number of bytes uploaded
+pub async fn put<R>(server: SocketAddr, login: Option<(&str, &str)>, file: &str, input: &mut R) -> QuickServeResult<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut control = Control::connect(server, login).await?;
+    let mut data = control.transfer(&format!("STOR {}", file)).await?;
+    let bytes = tokio::io::copy(input, &mut data).await?;
+    data.shutdown().await?;
+    drop(data);
+    control.done().await?;
+    Ok(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_reply() {
+        let mut input: &[u8] = b"220 Service ready\r\n";
+        assert_eq!(read_reply(&mut input).await.unwrap(), (220, "Service ready".into()));
+
+        let mut input: &[u8] = b"211-Features:\r\n EPSV\r\n211 End\r\n200 Next\r\n";
+        assert_eq!(read_reply(&mut input).await.unwrap(), (211, "Features:\n EPSV\nEnd".into()));
+        assert_eq!(read_reply(&mut input).await.unwrap(), (200, "Next".into()));
+
+        let mut input: &[u8] = b"Hello\r\n";
+        assert!(read_reply(&mut input).await.is_err());
+        let mut input: &[u8] = b"";
+        assert!(read_reply(&mut input).await.is_err());
+    }
+
+    #[test]
+    fn test_passive_ports() {
+        assert_eq!(epsv_port("Entering Extended Passive Mode (|||6446|)"), Some(6446));
+        assert_eq!(epsv_port("Entering Extended Passive Mode"), None);
+        assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,25,46)"), Some(25 * 256 + 46));
+        assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,25)"), None);
+        assert_eq!(pasv_port("Entering Passive Mode (127,0,0,1,256,1)"), None);
+    }
+}
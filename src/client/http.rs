@@ -0,0 +1,96 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::client::conn::http1;
+use hyper::header::{AUTHORIZATION, HOST};
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::client::network;
+use crate::{QuickServeError, QuickServeResult};
+
+/// Sends a request over a connection of its own
+pub(crate) async fn send<S>(stream: S, req: Request<Full<Bytes>>) -> QuickServeResult<Response<Incoming>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = http1::handshake(TokioIo::new(stream)).await.map_err(network)?;
+    tokio::spawn(connection);
+    sender.send_request(req).await.map_err(network)
+}
+
+/// Splits an `http://` URL into its authority and its path
+fn split_url(url: &str) -> QuickServeResult<(&str, &str)> {
+    let rest = url.strip_prefix("http://").unwrap_or(url);
+    if rest.contains("://") {
+        return Err(QuickServeError::validation(format!("Unsupported URL: {} (only http:// is)", url)));
+    }
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(QuickServeError::validation(format!("Invalid URL: {}", url)));
+    }
+    Ok((authority, path))
+}
+
+/// The path of an `http://` URL, `/` if it has none
+pub fn url_path(url: &str) -> QuickServeResult<&str> {
+    split_url(url).map(|(_, path)| path)
+}
+
+/// Downloads a file, authenticating with basic authentication if given a user
+///
+/// # Returns
+/// The number of bytes downloaded
+pub async fn get<W>(url: &str, user: Option<(&str, &str)>, out: &mut W) -> QuickServeResult<u64>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let (authority, path) = split_url(url)?;
+    let server = super::resolve(authority, 80).await?;
+
+    let mut builder = Request::get(path).header(HOST, authority);
+    if let Some((user, password)) = user {
+        builder = builder.header(AUTHORIZATION, format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password))));
+    }
+    let req = builder.body(Full::new(Bytes::new()))
+        .map_err(|e| QuickServeError::validation(e.to_string()))?;
+
+    let stream = TcpStream::connect(server).await
+        .map_err(|e| QuickServeError::Network(format!("Failed to connect to {}: {}", server, e)))?;
+    let response = send(stream, req).await?;
+    if !response.status().is_success() {
+        return Err(QuickServeError::Network(format!("{} replied {}", url, response.status())));
+    }
+
+    let mut body = response.into_body();
+    let mut bytes = 0u64;
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame.map_err(network)?.into_data() {
+            out.write_all(&data).await?;
+            bytes += data.len() as u64;
+        }
+    }
+    Ok(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(split_url("http://127.0.0.1:8080/fw/a.bin").unwrap(), ("127.0.0.1:8080", "/fw/a.bin"));
+        assert_eq!(split_url("lab:8080").unwrap(), ("lab:8080", "/"));
+        assert_eq!(url_path("http://lab/a.bin?x=1").unwrap(), "/a.bin?x=1");
+        assert!(split_url("https://lab/a.bin").is_err());
+        assert!(split_url("http:///a.bin").is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Clients for the protocols served, to check a server from the command line
+//! (`quick-serve client ...`) without needing `tftp`, `curl` or `wget` around.
+
+pub mod ftp;
+pub mod http;
+pub mod tftp;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::lookup_host;
+
+use crate::{format_bytes, ClientCommand, FtpCommand, HttpCommand, QuickServeError, QuickServeResult, TftpCommand};
+
+pub(crate) fn network(e: impl fmt::Display) -> QuickServeError {
+    QuickServeError::Network(e.to_string())
+}
+
+/// Resolves `HOST:PORT`, or `HOST` alone on the protocol's default port
+///
+/// IPv6 addresses are written in brackets, e.g. `[::1]:69`.
+pub async fn resolve(server: &str, default_port: u16) -> QuickServeResult<std::net::SocketAddr> {
+    let has_port = server.rsplit_once(':').is_some_and(|(_, port)| !port.ends_with(']'));
+    let server = if has_port { server.to_string() } else { format!("{}:{}", server, default_port) };
+    let mut addrs = lookup_host(&server).await
+        .map_err(|e| QuickServeError::Network(format!("Failed to resolve {}: {}", server, e)))?;
+    addrs.next().ok_or_else(|| QuickServeError::Network(format!("Failed to resolve {}", server)))
+}
+
+/// Where a download is written: stdout, or a file put in place only once complete,
+/// so that a failed download neither leaves a partial file nor clobbers an existing one
+struct Output {
+    /// The file and the partial one written meanwhile, unless writing to stdout
+    file: Option<(PathBuf, PathBuf)>,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+}
+
+impl Output {
+    async fn create(path: &str) -> QuickServeResult<Self> {
+        if path == "-" {
+            return Ok(Output { file: None, writer: Box::new(tokio::io::stdout()) });
+        }
+        let path = PathBuf::from(path);
+        let mut part = path.clone().into_os_string();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let writer = tokio::fs::File::create(&part).await?;
+        Ok(Output { file: Some((path, part)), writer: Box::new(writer) })
+    }
+
+    async fn finish(mut self, result: QuickServeResult<u64>) -> QuickServeResult<u64> {
+        let result = match result {
+            Ok(bytes) => self.writer.flush().await.map(|_| bytes).map_err(QuickServeError::from),
+            Err(e) => Err(e),
+        };
+        drop(self.writer);
+        if let Some((path, part)) = self.file {
+            match &result {
+                Ok(_) => tokio::fs::rename(&part, &path).await?,
+                Err(_) => { let _ = tokio::fs::remove_file(&part).await; }
+            }
+        }
+        result
+    }
+}
+
+/// The output given, or the name of the file downloaded, in the current directory
+fn output_name(remote: &str, output: Option<&str>) -> QuickServeResult<String> {
+    if let Some(output) = output {
+        return Ok(output.to_string());
+    }
+    match remote.rsplit('/').next() {
+        Some(name) if !name.is_empty() && name != ".." => Ok(name.to_string()),
+        _ => Err(QuickServeError::validation(format!("No file name in {:?}, give where to write it", remote))),
+    }
+}
+
+/// Splits `--user=USER[:PASSWORD]`
+fn credentials(user: &str) -> (&str, &str) {
+    user.split_once(':').unwrap_or((user, ""))
+}
+
+async fn client(command: &ClientCommand) -> QuickServeResult<()> {
+    let started = Instant::now();
+    let (name, bytes) = match command {
+        ClientCommand::Tftp(TftpCommand::Get { server, file, output }) => {
+            let addr = resolve(server, 69).await?;
+            let mut out = Output::create(&output_name(file, output.as_deref())?).await?;
+            let result = tftp::get(addr, file, &mut out.writer).await;
+            (file, out.finish(result).await?)
+        }
+        ClientCommand::Ftp(FtpCommand::Ls { server, path, user }) => {
+            let addr = resolve(server, 21).await?;
+            let listing = ftp::list(addr, user.as_deref().map(credentials), path.as_deref()).await?;
+            print!("{}", listing);
+            return Ok(());
+        }
+        ClientCommand::Ftp(FtpCommand::Get { server, file, output, user }) => {
+            let addr = resolve(server, 21).await?;
+            let mut out = Output::create(&output_name(file, output.as_deref())?).await?;
+            let result = ftp::get(addr, user.as_deref().map(credentials), file, &mut out.writer).await;
+            (file, out.finish(result).await?)
+        }
+        ClientCommand::Ftp(FtpCommand::Put { server, file, remote, user }) => {
+            let remote = match remote {
+                Some(remote) => remote.clone(),
+                None if file == "-" => return Err(QuickServeError::validation("Give where to write stdin on the server")),
+                None => output_name(file, None)?,
+            };
+            let mut input: Box<dyn AsyncRead + Unpin + Send> = match file.as_str() {
+                "-" => Box::new(tokio::io::stdin()),
+                _ => Box::new(tokio::fs::File::open(Path::new(file)).await?),
+            };
+            let addr = resolve(server, 21).await?;
+            let bytes = ftp::put(addr, user.as_deref().map(credentials), &remote, &mut input).await?;
+            eprintln!("Uploaded {} ({}) in {:.2?}", remote, format_bytes(bytes), started.elapsed());
+            return Ok(());
+        }
+        ClientCommand::Http(HttpCommand::Get { url, output, user }) => {
+            let path = http::url_path(url)?;
+            let name = path.split('?').next().unwrap_or_default();
+            let name = match name.rsplit('/').next() {
+                Some("") | None => "index.html",
+                Some(name) => name,
+            };
+            let mut out = Output::create(&output_name(name, output.as_deref())?).await?;
+            let result = http::get(url, user.as_deref().map(credentials), &mut out.writer).await;
+            (url, out.finish(result).await?)
+        }
+    };
+    eprintln!("Downloaded {} ({}) in {:.2?}", name, format_bytes(bytes), started.elapsed());
+    Ok(())
+}
+
+/// Runs a `client` command against a server
+///
+/// # Returns
+/// The exit status: 0 on success, 1 if the server could not be reached or refused the request
+pub async fn run_client(command: &ClientCommand) -> i32 {
+    match client(command).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_name() {
+        assert_eq!(output_name("fw/a.bin", None).unwrap(), "a.bin");
+        assert_eq!(output_name("fw/a.bin", Some("/tmp/b.bin")).unwrap(), "/tmp/b.bin");
+        assert!(output_name("", None).is_err());
+        assert!(output_name("fw/", None).is_err());
+        assert!(output_name("../..", None).is_err());
+        assert_eq!(credentials("user:pa:ss"), ("user", "pa:ss"));
+        assert_eq!(credentials("user"), ("user", ""));
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        assert_eq!(resolve("127.0.0.1:6969", 69).await.unwrap().to_string(), "127.0.0.1:6969");
+        assert_eq!(resolve("127.0.0.1", 69).await.unwrap().to_string(), "127.0.0.1:69");
+        assert_eq!(resolve("[::1]", 21).await.unwrap().to_string(), "[::1]:21");
+        assert_eq!(resolve("[::1]:2121", 21).await.unwrap().to_string(), "[::1]:2121");
+    }
+
+    #[tokio::test]
+    async fn test_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.bin");
+        std::fs::write(&path, b"old").unwrap();
+
+        // A failed download leaves the existing file alone
+        let mut out = Output::create(path.to_str().unwrap()).await.unwrap();
+        out.writer.write_all(b"partial").await.unwrap();
+        assert!(out.finish(Err(QuickServeError::Network("reset".into()))).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut out = Output::create(path.to_str().unwrap()).await.unwrap();
+        out.writer.write_all(b"new").await.unwrap();
+        assert_eq!(out.finish(Ok(3)).await.unwrap(), 3);
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,161 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use crate::{QuickServeError, QuickServeResult};
+
+const RRQ: u16 = 1;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+
+/// The block size of RFC 1350, no option being negotiated
+const BLOCK_SIZE: usize = 512;
+/// How long a packet is waited for before sending ours again
+const RETRY_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 5;
+
+/// A read request for a file, in octet mode
+fn read_request(file: &str) -> Vec<u8> {
+    let mut packet = RRQ.to_be_bytes().to_vec();
+    packet.extend_from_slice(file.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(b"octet");
+    packet.push(0);
+    packet
+}
+
+fn ack(block: u16) -> Vec<u8> {
+    [ACK.to_be_bytes(), block.to_be_bytes()].concat()
+}
+
+/// A packet received, as far as a client reading a file is concerned
+#[derive(Debug, PartialEq, Eq)]
+enum Packet<'a> {
+    Data { block: u16, data: &'a [u8] },
+    Error { code: u16, message: String },
+}
+
+fn parse(packet: &[u8]) -> QuickServeResult<Packet<'_>> {
+    if packet.len() < 4 {
+        return Err(QuickServeError::Network(format!("Truncated TFTP packet of {} bytes", packet.len())));
+    }
+    let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+    let value = u16::from_be_bytes([packet[2], packet[3]]);
+    match opcode {
+        DATA => Ok(Packet::Data { block: value, data: &packet[4..] }),
+        ERROR => {
+            let message = packet[4..].split(|&b| b == 0).next().unwrap_or_default();
+            Ok(Packet::Error { code: value, message: String::from_utf8_lossy(message).into_owned() })
+        }
+        _ => Err(QuickServeError::Network(format!("Unexpected TFTP packet with opcode {}", opcode))),
+    }
+}
+
+/// Downloads a file, acknowledging each block once written
+///
+/// # Returns
+/// The number of bytes downloaded
+pub async fn get<W>(server: SocketAddr, file: &str, out: &mut W) -> QuickServeResult<u64>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let local: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse()? } else { "[::]:0".parse()? };
+    let socket = UdpSocket::bind(local).await?;
+
+    // The server answers from a port of its own for the transfer (its TID)
+    let mut peer: Option<SocketAddr> = None;
+    let mut last = read_request(file);
+    socket.send_to(&last, server).await?;
+
+    let mut expected: u16 = 1;
+    let mut bytes = 0u64;
+    let mut retries = 0;
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let (len, from) = match timeout(RETRY_TIMEOUT, socket.recv_from(&mut buf)).await {
+            Ok(received) => received?,
+            Err(_) => {
+                retries += 1;
+                if retries > MAX_RETRIES {
+                    return Err(QuickServeError::Network(format!("Timed out waiting for {}", peer.unwrap_or(server))));
+                }
+                socket.send_to(&last, peer.unwrap_or(server)).await?;
+                continue;
+            }
+        };
+        if from.ip() != server.ip() || peer.is_some_and(|peer| peer != from) {
+            continue;
+        }
+
+        match parse(&buf[..len])? {
+            Packet::Error { code, message } => {
+                return Err(QuickServeError::Network(format!("{} replied error {}: {}", server, code, message)));
+            }
+            Packet::Data { block, data } if block == expected => {
+                peer = Some(from);
+                retries = 0;
+                out.write_all(data).await?;
+                bytes += data.len() as u64;
+                last = ack(block);
+                socket.send_to(&last, from).await?;
+                if data.len() < BLOCK_SIZE {
+                    return Ok(bytes);
+                }
+                expected = expected.wrapping_add(1);
+            }
+            // Our acknowledgement got lost, the server sent the block again
+            Packet::Data { block, .. } if block == expected.wrapping_sub(1) => {
+                socket.send_to(&ack(block), from).await?;
+            }
+            Packet::Data { .. } => {}
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(read_request("fw/a.bin"), b"\x00\x01fw/a.bin\x00octet\x00");
+        assert_eq!(ack(258), [0, 4, 1, 2]);
+
+        assert_eq!(parse(b"\x00\x03\x00\x02abc").unwrap(), Packet::Data { block: 2, data: b"abc" });
+        assert_eq!(parse(b"\x00\x05\x00\x01File not found\x00").unwrap(),
+            Packet::Error { code: 1, message: "File not found".into() });
+        assert!(parse(b"\x00\x03\x00").is_err());
+        assert!(parse(b"\x00\x04\x00\x01").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let data: Vec<u8> = (0..BLOCK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let sent = data.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let (len, client) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], read_request("a.bin"));
+
+            let transfer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            for (i, chunk) in sent.chunks(BLOCK_SIZE).enumerate() {
+                let block = (i + 1) as u16;
+                let packet = [&DATA.to_be_bytes()[..], &block.to_be_bytes(), chunk].concat();
+                transfer.send_to(&packet, client).await.unwrap();
+                let (len, _) = transfer.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..len], ack(block));
+            }
+        });
+
+        let mut out = Vec::new();
+        assert_eq!(get(addr, "a.bin", &mut out).await.unwrap(), data.len() as u64);
+        assert_eq!(out, data);
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use log::error;
+
+use crate::{Config, Direction, QuickServeError, QuickServeResult, Transfer};
+
+/// File the access log is written to, once opened
+static ACCESS_LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Opens the access log, appending to the file if it exists
+///
+/// The access log is kept apart from the debug log: one line per HTTP
+/// request, and one per FTP or TFTP transfer. Can only be opened once.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    ACCESS_LOG.set(Mutex::new(file))
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "Access log already opened"))
+}
+
+/// Opens the access log given in the configuration, if any
+///
+/// # Returns
+/// * `Err(QuickServeError)` - If the file cannot be opened
+pub fn start(config: &Config) -> QuickServeResult<()> {
+    let Some(path) = &config.access_log else { return Ok(()) };
+    open(Path::new(path))
+        .map_err(|e| QuickServeError::validation(format!("Failed to open access log {}: {}", path, e)))
+}
+
+/// Whether the access log is written to, i.e. whether entries are worth collecting
+pub fn is_enabled() -> bool {
+    ACCESS_LOG.get().is_some()
+}
+
+/// Appends a line to the access log, if opened
+pub fn write(line: &str) {
+    let Some(file) = ACCESS_LOG.get() else { return };
+    if let Ok(mut file) = file.lock() {
+        if let Err(e) = writeln!(file, "{}", line) {
+            error!("Failed to write to the access log: {}", e);
+        }
+    }
+}
+
+/// Quotes a field of a log line, escaping quotes and backslashes
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "\"-\"".to_string(),
+    }
+}
+
+/// An HTTP request, as written to the access log once its response is sent
+#[derive(Clone, Debug)]
+pub struct HttpAccess {
+    pub client: IpAddr,
+    /// User authenticated through basic authentication, if any
+    pub user: Option<String>,
+    pub method: String,
+    /// Path and query, as requested
+    pub target: String,
+    pub version: String,
+    pub status: u16,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    /// When the request was received
+    pub time: DateTime<Local>,
+}
+
+impl HttpAccess {
+    /// Formats the request in the Combined Log Format, followed by the time
+    /// taken to serve it, in microseconds (as Apache's `%D`)
+    ///
+    /// # Arguments
+    /// * `bytes` - Bytes of the body actually sent
+    /// * `duration` - Time from the request to the end of the response
+    pub fn line(&self, bytes: u64, duration: Duration) -> String {
+        let request = format!("{} {} {}", self.method, self.target, self.version);
+        format!("{} - {} [{}] {} {} {} {} {} {}",
+            self.client,
+            self.user.as_deref().unwrap_or("-"),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            quoted(Some(&request)),
+            self.status,
+            if bytes == 0 { "-".to_string() } else { bytes.to_string() },
+            quoted(self.referer.as_deref()),
+            quoted(self.user_agent.as_deref()),
+            duration.as_micros())
+    }
+}
+
+/// Formats a transfer as a wu-ftpd `xferlog` line
+///
+/// Whitespace in the file name is replaced with `_`, fields being separated
+/// with spaces. Transfers are always reported as binary.
+///
+/// # Arguments
+/// * `transfer` - The transfer, as it stood when it ended
+/// * `user` - The user logged in, anonymous access if none
+/// * `complete` - Whether the whole file went through
+/// * `time` - When the transfer ended
+pub fn xferlog_line(transfer: &Transfer, user: Option<&str>, complete: bool, time: DateTime<Local>) -> String {
+    let path = transfer.path.split_whitespace().collect::<Vec<_>>().join("_");
+    format!("{} {} {} {} {}{} b _ {} {} {} {} 0 * {}",
+        time.format("%a %b %e %H:%M:%S %Y"),
+        transfer.elapsed().as_secs(),
+        transfer.client.map_or("-".to_string(), |c| c.ip().to_string()),
+        transfer.bytes,
+        if path.starts_with('/') { "" } else { "/" },
+        path,
+        match transfer.direction {
+            Direction::Download => 'o',
+            Direction::Upload => 'i',
+        },
+        if user.is_some() { 'r' } else { 'a' },
+        user.unwrap_or("anonymous"),
+        transfer.protocol.to_string(),
+        if complete { 'c' } else { 'i' })
+}
+
+/// Appends a transfer to the access log, as an `xferlog` line
+pub fn xferlog(transfer: &Transfer, user: Option<&str>, complete: bool) {
+    if is_enabled() {
+        write(&xferlog_line(transfer, user, complete, Local::now()));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use chrono::TimeZone;
+    use crate::servers::Protocol;
+    use crate::TransferState;
+
+    fn time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 7, 9, 5, 1).unwrap()
+    }
+
+    #[test]
+    fn test_combined_log_format() {
+        let access = HttpAccess {
+            client: "10.0.0.2".parse().unwrap(),
+            user: None,
+            method: "GET".into(),
+            target: "/fw/a.bin?v=1".into(),
+            version: "HTTP/1.1".into(),
+            status: 200,
+            referer: None,
+            user_agent: Some("curl/8.5.0 \"test\"".into()),
+            time: time(),
+        };
+        let offset = time().format("%z").to_string();
+        assert_eq!(access.line(1024, Duration::from_millis(3)),
+            format!("10.0.0.2 - - [07/Mar/2026:09:05:01 {}] \"GET /fw/a.bin?v=1 HTTP/1.1\" 200 1024 \"-\" \"curl/8.5.0 \\\"test\\\"\" 3000", offset));
+
+        let denied = HttpAccess { user: Some("admin".into()), status: 401, ..access };
+        assert!(denied.line(0, Duration::ZERO).starts_with("10.0.0.2 - admin ["));
+        assert!(denied.line(0, Duration::ZERO).contains("\" 401 - \""));
+    }
+
+    #[test]
+    fn test_xferlog_line() {
+        let started = Instant::now();
+        let transfer = Transfer {
+            protocol: Protocol::Tftp,
+            client: Some("10.0.0.2:40000".parse().unwrap()),
+            path: "images/my file.bin".into(),
+            direction: Direction::Download,
+            size: Some(4096),
+            bytes: 4096,
+            started,
+            finished: Some(started + Duration::from_secs(2)),
+            state: TransferState::Completed,
+        };
+        assert_eq!(xferlog_line(&transfer, None, true, time()),
+            "Sat Mar  7 09:05:01 2026 2 10.0.0.2 4096 /images/my_file.bin b _ o a anonymous tftp 0 * c");
+
+        let upload = Transfer { protocol: Protocol::Ftp, client: None, direction: Direction::Upload, ..transfer };
+        assert_eq!(xferlog_line(&upload, Some("admin"), false, time()),
+            "Sat Mar  7 09:05:01 2026 2 - 4096 /images/my_file.bin b _ i r admin ftp 0 * i");
+    }
+}
@@ -0,0 +1,814 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Either, Full, Limited};
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::broadcast::Sender;
+use tokio::time::Interval;
+
+use crate::servers::{Mount, Protocol, ServerStatus, PROTOCOL_LIST};
+use crate::utils::access::AccessRules;
+use crate::utils::logger::LogEntry;
+use crate::utils::validation::SymlinkPolicy;
+use crate::utils::{net, validation};
+use crate::{format_mac, same_secret, CommandMsg, Config, Direction, QuickServeError, QuickServeResult, ServerAction, Stats, Transfer, TransferState};
+
+/// The page of the web interface, talking to the API below
+const INDEX_HTML: &str = include_str!("admin.html");
+
+/// Largest request body accepted, commands being small JSON objects
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Log entries sent at once when the log stream is opened
+const LOG_BACKLOG: usize = 1000;
+
+/// How often the log stream checks for new entries
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Ticks without any entry after which a comment keeps the log stream alive
+const LOG_KEEPALIVE_TICKS: u32 = 60;
+
+/// Body of the responses: either a whole document, or the log stream
+type AdminBody = Either<Full<Bytes>, LogEvents>;
+
+/// Settings the servers get started with from the web interface
+///
+/// As in the GUI, empty per protocol bind IPs and directories fall back
+/// to the global ones.
+struct Settings {
+    bind_ip: String,
+    path: String,
+    protocols: Vec<CommandMsg>,
+}
+
+impl Settings {
+    fn from_config(config: &Config) -> Self {
+        let protocols = PROTOCOL_LIST.iter().map(|protocol| {
+            let mut msg = CommandMsg::new(protocol);
+            if let Some((enabled, port)) = config.server(protocol) {
+                msg.action = if enabled { ServerAction::Start } else { ServerAction::Stop };
+                msg.port = port;
+            }
+            let (bind_ip, path) = config.overrides(protocol);
+            msg.bind_ip = bind_ip.cloned().unwrap_or_default();
+            msg.path = path.cloned().unwrap_or_default();
+            msg.mounts = config.mounts.clone();
+            msg.symlinks = config.symlinks;
+            msg.access = config.access.clone();
+            msg
+        }).collect();
+
+        Settings { bind_ip: config.bind_ip.clone(), path: config.serve_dir.clone(), protocols }
+    }
+
+    /// The command for a protocol's server, the global bind IP and directory
+    /// standing in for those it does not have of its own
+    fn command(&self, protocol: &Protocol, action: ServerAction) -> CommandMsg {
+        let mut cmd = self.protocols.iter().find(|msg| &msg.protocol == protocol).cloned()
+            .unwrap_or_else(|| CommandMsg::new(protocol));
+        cmd.action = action;
+        if cmd.bind_ip.is_empty() {
+            cmd.bind_ip = self.bind_ip.clone();
+        }
+        if cmd.path.is_empty() {
+            cmd.path = self.path.clone();
+        }
+        cmd
+    }
+}
+
+/// State shared by the connections to the web interface
+struct Admin {
+    token: String,
+    sender: Sender<CommandMsg>,
+    stats: Stats,
+    logs: Arc<Mutex<Vec<LogEntry>>>,
+    settings: Mutex<Settings>,
+}
+
+/// Change requested to a protocol's server. Missing fields are left as they are
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerRequest {
+    start: Option<bool>,
+    port: Option<u16>,
+    /// Empty for the global one
+    bind_ip: Option<String>,
+    /// Empty for the global one
+    path: Option<String>,
+    mounts: Option<Vec<Mount>>,
+    symlinks: Option<SymlinkPolicy>,
+    access: Option<AccessRules>,
+}
+
+/// Change requested to the global settings. Missing fields are left as they are
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsRequest {
+    bind_ip: Option<String>,
+    path: Option<String>,
+}
+
+/// Generates a token hard to guess, out of 128 bits from the OS random number generator
+fn generate_token() -> QuickServeResult<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| QuickServeError::validation(format!("Failed to generate the admin token: {}", e)))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Checks that a token can be passed as is in a URL
+fn validate_token(token: &str) -> QuickServeResult<()> {
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c)) {
+        return Err(QuickServeError::validation("Admin token must be made of letters, digits and -._~"));
+    }
+    Ok(())
+}
+
+/// Checks the token of a request, given as a bearer token or in the `token` query
+/// parameter (which is all the browser's `EventSource` allows)
+fn is_authorized<B>(req: &Request<B>, token: &str) -> bool {
+    let bearer = req.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = req.uri().query().into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|param| param.strip_prefix("token="));
+
+    bearer.or(query).is_some_and(|given| same_secret(given.trim().as_bytes(), token.as_bytes()))
+}
+
+fn status_name(status: &ServerStatus) -> &'static str {
+    match status {
+        ServerStatus::Idle => "idle",
+        ServerStatus::Listening(_) => "listening",
+        ServerStatus::Stopped => "stopped",
+        ServerStatus::Failed(_) => "failed",
+    }
+}
+
+fn transfer(t: &Transfer) -> Value {
+    json!({
+        "protocol": t.protocol.to_string(),
+        "client": t.client.map(|_| t.client_label()),
+        "path": t.path,
+        "direction": match t.direction {
+            Direction::Download => "download",
+            Direction::Upload => "upload",
+        },
+        "size": t.size,
+        "bytes": t.bytes,
+        "rate": t.rate() as u64,
+        "progress": t.progress(),
+    })
+}
+
+fn log_entry(entry: &LogEntry) -> Value {
+    json!({
+        "id": entry.id,
+        "level": entry.level.as_str(),
+        "protocol": entry.protocol.as_ref().map(|p| p.to_string()),
+        "message": entry.message,
+    })
+}
+
+impl Admin {
+    /// Returns the settings, the state of each server, the transfers going on and the DHCP leases
+    fn state(&self) -> Value {
+        let counters = self.stats.snapshot();
+        let settings = self.settings.lock().unwrap();
+        let now = Instant::now();
+
+        let servers: Vec<Value> = settings.protocols.iter().map(|msg| {
+            let status = self.stats.status(&msg.protocol);
+            let counters = counters.get(&msg.protocol).cloned().unwrap_or_default();
+            json!({
+                "protocol": msg.protocol.to_string(),
+                "port": msg.port,
+                "bind_ip": msg.bind_ip,
+                "path": msg.path,
+                "status": status_name(&status),
+                "addresses": match &status {
+                    ServerStatus::Listening(addrs) => addrs.iter().map(|a| a.to_string()).collect(),
+                    _ => Vec::new(),
+                },
+                "error": match &status {
+                    ServerStatus::Failed(e) => Some(e.clone()),
+                    _ => None,
+                },
+                "requests": counters.requests,
+                "errors": counters.errors,
+                "downloads": counters.transfers,
+                "bytes_sent": counters.bytes_sent,
+                "bytes_received": counters.bytes_received,
+            })
+        }).collect();
+
+        let transfers: Vec<Value> = self.stats.active().iter().map(transfer).collect();
+
+        let leases: Vec<Value> = self.stats.dhcp_leases().iter().map(|lease| json!({
+            "ip": lease.ip.to_string(),
+            "mac": format_mac(&lease.mac),
+            "expires_in": lease.expiry.saturating_duration_since(now).as_secs(),
+        })).collect();
+
+        json!({
+            "bind_ip": settings.bind_ip,
+            "path": settings.path,
+            "servers": servers,
+            "transfers": transfers,
+            "dhcp_pool": self.stats.dhcp_pool().map(|pool| json!({ "leased": pool.leased, "size": pool.size })),
+            "dhcp_leases": leases,
+        })
+    }
+
+    /// Returns the totals per protocol and client, the last finished transfers,
+    /// and the summary printed on exit
+    fn stats(&self) -> Value {
+        let protocols: serde_json::Map<String, Value> = self.stats.snapshot().into_iter().map(|(protocol, s)| {
+            (protocol.to_string().into(), json!({
+                "requests": s.requests,
+                "errors": s.errors,
+                "downloads": s.transfers,
+                "bytes_sent": s.bytes_sent,
+                "bytes_received": s.bytes_received,
+            }))
+        }).collect();
+        let clients: serde_json::Map<String, Value> = self.stats.clients().into_iter().map(|(ip, c)| {
+            (ip.to_string(), json!({
+                "completed": c.completed,
+                "failed": c.failed,
+                "bytes_sent": c.bytes_sent,
+                "bytes_received": c.bytes_received,
+            }))
+        }).collect();
+        let finished: Vec<Value> = self.stats.finished().iter().map(|t| {
+            let mut value = transfer(t);
+            value["completed"] = Value::Bool(t.state == TransferState::Completed);
+            value
+        }).collect();
+
+        json!({
+            "protocols": protocols,
+            "clients": clients,
+            "finished": finished,
+            "summary": self.stats.summary(),
+        })
+    }
+
+    /// Applies a change to a protocol's settings, starting or stopping its server if requested
+    ///
+    /// A server already running is restarted when started again, so as to
+    /// listen with the new settings. Otherwise it serves the new directory,
+    /// mounts and rules right away, as on a configuration reload.
+    fn update_server(&self, protocol: &Protocol, req: ServerRequest) -> QuickServeResult<()> {
+        let mut settings = self.settings.lock().unwrap();
+        let msg = settings.protocols.iter_mut()
+            .find(|msg| &msg.protocol == protocol)
+            .ok_or_else(|| QuickServeError::validation(format!("Unknown protocol: {}", protocol.to_string())))?;
+
+        if let Some(port) = req.port {
+            msg.port = port;
+        }
+        if let Some(ip) = req.bind_ip {
+            msg.bind_ip = ip.trim().to_string();
+        }
+        if let Some(dir) = req.path {
+            msg.path = dir.trim().to_string();
+        }
+        if let Some(mounts) = req.mounts {
+            msg.mounts = mounts;
+        }
+        if let Some(symlinks) = req.symlinks {
+            msg.symlinks = symlinks;
+        }
+        if let Some(access) = req.access {
+            msg.access = access;
+        }
+        let running = matches!(self.stats.status(protocol), ServerStatus::Listening(_));
+        let Some(start) = req.start else {
+            if running {
+                self.send(settings.command(protocol, ServerAction::Reconfigure))?;
+            }
+            return Ok(());
+        };
+        let action = if start { ServerAction::Start } else { ServerAction::Stop };
+        msg.action = action;
+        let mut cmd = settings.command(protocol, action);
+        if start {
+            validation::parse_bind_addresses(&cmd.bind_ip, cmd.port)?;
+        }
+
+        // Restarted with the new settings if already running
+        if start && running {
+            cmd.action = ServerAction::Restart;
+        }
+        self.send(cmd)?;
+        info!(protocol = protocol.to_string(), event = if start { "start" } else { "stop" };
+            "{} server {} from the web interface", protocol.to_string().to_uppercase(), if start { "started" } else { "stopped" });
+        Ok(())
+    }
+
+    /// Changes the global bind IP and directory, used by the servers started
+    /// next, the running servers relying on them serving the new directory
+    /// right away
+    fn update_settings(&self, req: SettingsRequest) -> QuickServeResult<()> {
+        let mut settings = self.settings.lock().unwrap();
+        if let Some(ip) = req.bind_ip {
+            settings.bind_ip = ip.trim().to_string();
+        }
+        if let Some(dir) = req.path {
+            settings.path = dir.trim().to_string();
+        }
+
+        let protocols: Vec<Protocol> = settings.protocols.iter()
+            .filter(|msg| msg.bind_ip.is_empty() || msg.path.is_empty())
+            .map(|msg| msg.protocol.clone())
+            .collect();
+        for protocol in protocols {
+            if matches!(self.stats.status(&protocol), ServerStatus::Listening(_)) {
+                self.send(settings.command(&protocol, ServerAction::Reconfigure))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, cmd: CommandMsg) -> QuickServeResult<()> {
+        let protocol = cmd.protocol.to_string().to_uppercase();
+        self.sender.send(cmd)
+            .map(|_| ())
+            .map_err(|e| QuickServeError::server_lifecycle(format!("Failed to send {} command: {}", protocol, e)))
+    }
+
+    /// Opens the log stream, starting after the entry the client last got, if any
+    ///
+    /// Unless following, the stream ends once the entries kept so far are sent.
+    fn log_events(&self, last_id: Option<u64>, follow: bool) -> LogEvents {
+        let mut interval = tokio::time::interval(LOG_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let last_id = last_id.unwrap_or_else(|| {
+            // Only the most recent entries at first
+            let logs = self.logs.lock().unwrap();
+            logs.len().checked_sub(LOG_BACKLOG + 1).map_or(0, |i| logs[i].id)
+        });
+        LogEvents { logs: self.logs.clone(), last_id, interval, idle: 0, follow, done: false }
+    }
+}
+
+/// Server-sent events, one per log entry, for as long as the client listens
+struct LogEvents {
+    logs: Arc<Mutex<Vec<LogEntry>>>,
+    last_id: u64,
+    interval: Interval,
+    /// Ticks since anything was sent
+    idle: u32,
+    /// Whether new entries are waited for, once the ones kept so far are sent
+    follow: bool,
+    done: bool,
+}
+
+impl Body for LogEvents {
+    type Data = Bytes;
+    type Error = std::convert::Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if this.interval.poll_tick(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            let mut events = String::new();
+            if let Ok(logs) = this.logs.lock() {
+                let last_id = this.last_id;
+                for entry in logs.iter().filter(|e| e.id > last_id) {
+                    events.push_str(&format!("id: {}\ndata: {}\n\n", entry.id, log_entry(entry)));
+                    this.last_id = entry.id;
+                }
+            }
+
+            if !this.follow {
+                this.done = true;
+                if events.is_empty() {
+                    continue;
+                }
+                return Poll::Ready(Some(Ok(Frame::data(Bytes::from(events)))));
+            }
+
+            this.idle += 1;
+            if events.is_empty() && this.idle >= LOG_KEEPALIVE_TICKS {
+                events.push_str(": keepalive\n\n");
+            }
+            if !events.is_empty() {
+                this.idle = 0;
+                return Poll::Ready(Some(Ok(Frame::data(Bytes::from(events)))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+fn document(status: StatusCode, content_type: &str, body: impl Into<Bytes>) -> Response<AdminBody> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .header(CACHE_CONTROL, "no-store")
+        .body(Either::Left(Full::new(body.into())))
+        .unwrap()
+}
+
+fn json_response(status: StatusCode, value: Value) -> Response<AdminBody> {
+    document(status, "application/json", value.to_string())
+}
+
+fn error_response(status: StatusCode, message: impl ToString) -> Response<AdminBody> {
+    json_response(status, json!({ "error": message.to_string() }))
+}
+
+/// Reads the JSON body of a request
+async fn read_json<T: for<'de> Deserialize<'de>>(req: Request<Incoming>) -> Result<T, Response<AdminBody>> {
+    let body = Limited::new(req.into_body(), MAX_BODY_SIZE).collect().await
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, format!("Failed to read request: {}", e)))?
+        .to_bytes();
+    serde_json::from_slice(&body)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))
+}
+
+/// Answers a request. Those through the control socket are trusted, without token
+async fn receive_request(req: Request<Incoming>, admin: Arc<Admin>, trusted: bool) -> Result<Response<AdminBody>, hyper::Error> {
+    let path = req.uri().path().to_string();
+
+    // The page itself holds nothing sensitive, the API calls it makes carry the token
+    if path == "/" || path == "/index.html" {
+        return Ok(document(StatusCode::OK, "text/html; charset=utf-8", INDEX_HTML));
+    }
+    if !trusted && !is_authorized(&req, &admin.token) {
+        warn!("Unauthorized request to the web interface for {}", path);
+        return Ok(error_response(StatusCode::UNAUTHORIZED, "Invalid or missing token"));
+    }
+
+    let response = match (req.method(), path.as_str()) {
+        (&Method::GET, "/api/state") => json_response(StatusCode::OK, admin.state()),
+        (&Method::GET, "/api/stats") => json_response(StatusCode::OK, admin.stats()),
+        (&Method::GET, "/api/logs") => {
+            let last_id = req.headers().get("last-event-id")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            let follow = !req.uri().query().is_some_and(|query| query.split('&').any(|param| param == "follow=false"));
+            Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .header(CACHE_CONTROL, "no-store")
+                .body(Either::Right(admin.log_events(last_id, follow)))
+                .unwrap()
+        }
+        (&Method::POST, "/api/settings") => match read_json(req).await {
+            Ok(settings) => match admin.update_settings(settings) {
+                Ok(()) => json_response(StatusCode::OK, admin.state()),
+                Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+            },
+            Err(response) => response,
+        },
+        (&Method::POST, p) if p.starts_with("/api/servers/") => {
+            let protocol = match p["/api/servers/".len()..].parse::<Protocol>() {
+                Ok(protocol) => protocol,
+                Err(e) => return Ok(error_response(StatusCode::NOT_FOUND, e)),
+            };
+            match read_json(req).await {
+                Ok(request) => match admin.update_server(&protocol, request) {
+                    Ok(()) => json_response(StatusCode::OK, admin.state()),
+                    Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+                },
+                Err(response) => response,
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+    Ok(response)
+}
+
+fn serve_connection<S>(stream: S, peer: String, admin: Arc<Admin>, trusted: bool)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(err) = http1::Builder::new()
+            .serve_connection(TokioIo::new(stream), service_fn(move |req| receive_request(req, admin.clone(), trusted)))
+            .await
+        {
+            debug!("Error serving the web interface to {}: {:?}", peer, err);
+        }
+    });
+}
+
+async fn accept_loop(listener: TcpListener, admin: Arc<Admin>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                debug!("New web interface connection from {}", addr);
+                serve_connection(stream, addr.to_string(), admin.clone(), false);
+            }
+            Err(e) => error!("Failed to accept web interface connection: {}", e),
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn accept_control_loop(listener: UnixListener, admin: Arc<Admin>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                debug!("New control socket connection");
+                serve_connection(stream, "the control socket".into(), admin.clone(), true);
+            }
+            Err(e) => error!("Failed to accept control socket connection: {}", e),
+        }
+    }
+}
+
+/// Binds the control socket, only reachable by the current user
+///
+/// The socket is bound in a directory only the current user may enter, then
+/// moved in place, for it never to be reachable by others. A socket left
+/// behind by an instance that did not exit cleanly is replaced, once proven
+/// dead by connections being refused. Anything else in the way is left alone.
+#[cfg(unix)]
+fn bind_control(path: &str) -> QuickServeResult<UnixListener> {
+    use std::io::ErrorKind;
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::path::Path;
+
+    let failed = |e: std::io::Error| QuickServeError::Network(format!("Failed to bind control socket {}: {}", path, e));
+    let stale = match std::fs::symlink_metadata(path) {
+        Ok(meta) if !meta.file_type().is_socket() => {
+            return Err(QuickServeError::Network(format!("Control socket {} exists and is not a socket", path)));
+        }
+        Ok(_) => match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => return Err(QuickServeError::Network(format!("Control socket {} is in use by another instance", path))),
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => true,
+            Err(e) => return Err(failed(e)),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => return Err(failed(e)),
+    };
+
+    let target = Path::new(path);
+    let parent = target.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = target.file_name().ok_or_else(|| failed(ErrorKind::InvalidInput.into()))?;
+    let private = parent.join(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private).map_err(failed)?;
+    let bound = private.join("sock");
+    let moved = UnixListener::bind(&bound)
+        .and_then(|listener| {
+            std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+            // Atomically over the dead socket, else only where nothing appeared meanwhile
+            if stale {
+                std::fs::rename(&bound, target)?;
+            } else {
+                std::fs::hard_link(&bound, target)?;
+            }
+            Ok(listener)
+        });
+    let _ = std::fs::remove_file(&bound);
+    let _ = std::fs::remove_dir(&private);
+    moved.map_err(failed)
+}
+
+/// Serves the web interface and the control socket, if enabled, for as long as the process runs
+///
+/// The web interface listens on the global bind addresses, on the `admin`
+/// port. Its API is protected by the `admin_token`, a random one being
+/// generated if none is configured, printed to stderr only, out of the logs
+/// any token holder can read. The same API is served
+/// on the `control` socket, where no token is needed, the socket being
+/// only reachable by the current user.
+///
+/// # Arguments
+/// * `config` - The settings, including those the servers are started with
+/// * `sender` - Where to send the commands starting and stopping the servers
+/// * `stats` - Statistics shared by all servers
+/// * `logs` - The logs kept in memory by the logger
+///
+/// # Returns
+/// * `Ok(Vec<SocketAddr>)` - The addresses listened on, none if disabled
+/// * `Err(QuickServeError)` - If the token is invalid or an address or the socket cannot be bound
+pub fn start_admin(config: &Config, sender: Sender<CommandMsg>, stats: &Stats, logs: Arc<Mutex<Vec<LogEntry>>>) -> QuickServeResult<Vec<SocketAddr>> {
+    if config.admin.is_none() && config.control.is_none() {
+        return Ok(Vec::new());
+    }
+    let token = match &config.admin_token {
+        Some(token) => {
+            validate_token(token)?;
+            token.clone()
+        }
+        None => generate_token()?,
+    };
+
+    // Bind all addresses first, so that none is served if any fails
+    let mut listeners = Vec::new();
+    if let Some(port) = config.admin {
+        for ip in validation::parse_bind_addresses(&config.bind_ip, port)? {
+            let addr = SocketAddr::new(ip, port);
+            let listener = net::bind_tcp(addr)
+                .and_then(TcpListener::from_std)
+                .map_err(|e| QuickServeError::Network(format!("Failed to bind web interface to {}: {}", addr, e)))?;
+            listeners.push(listener);
+        }
+    }
+    #[cfg(unix)]
+    let control = config.control.as_deref().map(bind_control).transpose()?;
+    #[cfg(not(unix))]
+    if config.control.is_some() {
+        return Err(QuickServeError::validation("The control socket is only supported on Unix"));
+    }
+
+    let admin = Arc::new(Admin {
+        token,
+        sender,
+        stats: stats.clone(),
+        logs,
+        settings: Mutex::new(Settings::from_config(config)),
+    });
+
+    let mut addrs = Vec::new();
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        info!("Serving the web interface on http://{}/", addr);
+        if config.admin_token.is_none() {
+            eprintln!("Web interface: http://{}/?token={}", addr, admin.token);
+        }
+        addrs.push(addr);
+        tokio::spawn(accept_loop(listener, admin.clone()));
+    }
+    #[cfg(unix)]
+    if let (Some(listener), Some(path)) = (control, &config.control) {
+        info!("Serving the control API on {}", path);
+        tokio::spawn(accept_control_loop(listener, admin));
+    }
+    Ok(addrs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultChannel;
+
+    fn admin(channel: &DefaultChannel<CommandMsg>) -> Admin {
+        let mut config = Config::default();
+        config.set_server(&Protocol::Tftp, false, 6970);
+        Admin {
+            token: "secret".into(),
+            sender: channel.sender.clone(),
+            stats: Stats::default(),
+            logs: Default::default(),
+            settings: Mutex::new(Settings::from_config(&config)),
+        }
+    }
+
+    #[test]
+    fn test_token() {
+        let request = |auth: Option<&str>, uri: &str| {
+            let mut builder = Request::builder().uri(uri);
+            if let Some(value) = auth {
+                builder = builder.header(AUTHORIZATION, value);
+            }
+            builder.body(()).unwrap()
+        };
+        assert!(is_authorized(&request(Some("Bearer secret"), "/api/state"), "secret"));
+        assert!(is_authorized(&request(None, "/api/logs?x=1&token=secret"), "secret"));
+        assert!(!is_authorized(&request(Some("Bearer secrets"), "/api/state"), "secret"));
+        assert!(!is_authorized(&request(None, "/api/state?token="), "secret"));
+        assert!(!is_authorized(&request(None, "/api/state"), "secret"));
+
+        assert_eq!(generate_token().unwrap().len(), 32);
+        assert_ne!(generate_token().unwrap(), generate_token().unwrap());
+        assert!(validate_token("a-b_c.d~1").is_ok());
+        assert!(validate_token("a b").is_err());
+        assert!(validate_token("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_server() {
+        let mut channel = DefaultChannel::default();
+        let admin = admin(&channel);
+
+        // Only stored while no server runs
+        admin.update_settings(SettingsRequest { path: Some("/srv/images".into()), ..Default::default() }).unwrap();
+        admin.update_server(&Protocol::Tftp, ServerRequest { port: Some(0), ..Default::default() }).unwrap();
+        assert!(channel.receiver.try_recv().is_err());
+        admin.update_server(&Protocol::Tftp, ServerRequest { start: Some(true), ..Default::default() }).unwrap();
+        let cmd = channel.receiver.recv().await.unwrap();
+        assert_eq!(cmd.action, ServerAction::Start);
+        assert_eq!(cmd.protocol, Protocol::Tftp);
+        assert_eq!(cmd.port, 0);
+        assert_eq!(cmd.path, "/srv/images", "falls back to the global directory");
+        assert_eq!(cmd.bind_ip, "127.0.0.1");
+
+        // Restarted with the new settings if already running
+        admin.stats.set_status(&Protocol::Tftp, ServerStatus::Listening(vec!["127.0.0.1:6970".parse().unwrap()]));
+        admin.update_server(&Protocol::Tftp, ServerRequest { start: Some(true), bind_ip: Some("0.0.0.0".into()), ..Default::default() }).unwrap();
+        let cmd = channel.receiver.recv().await.unwrap();
+        assert_eq!(cmd.action, ServerAction::Restart);
+        assert_eq!(cmd.bind_ip, "0.0.0.0");
+
+        let request: ServerRequest = serde_json::from_str(r#"{"start": true, "mounts": ["/fw=/opt/firmware"], "symlinks": "deny"}"#).unwrap();
+        admin.update_server(&Protocol::Tftp, request).unwrap();
+        let cmd = channel.receiver.recv().await.unwrap();
+        assert_eq!(cmd.action, ServerAction::Restart);
+        assert_eq!(cmd.mounts, vec!["/fw=/opt/firmware".parse().unwrap()]);
+        assert_eq!(cmd.symlinks, SymlinkPolicy::Deny);
+
+        // Applied to the running server right away otherwise
+        admin.update_server(&Protocol::Tftp, ServerRequest { path: Some("/srv/tftp".into()), ..Default::default() }).unwrap();
+        let cmd = channel.receiver.recv().await.unwrap();
+        assert_eq!((cmd.action, cmd.path.as_str()), (ServerAction::Reconfigure, "/srv/tftp"));
+        admin.update_settings(SettingsRequest { bind_ip: Some("0.0.0.0".into()), ..Default::default() }).unwrap();
+        assert!(channel.receiver.try_recv().is_err(), "the server has a bind IP and directory of its own");
+        admin.update_server(&Protocol::Tftp, ServerRequest { path: Some(String::new()), ..Default::default() }).unwrap();
+        assert_eq!(channel.receiver.recv().await.unwrap().path, "/srv/images");
+        admin.update_settings(SettingsRequest { path: Some("/srv/next".into()), ..Default::default() }).unwrap();
+        let cmd = channel.receiver.recv().await.unwrap();
+        assert_eq!((cmd.action, cmd.protocol, cmd.path.as_str()), (ServerAction::Reconfigure, Protocol::Tftp, "/srv/next"));
+        assert!(channel.receiver.try_recv().is_err(), "the other servers are not running");
+
+        admin.update_server(&Protocol::Tftp, ServerRequest { start: Some(false), ..Default::default() }).unwrap();
+        assert_eq!(channel.receiver.recv().await.unwrap().action, ServerAction::Stop);
+
+        assert!(admin.update_server(&Protocol::Tftp, ServerRequest { start: Some(true), bind_ip: Some("nope".into()), ..Default::default() }).is_err());
+
+        let state = admin.state();
+        let tftp = state["servers"].as_array().unwrap().iter().find(|s| s["protocol"] == "tftp").unwrap();
+        assert_eq!(tftp["bind_ip"], "nope", "settings are kept even if the server could not be started");
+        assert_eq!(tftp["status"], "listening");
+        assert_eq!(state["path"], "/srv/next");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_control() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ctl.sock");
+        let path_str = path.to_str().unwrap();
+
+        let listener = bind_control(path_str).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1, "nothing left behind");
+        assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+        assert!(bind_control(path_str).unwrap_err().to_string().contains("in use"));
+
+        // Left behind by an instance that did not exit cleanly
+        drop(listener);
+        assert!(bind_control(path_str).is_ok());
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"data").unwrap();
+        assert!(bind_control(file.to_str().unwrap()).unwrap_err().to_string().contains("not a socket"));
+        assert_eq!(std::fs::read(&file).unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    async fn test_log_events() {
+        let channel = DefaultChannel::default();
+        let admin = admin(&channel);
+        admin.logs.lock().unwrap().push(LogEntry { id: 1, level: log::Level::Info, protocol: Some(Protocol::Http), message: "GET /a.bin".into() });
+
+        // Unless following, the stream ends once the entries kept are sent
+        let mut events = admin.log_events(None, false);
+        let frame = events.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert!(String::from_utf8_lossy(&frame).starts_with("id: 1\ndata: {"));
+        assert!(events.frame().await.is_none());
+        assert!(admin.log_events(Some(1), false).frame().await.is_none());
+
+        let stats = admin.stats();
+        assert!(stats["summary"].as_array().unwrap().is_empty());
+        assert!(stats["finished"].as_array().unwrap().is_empty());
+    }
+}
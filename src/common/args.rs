@@ -1,11 +1,15 @@
 
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use clap::ArgAction;
+use globset::Glob;
+use std::time::Duration;
 
-use crate::Protocol;
+use crate::{Mount, Protocol};
+use crate::utils::logger::{LogFilter, LogFormat};
+use crate::utils::validation::{self, SymlinkPolicy};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about = "Quick-Serve", long_about = "Instant file serving made easy")]
 pub struct Cli {
 
@@ -18,20 +22,160 @@ pub struct Cli {
     )] pub headless: bool,
 
     #[arg(
-        help = "Bind IP",
+        help = "Full-screen terminal interface, e.g. over SSH",
+        long, required = false,
+        action = ArgAction::SetTrue,
+    )] pub tui: bool,
+
+    #[arg(
+        help = "Configuration file [default: <config dir>/quick-serve/quick-serve.toml]",
+        short, long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub config: Option<String>,
+
+    #[arg(
+        help = "Reload the configuration file whenever it changes, as on SIGHUP (headless)",
+        long, required = false,
+        action = ArgAction::SetTrue,
+    )] pub watch_config: bool,
+
+    // Bind IP and directory have no clap default, so that values from
+    // the configuration file are only overridden when actually given
+    #[arg(
+        help = "Bind IP, or comma separated IPs to listen on each [default: 127.0.0.1]",
         short, long, required = false,
-        default_value = "127.0.0.1",
         value_name = "IP",
         require_equals = true,
-    )] pub bind_ip: String,
+    )] pub bind_ip: Option<String>,
 
     #[arg(
-        help = "Directory to serve",
+        help = "Listen on the addresses of a network interface, e.g. eth0, the DHCP pool taking its subnet",
+        long, required = false,
+        value_name = "NAME",
+        require_equals = true,
+        conflicts_with = "bind_ip",
+    )] pub bind_iface: Option<String>,
+
+    #[arg(
+        help = "Directory to serve [default: /tmp/]",
         short = 'd', long, required = false,
-        default_value = "/tmp/",
         value_name = "PATH",
         require_equals = true,
-    )] pub serve_dir: String,
+    )] pub serve_dir: Option<String>,
+
+    #[arg(
+        help = "Graft a directory or file into the served tree, e.g. /fw=/opt/firmware (repeatable)",
+        short, long, required = false,
+        value_name = "/AT=SOURCE",
+        require_equals = true,
+    )] pub mount: Vec<Mount>,
+
+    #[arg(
+        help = "How links in the served tree are handled: follow, follow-within-root or deny [default: follow-within-root]",
+        long, required = false,
+        value_name = "POLICY",
+        require_equals = true,
+    )] pub symlinks: Option<SymlinkPolicy>,
+
+    #[arg(
+        help = "Serve what matches, even if denied, e.g. .well-known (repeatable)",
+        long, required = false,
+        value_name = "GLOB",
+        require_equals = true,
+    )] pub allow: Vec<Glob>,
+
+    #[arg(
+        help = "Never serve what matches, on top of the default rules, e.g. '**/*.key' (repeatable)",
+        long, required = false,
+        value_name = "GLOB",
+        require_equals = true,
+    )] pub deny: Vec<Glob>,
+
+    #[arg(
+        help = "Disable all allow/deny rules, serving hidden files and system directories",
+        long = "i-know-what-im-doing", required = false,
+        action = ArgAction::SetTrue,
+    )] pub i_know_what_im_doing: bool,
+
+    #[arg(
+        help = "Write the address each server is bound to as JSON, once all are ready",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub ready_file: Option<String>,
+
+    #[arg(
+        help = "File the client commands logged at startup fetch, e.g. boot/Image [default: the first one served]",
+        long, required = false,
+        value_name = "FILE",
+        require_equals = true,
+    )] pub example_file: Option<String>,
+
+    #[arg(
+        help = "Serve Prometheus metrics on /metrics, on the given port",
+        long, required = false,
+        value_name = "PORT",
+        require_equals = true,
+    )] pub metrics: Option<u16>,
+
+    #[arg(
+        help = "Serve the web interface on the given port",
+        long, required = false,
+        value_name = "PORT",
+        require_equals = true,
+    )] pub admin: Option<u16>,
+
+    #[arg(
+        help = "Token required by the web interface [default: random, printed at startup]",
+        long, required = false,
+        value_name = "TOKEN",
+        require_equals = true,
+    )] pub admin_token: Option<String>,
+
+    #[arg(
+        help = "Serve the control API on a UNIX socket, only reachable by the current user",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub control: Option<String>,
+
+    #[arg(
+        help = "Write an access log: Combined Log Format for HTTP, xferlog for FTP and TFTP",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub access_log: Option<String>,
+
+    #[arg(
+        help = "Exit once N files have been fully downloaded, over all protocols",
+        long, required = false,
+        value_name = "N",
+        require_equals = true,
+    )] pub max_transfers: Option<u64>,
+
+    #[arg(
+        help = "Exit after serving for the given time, e.g. 90s, 5m or 1h",
+        long, required = false,
+        value_name = "DURATION",
+        require_equals = true,
+        value_parser = validation::parse_duration,
+    )] pub timeout: Option<Duration>,
+
+    #[arg(
+        help = "Time given to the transfers going on to complete once stopped, e.g. 30s or 2m [default: 5s]",
+        long, required = false,
+        value_name = "DURATION",
+        require_equals = true,
+        value_parser = validation::parse_duration,
+    )] pub grace_period: Option<Duration>,
+
+    #[arg(
+        help = "Exit once the given file has been fully downloaded, with any protocol",
+        long, required = false,
+        value_name = "FILE",
+        require_equals = true,
+    )] pub exit_after: Option<String>,
 
     #[arg(
         help = "Verbose logging",
@@ -39,6 +183,34 @@ pub struct Cli {
         action = clap::ArgAction::Count,
     )] pub verbose: u8,
 
+    #[arg(
+        help = "Log format: text or json [default: text]",
+        long, required = false,
+        value_name = "FORMAT",
+        require_equals = true,
+    )] pub log_format: Option<LogFormat>,
+
+    #[arg(
+        help = "Only log the given protocols, each at an optional level, e.g. tftp,dhcp:debug",
+        long, required = false,
+        value_name = "FILTER",
+        require_equals = true,
+    )] pub log_filter: Option<LogFilter>,
+
+    #[arg(
+        help = "Also write the logs to a file, rotated once it grows past --log-max-size",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub log_file: Option<String>,
+
+    #[arg(
+        help = "Size in MiB past which the log file is rotated, keeping the last 5 [default: 10]",
+        long, required = false,
+        value_name = "MIB",
+        require_equals = true,
+    )] pub log_max_size: Option<u64>,
+
     #[arg(
         default_missing_value = Protocol::Http.get_default_port().to_string(),
         help = format!("Start the HTTP server [default port: {}]", Protocol::Http.get_default_port().to_string()),
@@ -48,6 +220,20 @@ pub struct Cli {
         value_name = "PORT",
     )] pub http: Option<u32>,
 
+    #[arg(
+        help = "Bind IP of the HTTP server [default: --bind-ip]",
+        long, required = false,
+        value_name = "IP",
+        require_equals = true,
+    )] pub http_bind: Option<String>,
+
+    #[arg(
+        help = "Directory served by the HTTP server [default: --serve-dir]",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub http_dir: Option<String>,
+
     #[arg(
         default_missing_value = Protocol::Ftp.get_default_port().to_string(),
         help = format!("Start the FTP server [default port: {}]", Protocol::Ftp.get_default_port().to_string()),
@@ -57,6 +243,20 @@ pub struct Cli {
         value_name = "PORT",
     )] pub ftp: Option<u32>,
 
+    #[arg(
+        help = "Bind IP of the FTP server [default: --bind-ip]",
+        long, required = false,
+        value_name = "IP",
+        require_equals = true,
+    )] pub ftp_bind: Option<String>,
+
+    #[arg(
+        help = "Directory served by the FTP server [default: --serve-dir]",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub ftp_dir: Option<String>,
+
     #[arg(
         default_missing_value = Protocol::Tftp.get_default_port().to_string(),
         help = format!("Start the TFTP server [default port: {}]", Protocol::Tftp.get_default_port().to_string()),
@@ -66,6 +266,20 @@ pub struct Cli {
         value_name = "PORT",
     )] pub tftp: Option<u32>,
 
+    #[arg(
+        help = "Bind IP of the TFTP server [default: --bind-ip]",
+        long, required = false,
+        value_name = "IP",
+        require_equals = true,
+    )] pub tftp_bind: Option<String>,
+
+    #[arg(
+        help = "Directory served by the TFTP server [default: --serve-dir]",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub tftp_dir: Option<String>,
+
     #[arg(
         default_missing_value = Protocol::Dhcp.get_default_port().to_string(),
         help = format!("Start the DHCP server [default port: {}]", Protocol::Dhcp.get_default_port().to_string()),
@@ -74,6 +288,253 @@ pub struct Cli {
         require_equals = true,
         value_name = "PORT",
     )] pub dhcp: Option<u32>,
+
+    #[arg(
+        help = "Bind IP of the DHCP server [default: --bind-ip]",
+        long, required = false,
+        value_name = "IP",
+        require_equals = true,
+    )] pub dhcp_bind: Option<String>,
+
+    #[arg(
+        help = "Directory served by the DHCP server [default: --serve-dir]",
+        long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub dhcp_dir: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Run instead of the servers
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Control a running instance through its control API
+    Ctl(CtlArgs),
+
+    /// Talk to a server as a client would, e.g. to check it serves a file
+    #[command(subcommand)]
+    Client(ClientCommand),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CtlArgs {
+    #[arg(
+        help = "Control socket of the instance, as given to --control",
+        short, long, required = false,
+        value_name = "PATH",
+        require_equals = true,
+    )] pub socket: Option<String>,
+
+    #[arg(
+        help = "Address of the instance's web interface, e.g. http://127.0.0.1:8081",
+        short, long, required = false,
+        value_name = "URL",
+        require_equals = true,
+    )] pub url: Option<String>,
+
+    #[arg(
+        help = "Token of the web interface, as given to --admin-token",
+        short, long, required = false,
+        value_name = "TOKEN",
+        require_equals = true,
+    )] pub token: Option<String>,
+
+    #[arg(
+        help = "Print the JSON responses as they are",
+        long, required = false,
+        action = ArgAction::SetTrue,
+    )] pub json: bool,
+
+    #[command(subcommand)]
+    pub action: CtlAction,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CtlAction {
+    /// List the servers with their status
+    Status,
+
+    /// Start a server, restarting it with the new settings if running
+    Start {
+        #[arg(help = "Protocol of the server: http, ftp, tftp or dhcp")]
+        protocol: Protocol,
+
+        #[arg(
+            help = "Port to listen on",
+            short, long, required = false,
+            value_name = "PORT",
+            require_equals = true,
+        )] port: Option<u16>,
+
+        #[arg(
+            help = "Bind IP, or comma separated IPs [default: the instance's]",
+            short, long, required = false,
+            value_name = "IP",
+            require_equals = true,
+        )] bind_ip: Option<String>,
+
+        #[arg(
+            help = "Directory to serve [default: the instance's]",
+            short = 'd', long, required = false,
+            value_name = "PATH",
+            require_equals = true,
+        )] serve_dir: Option<String>,
+
+        #[arg(
+            help = "Graft a directory or file into the served tree, replacing the instance's (repeatable)",
+            short, long, required = false,
+            value_name = "/AT=SOURCE",
+            require_equals = true,
+        )] mount: Vec<Mount>,
+
+        #[arg(
+            help = "How links in the served tree are handled: follow, follow-within-root or deny",
+            long, required = false,
+            value_name = "POLICY",
+            require_equals = true,
+        )] symlinks: Option<SymlinkPolicy>,
+    },
+
+    /// Stop a server
+    Stop {
+        #[arg(help = "Protocol of the server: http, ftp, tftp or dhcp")]
+        protocol: Protocol,
+    },
+
+    /// Change the directory and bind IP servers fall back to when started
+    Set {
+        #[arg(
+            help = "Directory to serve",
+            short = 'd', long, required = false,
+            value_name = "PATH",
+            require_equals = true,
+        )] serve_dir: Option<String>,
+
+        #[arg(
+            help = "Bind IP, or comma separated IPs",
+            short, long, required = false,
+            value_name = "IP",
+            require_equals = true,
+        )] bind_ip: Option<String>,
+    },
+
+    /// Print what was served so far, per protocol, client and file
+    Stats,
+
+    /// Print the last logs
+    Logs {
+        #[arg(
+            help = "Keep printing new logs as they come",
+            short, long, required = false,
+            action = ArgAction::SetTrue,
+        )] follow: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ClientCommand {
+    /// TFTP client
+    #[command(subcommand)]
+    Tftp(TftpCommand),
+
+    /// FTP client, logging in anonymously unless given a --user
+    #[command(subcommand)]
+    Ftp(FtpCommand),
+
+    /// HTTP client
+    #[command(subcommand)]
+    Http(HttpCommand),
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum TftpCommand {
+    /// Download a file
+    Get {
+        #[arg(help = "Server, e.g. 192.168.1.10:6969 [default port: 69]", value_name = "HOST:PORT")]
+        server: String,
+
+        #[arg(help = "File to download")]
+        file: String,
+
+        #[arg(help = "Where to write it, - for stdout [default: its name, in the current directory]")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FtpCommand {
+    /// List a directory
+    Ls {
+        #[arg(help = "Server, e.g. 192.168.1.10:2121 [default port: 21]", value_name = "HOST:PORT")]
+        server: String,
+
+        #[arg(help = "Directory to list [default: the root]")]
+        path: Option<String>,
+
+        #[arg(
+            help = "User to log in as",
+            short, long, required = false,
+            value_name = "USER[:PASSWORD]",
+            require_equals = true,
+        )] user: Option<String>,
+    },
 
+    /// Download a file
+    Get {
+        #[arg(help = "Server, e.g. 192.168.1.10:2121 [default port: 21]", value_name = "HOST:PORT")]
+        server: String,
+
+        #[arg(help = "File to download")]
+        file: String,
+
+        #[arg(help = "Where to write it, - for stdout [default: its name, in the current directory]")]
+        output: Option<String>,
+
+        #[arg(
+            help = "User to log in as",
+            short, long, required = false,
+            value_name = "USER[:PASSWORD]",
+            require_equals = true,
+        )] user: Option<String>,
+    },
+
+    /// Upload a file
+    Put {
+        #[arg(help = "Server, e.g. 192.168.1.10:2121 [default port: 21]", value_name = "HOST:PORT")]
+        server: String,
+
+        #[arg(help = "File to upload, - for stdin")]
+        file: String,
+
+        #[arg(help = "Where to write it on the server [default: its name, in the root]")]
+        remote: Option<String>,
+
+        #[arg(
+            help = "User to log in as",
+            short, long, required = false,
+            value_name = "USER[:PASSWORD]",
+            require_equals = true,
+        )] user: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum HttpCommand {
+    /// Download a file
+    Get {
+        #[arg(help = "URL of the file, e.g. http://192.168.1.10:8080/fw/a.bin")]
+        url: String,
+
+        #[arg(help = "Where to write it, - for stdout [default: its name, in the current directory]")]
+        output: Option<String>,
+
+        #[arg(
+            help = "User to authenticate as, with basic authentication",
+            short, long, required = false,
+            value_name = "USER[:PASSWORD]",
+            require_equals = true,
+        )] user: Option<String>,
+    },
+}
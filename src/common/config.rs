@@ -0,0 +1,700 @@
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::servers::{DhcpPool, Mount, Protocol, DEFAULT_GRACE_PERIOD};
+use crate::utils::access::AccessRules;
+use crate::utils::net::{self, InterfaceAddr};
+use crate::utils::validation::SymlinkPolicy;
+use crate::{Cli, QuickServeError, QuickServeResult};
+
+/// Name of the configuration file, looked up in the user's config directory
+pub const CONFIG_FILE_NAME: &str = "quick-serve.toml";
+
+/// Username and password a client has to present
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// `[http]` section of the configuration file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpSection {
+    /// Start the server when running headless
+    pub enabled: bool,
+    pub port: u16,
+    /// Overrides the global `bind_ip` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_ip: Option<String>,
+    /// Overrides the global `serve_dir` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_dir: Option<String>,
+    /// Require HTTP basic authentication
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Credentials>,
+}
+
+impl Default for HttpSection {
+    fn default() -> Self {
+        HttpSection {
+            enabled: false,
+            port: Protocol::Http.get_default_port(),
+            bind_ip: None,
+            serve_dir: None,
+            auth: None,
+        }
+    }
+}
+
+/// `[ftp]` section of the configuration file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FtpSection {
+    /// Start the server when running headless
+    pub enabled: bool,
+    pub port: u16,
+    /// Overrides the global `bind_ip` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_ip: Option<String>,
+    /// Overrides the global `serve_dir` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_dir: Option<String>,
+    /// Require a login instead of accepting anyone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Credentials>,
+    /// Range of ports offered for passive data connections
+    pub passive_ports: RangeInclusive<u16>,
+    /// No longer used, the global `grace_period` applying to all the servers.
+    /// Still read, for the files written by earlier versions to load
+    #[serde(skip_serializing)]
+    pub grace_period_secs: Option<u64>,
+}
+
+impl Default for FtpSection {
+    fn default() -> Self {
+        FtpSection {
+            enabled: false,
+            port: Protocol::Ftp.get_default_port(),
+            bind_ip: None,
+            serve_dir: None,
+            auth: None,
+            passive_ports: 50000..=65535,
+            grace_period_secs: None,
+        }
+    }
+}
+
+/// `[tftp]` section of the configuration file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TftpSection {
+    /// Start the server when running headless
+    pub enabled: bool,
+    pub port: u16,
+    /// Overrides the global `bind_ip` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_ip: Option<String>,
+    /// Overrides the global `serve_dir` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_dir: Option<String>,
+    /// Seconds to wait for an acknowledgement before resending a block
+    pub timeout_secs: u64,
+    /// Largest block size negotiated with clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_size_limit: Option<u16>,
+}
+
+impl Default for TftpSection {
+    fn default() -> Self {
+        TftpSection {
+            enabled: false,
+            port: Protocol::Tftp.get_default_port(),
+            bind_ip: None,
+            serve_dir: None,
+            timeout_secs: 3,
+            block_size_limit: None,
+        }
+    }
+}
+
+/// `[dhcp]` section of the configuration file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DhcpSection {
+    /// Start the server when running headless
+    pub enabled: bool,
+    pub port: u16,
+    /// Overrides the global `bind_ip` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_ip: Option<String>,
+    /// Overrides the global `serve_dir` for this server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_dir: Option<String>,
+    /// Addresses and options handed out to clients
+    pub pool: DhcpPool,
+}
+
+impl Default for DhcpSection {
+    fn default() -> Self {
+        DhcpSection {
+            enabled: false,
+            port: Protocol::Dhcp.get_default_port(),
+            bind_ip: None,
+            serve_dir: None,
+            pool: DhcpPool::default(),
+        }
+    }
+}
+
+/// Settings of all servers, as read from `quick-serve.toml`
+///
+/// Every field is optional in the file; missing ones keep their default.
+/// Command line flags take precedence over the file (see [`Config::resolve`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_ip: String,
+    pub serve_dir: String,
+    /// Directories and files grafted into the served tree, e.g. `"/fw=/opt/firmware"`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+    /// How links found in the served tree are handled
+    pub symlinks: SymlinkPolicy,
+    /// `[access]` section, with the rules deciding what may be served
+    pub access: AccessRules,
+    /// Port of the Prometheus metrics endpoint, served on `bind_ip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<u16>,
+    /// Port of the web interface, served on `bind_ip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<u16>,
+    /// Token required by the web interface, a random one being generated if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+    /// UNIX socket the control API is served on, without token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control: Option<String>,
+    /// File the HTTP requests and the FTP/TFTP transfers are logged to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<String>,
+    /// Given to the transfers going on to complete once the servers are stopped,
+    /// e.g. `"30s"` or `"500ms"`. Also read from `grace_period_secs`, as written
+    /// by earlier versions
+    #[serde(alias = "grace_period_secs", with = "duration")]
+    pub grace_period: Duration,
+    /// File the client commands logged at startup fetch, e.g. `boot/Image`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example_file: Option<String>,
+    pub http: HttpSection,
+    pub ftp: FtpSection,
+    pub tftp: TftpSection,
+    pub dhcp: DhcpSection,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_ip: "127.0.0.1".to_string(),
+            serve_dir: "/tmp/".to_string(),
+            mounts: Vec::new(),
+            symlinks: SymlinkPolicy::default(),
+            access: AccessRules::default(),
+            metrics: None,
+            admin: None,
+            admin_token: None,
+            control: None,
+            access_log: None,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            example_file: None,
+            http: HttpSection::default(),
+            ftp: FtpSection::default(),
+            tftp: TftpSection::default(),
+            dhcp: DhcpSection::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Returns where the configuration file is expected by default
+    ///
+    /// That is `$XDG_CONFIG_HOME/quick-serve/quick-serve.toml`, falling back to
+    /// `~/.config` (or `%APPDATA%` on Windows) when `XDG_CONFIG_HOME` is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let non_empty = |var| std::env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from);
+
+        let base = non_empty("XDG_CONFIG_HOME")
+            .or_else(|| non_empty("APPDATA"))
+            .or_else(|| non_empty("HOME").map(|home| home.join(".config")))?;
+        Some(base.join("quick-serve").join(CONFIG_FILE_NAME))
+    }
+
+    /// Returns the configuration file used for the given command line
+    ///
+    /// The one given with `--config`, otherwise the default location.
+    pub fn path(cli: &Cli) -> Option<PathBuf> {
+        cli.config.as_ref().map(PathBuf::from).or_else(Config::default_path)
+    }
+
+    /// Reads a configuration file
+    ///
+    /// # Arguments
+    /// * `path` - The TOML file to read
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The settings, with defaults for whatever is missing
+    /// * `Err(QuickServeError)` - If the file cannot be read or is invalid
+    pub fn load(path: &Path) -> QuickServeResult<Config> {
+        let content = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| QuickServeError::validation(format!("Invalid config file {}: {}", path.display(), e)))?;
+        if config.ftp.grace_period_secs.is_some() {
+            warn!("Ignoring [ftp] grace_period_secs in {}, the global grace_period applies to all the servers", path.display());
+        }
+        Ok(config)
+    }
+
+    /// Writes the settings to a configuration file, creating its directory if needed
+    ///
+    /// # Arguments
+    /// * `path` - The TOML file to write
+    pub fn save(&self, path: &Path) -> QuickServeResult<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| QuickServeError::validation(format!("Failed to serialize config: {}", e)))?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Builds the effective settings for a command line
+    ///
+    /// The file given with `--config` must exist. The one in the default
+    /// location is only read if present. Flags given on the command line
+    /// override the values from the file.
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The merged settings
+    /// * `Err(QuickServeError)` - If the configuration file is missing or invalid
+    pub fn resolve(cli: &Cli) -> QuickServeResult<Config> {
+        let mut config = match (&cli.config, Config::default_path()) {
+            (Some(path), _) => Config::load(Path::new(path))?,
+            (None, Some(path)) if path.is_file() => Config::load(&path)?,
+            _ => Config::default(),
+        };
+        config.apply_cli(cli);
+        if let Some(name) = &cli.bind_iface {
+            config.bind_interface(&net::interface_addrs(name)?);
+        }
+        Ok(config)
+    }
+
+    /// Listens on the addresses of a network interface
+    ///
+    /// Unless set otherwise, the DHCP server listens on its (first) IPv4
+    /// address and leases in its subnet.
+    pub fn bind_interface(&mut self, addrs: &[InterfaceAddr]) {
+        self.bind_ip = net::bind_ip(addrs);
+        let Some((ip, prefix_len)) = addrs.iter().find_map(|iface| match iface.ip {
+            IpAddr::V4(ip) => Some((ip, iface.prefix_len)),
+            IpAddr::V6(_) => None,
+        }) else { return };
+
+        if self.dhcp.bind_ip.is_none() {
+            self.dhcp.bind_ip = Some(ip.to_string());
+        }
+        if self.dhcp.pool == DhcpPool::default() {
+            if let Some(pool) = DhcpPool::for_subnet(ip, prefix_len) {
+                self.dhcp.pool = pool;
+            }
+        }
+    }
+
+    /// Overrides the settings with the flags given on the command line
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(bind_ip) = &cli.bind_ip {
+            self.bind_ip = bind_ip.clone();
+        }
+        if let Some(serve_dir) = &cli.serve_dir {
+            self.serve_dir = serve_dir.clone();
+        }
+        if let Some(port) = cli.metrics {
+            self.metrics = Some(port);
+        }
+        if let Some(port) = cli.admin {
+            self.admin = Some(port);
+        }
+        if let Some(token) = &cli.admin_token {
+            self.admin_token = Some(token.clone());
+        }
+        if let Some(path) = &cli.control {
+            self.control = Some(path.clone());
+        }
+        if let Some(path) = &cli.access_log {
+            self.access_log = Some(path.clone());
+        }
+        if let Some(grace_period) = cli.grace_period {
+            self.grace_period = grace_period;
+        }
+        if let Some(file) = &cli.example_file {
+            self.example_file = Some(file.clone());
+        }
+        if let Some(symlinks) = cli.symlinks {
+            self.symlinks = symlinks;
+        }
+        self.access.allow.extend(cli.allow.iter().cloned());
+        self.access.deny.extend(cli.deny.iter().cloned());
+        if cli.i_know_what_im_doing {
+            self.access.unrestricted = true;
+        }
+        // Mounts given on the command line add up to the ones from the file,
+        // replacing any at the same mount point
+        for mount in &cli.mount {
+            self.mounts.retain(|m| m.at != mount.at);
+            self.mounts.push(mount.clone());
+        }
+
+        let requested = [
+            (Protocol::Http, cli.http, &cli.http_bind, &cli.http_dir),
+            (Protocol::Ftp, cli.ftp, &cli.ftp_bind, &cli.ftp_dir),
+            (Protocol::Tftp, cli.tftp, &cli.tftp_bind, &cli.tftp_dir),
+            (Protocol::Dhcp, cli.dhcp, &cli.dhcp_bind, &cli.dhcp_dir),
+        ];
+        for (protocol, port, bind_ip, serve_dir) in requested {
+            if let Some(port) = port {
+                self.set_server(&protocol, true, port as u16);
+            }
+            let (current_bind, current_dir) = self.overrides(&protocol);
+            let bind_ip = bind_ip.as_ref().or(current_bind).cloned();
+            let serve_dir = serve_dir.as_ref().or(current_dir).cloned();
+            self.set_overrides(&protocol, bind_ip, serve_dir);
+        }
+    }
+
+    /// Returns whether a protocol's server is enabled, and on which port
+    pub fn server(&self, protocol: &Protocol) -> Option<(bool, u16)> {
+        match protocol {
+            Protocol::Http => Some((self.http.enabled, self.http.port)),
+            Protocol::Ftp => Some((self.ftp.enabled, self.ftp.port)),
+            Protocol::Tftp => Some((self.tftp.enabled, self.tftp.port)),
+            Protocol::Dhcp => Some((self.dhcp.enabled, self.dhcp.port)),
+            Protocol::Custom(_) => None,
+        }
+    }
+
+    /// Enables or disables a protocol's server, on the given port
+    pub fn set_server(&mut self, protocol: &Protocol, enabled: bool, port: u16) {
+        let (e, p) = match protocol {
+            Protocol::Http => (&mut self.http.enabled, &mut self.http.port),
+            Protocol::Ftp => (&mut self.ftp.enabled, &mut self.ftp.port),
+            Protocol::Tftp => (&mut self.tftp.enabled, &mut self.tftp.port),
+            Protocol::Dhcp => (&mut self.dhcp.enabled, &mut self.dhcp.port),
+            Protocol::Custom(_) => return,
+        };
+        *e = enabled;
+        *p = port;
+    }
+
+    /// Returns the bind address and directory overrides of a protocol's server
+    pub fn overrides(&self, protocol: &Protocol) -> (Option<&String>, Option<&String>) {
+        match protocol {
+            Protocol::Http => (self.http.bind_ip.as_ref(), self.http.serve_dir.as_ref()),
+            Protocol::Ftp => (self.ftp.bind_ip.as_ref(), self.ftp.serve_dir.as_ref()),
+            Protocol::Tftp => (self.tftp.bind_ip.as_ref(), self.tftp.serve_dir.as_ref()),
+            Protocol::Dhcp => (self.dhcp.bind_ip.as_ref(), self.dhcp.serve_dir.as_ref()),
+            Protocol::Custom(_) => (None, None),
+        }
+    }
+
+    /// Overrides (or, with `None`, resets to the global one) the bind
+    /// address and directory of a protocol's server
+    pub fn set_overrides(&mut self, protocol: &Protocol, bind_ip: Option<String>, serve_dir: Option<String>) {
+        let (b, d) = match protocol {
+            Protocol::Http => (&mut self.http.bind_ip, &mut self.http.serve_dir),
+            Protocol::Ftp => (&mut self.ftp.bind_ip, &mut self.ftp.serve_dir),
+            Protocol::Tftp => (&mut self.tftp.bind_ip, &mut self.tftp.serve_dir),
+            Protocol::Dhcp => (&mut self.dhcp.bind_ip, &mut self.dhcp.serve_dir),
+            Protocol::Custom(_) => return,
+        };
+        *b = bind_ip;
+        *d = serve_dir;
+    }
+
+    /// Returns the address a protocol's server binds to
+    pub fn bind_ip_for(&self, protocol: &Protocol) -> &str {
+        self.overrides(protocol).0.unwrap_or(&self.bind_ip)
+    }
+
+    /// Returns the directory a protocol's server serves
+    pub fn serve_dir_for(&self, protocol: &Protocol) -> &str {
+        self.overrides(protocol).1.unwrap_or(&self.serve_dir)
+    }
+}
+
+/// Reads durations as written on the command line, e.g. `"30s"` or `"500ms"`,
+/// or as a number of seconds, and writes them back the former way
+mod duration {
+    use std::time::Duration;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use crate::utils::validation;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Written {
+        Secs(u64),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&validation::format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        match Written::deserialize(deserializer)? {
+            Written::Secs(secs) => Ok(Duration::from_secs(secs)),
+            Written::Text(text) => validation::parse_duration(&text).map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_parse_full_file() {
+        let config: Config = toml::from_str(r#"
+            bind_ip = "0.0.0.0"
+            serve_dir = "/srv/images"
+
+            [http]
+            enabled = true
+            port = 8000
+            auth = { username = "user", password = "secret" }
+
+            [ftp]
+            port = 2100
+            passive_ports = { start = 40000, end = 40010 }
+
+            [tftp]
+            enabled = true
+            timeout_secs = 10
+            block_size_limit = 1024
+
+            [dhcp]
+            [dhcp.pool]
+            start = "10.0.0.10"
+            size = 5
+            router = "10.0.0.1"
+            dns = ["10.0.0.1"]
+        "#).expect("config should parse");
+
+        assert_eq!(config.bind_ip, "0.0.0.0");
+        assert_eq!(config.serve_dir, "/srv/images");
+        assert_eq!(config.server(&Protocol::Http), Some((true, 8000)));
+        assert_eq!(config.http.auth, Some(Credentials { username: "user".into(), password: "secret".into() }));
+        assert_eq!(config.server(&Protocol::Ftp), Some((false, 2100)));
+        assert_eq!(config.ftp.passive_ports, 40000..=40010);
+        assert_eq!(config.server(&Protocol::Tftp), Some((true, 6969)));
+        assert_eq!(config.tftp.block_size_limit, Some(1024));
+        assert_eq!(config.dhcp.pool.start, Ipv4Addr::new(10, 0, 0, 10));
+        assert_eq!(config.dhcp.pool.size, 5);
+        // Not given, so the default is kept
+        assert_eq!(config.dhcp.pool.subnet_mask, Ipv4Addr::new(255, 255, 255, 0));
+    }
+
+    #[test]
+    fn test_empty_file_is_default() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("bind_pi = \"0.0.0.0\"").is_err());
+        assert!(toml::from_str::<Config>("[http]\nprot = 80").is_err());
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let mut config: Config = toml::from_str(r#"
+            bind_ip = "0.0.0.0"
+            serve_dir = "/srv"
+            [http]
+            enabled = true
+            port = 8000
+            [ftp]
+            enabled = true
+        "#).unwrap();
+
+        let cli = Cli::parse_from(["quick-serve", "--serve-dir=/data", "--http=9000", "--tftp"]);
+        config.apply_cli(&cli);
+
+        assert_eq!(config.bind_ip, "0.0.0.0", "not given on the command line, file value is kept");
+        assert_eq!(config.serve_dir, "/data");
+        assert_eq!(config.server(&Protocol::Http), Some((true, 9000)));
+        assert_eq!(config.server(&Protocol::Ftp), Some((true, 2121)));
+        assert_eq!(config.server(&Protocol::Tftp), Some((true, 6969)));
+        assert_eq!(config.server(&Protocol::Dhcp), Some((false, 6767)));
+    }
+
+    #[test]
+    fn test_per_protocol_overrides() {
+        let mut config: Config = toml::from_str(r#"
+            bind_ip = "127.0.0.1"
+            serve_dir = "/srv/images"
+            [tftp]
+            bind_ip = "10.0.0.1"
+            serve_dir = "/srv/tftp"
+            [dhcp]
+            bind_ip = "10.0.0.1"
+        "#).unwrap();
+
+        let cli = Cli::parse_from(["quick-serve", "--http-bind=0.0.0.0", "--tftp-dir=/data/tftp"]);
+        config.apply_cli(&cli);
+
+        assert_eq!(config.bind_ip_for(&Protocol::Http), "0.0.0.0");
+        assert_eq!(config.serve_dir_for(&Protocol::Http), "/srv/images");
+        assert_eq!(config.bind_ip_for(&Protocol::Tftp), "10.0.0.1", "not given on the command line, file value is kept");
+        assert_eq!(config.serve_dir_for(&Protocol::Tftp), "/data/tftp");
+        assert_eq!(config.bind_ip_for(&Protocol::Dhcp), "10.0.0.1");
+        assert_eq!(config.bind_ip_for(&Protocol::Ftp), "127.0.0.1");
+        assert_eq!(config.serve_dir_for(&Protocol::Ftp), "/srv/images");
+    }
+
+    #[test]
+    fn test_mounts_from_file_and_cli() {
+        let mut config: Config = toml::from_str(r#"
+            mounts = ["/fw=/opt/firmware", "/kernel=/build/out/Image"]
+        "#).unwrap();
+        assert_eq!(config.mounts.len(), 2);
+        assert!(toml::from_str::<Config>("mounts = [\"/fw\"]").is_err(), "mounts need a source");
+
+        let cli = Cli::parse_from(["quick-serve", "--mount=/fw=/srv/firmware", "-m=/extra=/srv/extra"]);
+        config.apply_cli(&cli);
+
+        let mounts: Vec<String> = config.mounts.iter().map(|m| m.to_string()).collect();
+        assert_eq!(mounts, ["/kernel=/build/out/Image", "/fw=/srv/firmware", "/extra=/srv/extra"]);
+    }
+
+    #[test]
+    fn test_symlink_policy_from_file_and_cli() {
+        let mut config: Config = toml::from_str("symlinks = \"deny\"").unwrap();
+        assert_eq!(config.symlinks, SymlinkPolicy::Deny);
+        assert!(toml::from_str::<Config>("symlinks = \"sometimes\"").is_err());
+
+        config.apply_cli(&Cli::parse_from(["quick-serve"]));
+        assert_eq!(config.symlinks, SymlinkPolicy::Deny, "not given on the command line, file value is kept");
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--symlinks=follow"]));
+        assert_eq!(config.symlinks, SymlinkPolicy::Follow);
+        assert!(Cli::try_parse_from(["quick-serve", "--symlinks=sometimes"]).is_err());
+    }
+
+    #[test]
+    fn test_access_rules_from_file_and_cli() {
+        let mut config: Config = toml::from_str(r#"
+            [access]
+            allow = [".well-known"]
+            deny = ["*.pem"]
+        "#).unwrap();
+        assert!(config.access.matcher().check_file(".git/config").is_err(), "default deny rules are kept");
+
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--deny=**/*.key", "--allow=.netrc"]));
+        assert_eq!(config.access.allow.len(), 2);
+        assert_eq!(config.access.deny.len(), 2, "added to the file's rules");
+        assert!(!config.access.unrestricted);
+
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--i-know-what-im-doing"]));
+        assert!(config.access.unrestricted);
+        assert!(Cli::try_parse_from(["quick-serve", "--deny=a["]).is_err(), "invalid globs are rejected");
+    }
+
+    #[test]
+    fn test_metrics_port_from_file_and_cli() {
+        let mut config: Config = toml::from_str("metrics = 9100").unwrap();
+        assert_eq!(config.metrics, Some(9100));
+
+        config.apply_cli(&Cli::parse_from(["quick-serve"]));
+        assert_eq!(config.metrics, Some(9100), "kept when not given on the command line");
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--metrics=9200"]));
+        assert_eq!(config.metrics, Some(9200));
+    }
+
+    #[test]
+    fn test_grace_period_from_file_and_cli() {
+        let mut config: Config = toml::from_str("grace_period = \"1500ms\"").unwrap();
+        assert_eq!(config.grace_period, Duration::from_millis(1500), "written as on the command line");
+        assert!(toml::from_str::<Config>("grace_period = \"soon\"").is_err());
+
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--grace-period=2m"]));
+        assert_eq!(config.grace_period, Duration::from_secs(120));
+        config.apply_cli(&Cli::parse_from(["quick-serve", "--grace-period=500ms"]));
+        assert_eq!(config.grace_period, Duration::from_millis(500), "sub-second periods are kept");
+        assert_eq!(Config::default().grace_period, DEFAULT_GRACE_PERIOD);
+
+        let saved: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.grace_period, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_grace_period_former_keys() {
+        let config: Config = toml::from_str("grace_period_secs = 30\n[ftp]\ngrace_period_secs = 60").unwrap();
+        assert_eq!(config.grace_period, Duration::from_secs(30), "read as seconds");
+        assert_eq!(config.ftp.grace_period_secs, Some(60), "accepted, though ignored");
+        assert!(!toml::to_string(&config).unwrap().contains("grace_period_secs"), "not written back");
+    }
+
+    #[test]
+    fn test_bind_interface() {
+        let iface = |ip: &str| InterfaceAddr { name: "eth0".into(), ip: ip.parse().unwrap(), prefix_len: 24 };
+        let mut config = Config::default();
+        config.bind_interface(&[iface("2001:db8::2"), iface("192.168.1.2")]);
+        assert_eq!(config.bind_ip, "2001:db8::2, 192.168.1.2");
+        assert_eq!(config.dhcp.bind_ip.as_deref(), Some("192.168.1.2"));
+        assert_eq!(config.dhcp.pool.start, Ipv4Addr::new(192, 168, 1, 128));
+
+        // DHCP settings from the file are kept
+        let mut config: Config = toml::from_str("[dhcp]\nbind_ip = \"0.0.0.0\"\n[dhcp.pool]\nsize = 10").unwrap();
+        config.bind_interface(&[iface("192.168.1.2")]);
+        assert_eq!(config.dhcp.bind_ip.as_deref(), Some("0.0.0.0"));
+        assert_eq!(config.dhcp.pool.size, 10);
+
+        assert!(Cli::try_parse_from(["quick-serve", "--bind-iface=eth0", "--bind-ip=127.0.0.1"]).is_err());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&path, "").unwrap();
+        let cli = Cli::parse_from(["quick-serve", &format!("--config={}", path.display()), "--bind-iface=nonexistent0"]);
+        assert!(Config::resolve(&cli).unwrap_err().to_string().contains("nonexistent0"));
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(CONFIG_FILE_NAME);
+
+        let mut config = Config { bind_ip: "192.168.1.2".into(), ..Default::default() };
+        config.set_server(&Protocol::Tftp, true, 0);
+        config.set_overrides(&Protocol::Tftp, Some("10.0.0.1".into()), None);
+        config.ftp.auth = Some(Credentials { username: "a".into(), password: "b".into() });
+
+        config.save(&path).expect("config should be saved");
+        assert_eq!(Config::load(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn test_resolve_fails_on_missing_explicit_file() {
+        let cli = Cli::parse_from(["quick-serve", "--config=/this/file/does/not/exist.toml"]);
+        assert!(Config::resolve(&cli).is_err());
+    }
+}
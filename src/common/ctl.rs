@@ -0,0 +1,318 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HOST};
+use hyper::{Method, Request, Response};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+
+use crate::client::http::send;
+use crate::client::network;
+use crate::{format_bytes, CtlAction, CtlArgs, QuickServeError, QuickServeResult};
+
+/// How long a server is waited for, once asked to start or stop
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the control API of a running instance is reached
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Endpoint {
+    /// Its control socket, no token being needed
+    Socket(String),
+    /// Its web interface, as `host:port`
+    Web { authority: String, token: Option<String> },
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Socket(path) => write!(f, "{}", path),
+            Endpoint::Web { authority, .. } => write!(f, "http://{}", authority),
+        }
+    }
+}
+
+impl Endpoint {
+    fn from_args(args: &CtlArgs) -> QuickServeResult<Self> {
+        match (&args.socket, &args.url) {
+            (Some(path), None) => Ok(Endpoint::Socket(path.clone())),
+            (None, Some(url)) => {
+                let authority = url.strip_prefix("http://").unwrap_or(url).trim_end_matches('/');
+                if authority.is_empty() || authority.contains('/') {
+                    return Err(QuickServeError::validation(format!("Invalid URL: {} (expected e.g. http://127.0.0.1:8081)", url)));
+                }
+                Ok(Endpoint::Web { authority: authority.to_string(), token: args.token.clone() })
+            }
+            _ => Err(QuickServeError::validation("Give either the --socket or the --url of the instance")),
+        }
+    }
+
+    /// Sends a request, with a JSON body if any
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> QuickServeResult<Response<Incoming>> {
+        let mut builder = Request::builder().method(method).uri(path);
+        match self {
+            Endpoint::Socket(_) => builder = builder.header(HOST, "localhost"),
+            Endpoint::Web { authority, token } => {
+                builder = builder.header(HOST, authority.as_str());
+                if let Some(token) = token {
+                    builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+                }
+            }
+        }
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let req = builder.header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| QuickServeError::validation(e.to_string()))?;
+
+        let failed = |e: std::io::Error| QuickServeError::Network(format!("Failed to connect to {}: {}", self, e));
+        match self {
+            #[cfg(unix)]
+            Endpoint::Socket(path) => send(tokio::net::UnixStream::connect(path).await.map_err(failed)?, req).await,
+            #[cfg(not(unix))]
+            Endpoint::Socket(_) => Err(QuickServeError::validation("The control socket is only supported on Unix")),
+            Endpoint::Web { authority, .. } => send(TcpStream::connect(authority.as_str()).await.map_err(failed)?, req).await,
+        }
+    }
+
+    /// Reads a JSON response, turning the errors the instance replies with into ours
+    async fn read(&self, response: Response<Incoming>) -> QuickServeResult<Value> {
+        let status = response.status();
+        let body = response.into_body().collect().await.map_err(network)?.to_bytes();
+        let value: Value = serde_json::from_slice(&body)
+            .map_err(|e| QuickServeError::Network(format!("Invalid response from {}: {}", self, e)))?;
+        if !status.is_success() {
+            let message = value["error"].as_str().unwrap_or_default();
+            return Err(QuickServeError::Network(format!("{} replied {}: {}", self, status, message)));
+        }
+        Ok(value)
+    }
+
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> QuickServeResult<Value> {
+        let response = self.request(method, path, body).await?;
+        self.read(response).await
+    }
+
+    /// Waits for a server to be listening (or to have failed) once started,
+    /// or to be stopped, returning the state it ended up in
+    ///
+    /// # Returns
+    /// * `Err(QuickServeError)` - If the server failed to start
+    async fn settle(&self, protocol: &str, start: bool, mut state: Value) -> QuickServeResult<Value> {
+        let deadline = Instant::now() + SETTLE_TIMEOUT;
+        loop {
+            let server = state["servers"].as_array().into_iter().flatten()
+                .find(|server| server["protocol"] == protocol)
+                .cloned()
+                .unwrap_or_default();
+            match server["status"].as_str() {
+                Some("failed") if start => {
+                    return Err(QuickServeError::server_lifecycle(format!("{} server failed: {}",
+                        protocol.to_uppercase(), server["error"].as_str().unwrap_or_default())));
+                }
+                Some("listening") if start => return Ok(state),
+                Some(status) if !start && status != "listening" => return Ok(state),
+                _ if Instant::now() >= deadline => return Ok(state),
+                _ => {}
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            state = self.call(Method::GET, "/api/state", None).await?;
+        }
+    }
+
+    /// Prints the logs kept so far, then the new ones as they come if following
+    async fn logs(&self, follow: bool, raw: bool) -> QuickServeResult<()> {
+        let path = if follow { "/api/logs" } else { "/api/logs?follow=false" };
+        let response = self.request(Method::GET, path, None).await?;
+        if !response.status().is_success() {
+            return self.read(response).await.map(|_| ());
+        }
+
+        let mut body = response.into_body();
+        let mut pending = String::new();
+        while let Some(frame) = body.frame().await {
+            let Ok(data) = frame.map_err(network)?.into_data() else { continue };
+            pending.push_str(&String::from_utf8_lossy(&data));
+            while let Some(end) = pending.find("\n\n") {
+                let event: String = pending.drain(..end + 2).collect();
+                for data in event.lines().filter_map(|line| line.strip_prefix("data: ")) {
+                    if raw {
+                        println!("{}", data);
+                    } else if let Ok(entry) = serde_json::from_str::<Value>(data) {
+                        println!("{}", log_line(&entry));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A log entry of the stream, as printed
+fn log_line(entry: &Value) -> String {
+    let text = |key: &str| entry[key].as_str().unwrap_or_default().to_string();
+    match entry["protocol"].as_str() {
+        Some(protocol) => format!("[{}] {}: {}", text("level"), protocol, text("message")),
+        None => format!("[{}] {}", text("level"), text("message")),
+    }
+}
+
+/// The settings, each server with its status, and the transfers going on, one line each
+fn state_lines(state: &Value) -> Vec<String> {
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+    let bytes = |value: &Value| format_bytes(value.as_u64().unwrap_or_default());
+
+    let mut lines = vec![format!("Directory: {}, bind IP: {}", text(&state["path"]), text(&state["bind_ip"]))];
+    for server in state["servers"].as_array().into_iter().flatten() {
+        let detail = match server["status"].as_str() {
+            Some("listening") => {
+                let addrs: Vec<String> = server["addresses"].as_array().into_iter().flatten().map(text).collect();
+                format!("{}, {} requests, {} downloads, {} errors, {} sent, {} received",
+                    addrs.join(", "), server["requests"], server["downloads"], server["errors"],
+                    bytes(&server["bytes_sent"]), bytes(&server["bytes_received"]))
+            }
+            Some("failed") => text(&server["error"]),
+            _ => format!("port {}", server["port"]),
+        };
+        lines.push(format!("{:<5} {:<9} {}", text(&server["protocol"]).to_uppercase(), text(&server["status"]), detail));
+    }
+    for t in state["transfers"].as_array().into_iter().flatten() {
+        let progress = t["progress"].as_f64().map(|p| format!(" {:.0}%", p * 100.0)).unwrap_or_default();
+        let client = t["client"].as_str().unwrap_or("-");
+        lines.push(format!("  {} {} {} {}{} {} at {}/s", text(&t["protocol"]).to_uppercase(), text(&t["direction"]),
+            client, text(&t["path"]), progress, bytes(&t["bytes"]), bytes(&t["rate"])));
+    }
+    lines
+}
+
+async fn ctl(args: &CtlArgs) -> QuickServeResult<()> {
+    let endpoint = Endpoint::from_args(args)?;
+    let state = match &args.action {
+        CtlAction::Status => endpoint.call(Method::GET, "/api/state", None).await?,
+        CtlAction::Start { protocol, port, bind_ip, serve_dir, mount, symlinks } => {
+            let mut body = json!({ "start": true });
+            if let Some(port) = port {
+                body["port"] = json!(port);
+            }
+            if let Some(ip) = bind_ip {
+                body["bind_ip"] = json!(ip);
+            }
+            if let Some(dir) = serve_dir {
+                body["path"] = json!(dir);
+            }
+            if !mount.is_empty() {
+                body["mounts"] = json!(mount);
+            }
+            if let Some(symlinks) = symlinks {
+                body["symlinks"] = json!(symlinks);
+            }
+            let state = endpoint.call(Method::POST, &format!("/api/servers/{}", protocol.to_string()), Some(body)).await?;
+            endpoint.settle(protocol.to_string(), true, state).await?
+        }
+        CtlAction::Stop { protocol } => {
+            let body = json!({ "start": false });
+            let state = endpoint.call(Method::POST, &format!("/api/servers/{}", protocol.to_string()), Some(body)).await?;
+            endpoint.settle(protocol.to_string(), false, state).await?
+        }
+        CtlAction::Set { serve_dir, bind_ip } => {
+            if serve_dir.is_none() && bind_ip.is_none() {
+                return Err(QuickServeError::validation("Nothing to change, give --serve-dir or --bind-ip"));
+            }
+            let body = json!({ "path": serve_dir, "bind_ip": bind_ip });
+            endpoint.call(Method::POST, "/api/settings", Some(body)).await?
+        }
+        CtlAction::Stats => {
+            let stats = endpoint.call(Method::GET, "/api/stats", None).await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&stats).unwrap_or_default());
+            } else {
+                for line in stats["summary"].as_array().into_iter().flatten() {
+                    println!("{}", line.as_str().unwrap_or_default());
+                }
+            }
+            return Ok(());
+        }
+        CtlAction::Logs { follow } => return endpoint.logs(*follow, args.json).await,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&state).unwrap_or_default());
+    } else {
+        for line in state_lines(&state) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Runs a `ctl` command against a running instance, printing what it replies
+///
+/// # Returns
+/// The exit status: 0 on success, 1 if the instance could not be reached or refused the command
+pub async fn run_ctl(args: &CtlArgs) -> i32 {
+    match ctl(args).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(socket: Option<&str>, url: Option<&str>) -> CtlArgs {
+        CtlArgs {
+            socket: socket.map(String::from),
+            url: url.map(String::from),
+            token: Some("s3cr3t".into()),
+            json: false,
+            action: CtlAction::Status,
+        }
+    }
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(Endpoint::from_args(&args(Some("/run/qs.sock"), None)).unwrap(), Endpoint::Socket("/run/qs.sock".into()));
+        assert_eq!(Endpoint::from_args(&args(None, Some("http://127.0.0.1:8081/"))).unwrap(),
+            Endpoint::Web { authority: "127.0.0.1:8081".into(), token: Some("s3cr3t".into()) });
+        assert_eq!(Endpoint::from_args(&args(None, Some("lab:8081"))).unwrap().to_string(), "http://lab:8081");
+        assert!(Endpoint::from_args(&args(None, Some("https://lab:8081"))).is_err());
+        assert!(Endpoint::from_args(&args(None, None)).is_err());
+        assert!(Endpoint::from_args(&args(Some("/run/qs.sock"), Some("lab:8081"))).is_err());
+    }
+
+    #[test]
+    fn test_state_lines() {
+        let state = json!({
+            "bind_ip": "0.0.0.0",
+            "path": "/srv",
+            "servers": [
+                { "protocol": "http", "port": 8080, "status": "listening", "addresses": ["0.0.0.0:8080"], "error": null,
+                  "requests": 3, "errors": 1, "downloads": 2, "bytes_sent": 2048, "bytes_received": 0 },
+                { "protocol": "tftp", "port": 69, "status": "failed", "addresses": [], "error": "Permission denied",
+                  "requests": 0, "errors": 0, "downloads": 0, "bytes_sent": 0, "bytes_received": 0 },
+                { "protocol": "ftp", "port": 2121, "status": "idle", "addresses": [], "error": null,
+                  "requests": 0, "errors": 0, "downloads": 0, "bytes_sent": 0, "bytes_received": 0 },
+            ],
+            "transfers": [
+                { "protocol": "http", "client": "10.0.0.2:5000", "path": "fw/a.bin", "direction": "download",
+                  "size": 100, "bytes": 50, "rate": 25, "progress": 0.5 },
+            ],
+        });
+        assert_eq!(state_lines(&state), [
+            "Directory: /srv, bind IP: 0.0.0.0",
+            "HTTP  listening 0.0.0.0:8080, 3 requests, 2 downloads, 1 errors, 2.0 KiB sent, 0 B received",
+            "TFTP  failed    Permission denied",
+            "FTP   idle      port 2121",
+            "  HTTP download 10.0.0.2:5000 fw/a.bin 50% 50 B at 25 B/s",
+        ]);
+
+        let entry = json!({ "id": 1, "level": "INFO", "protocol": "tftp", "message": "Sent a.bin" });
+        assert_eq!(log_line(&entry), "[INFO] tftp: Sent a.bin");
+    }
+}
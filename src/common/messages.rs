@@ -3,27 +3,70 @@
 #![allow(dead_code)]
 
 use crate::servers::server::Protocol;
+use crate::Config;
+use crate::servers::vfs::Mount;
+use crate::utils::access::AccessRules;
+use crate::utils::validation::SymlinkPolicy;
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 
+/// What a [`CommandMsg`] asks of its protocol's server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ServerAction {
+    /// Start the server, unless already running
+    Start,
+    /// Stop the server, letting the transfers going on complete within its grace period
+    #[default]
+    Stop,
+    /// Stop the server if running, then start it again with the settings given
+    Restart,
+    /// Hand the settings to the running server, without restarting it
+    Reconfigure,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct CommandMsg {
-    pub start: bool, 
+    pub action: ServerAction,
     pub port: u16,
     // pub protocol: String,
     pub protocol: Protocol,
     pub bind_ip: String,
     pub path: String,
+    /// Directories and files grafted into the served tree
+    pub mounts: Vec<Mount>,
+    /// How links found in the served tree are handled
+    pub symlinks: SymlinkPolicy,
+    /// Rules deciding what may be served
+    pub access: AccessRules,
 }
 
 impl CommandMsg {
     pub fn new(prot: &Protocol) -> Self {
         Self {
-            start: false, 
+            action: ServerAction::Stop,
             port: prot.get_default_port(),
             protocol: prot.clone(),
             ..Default::default()
         }
     }
+
+    /// Builds the command for a protocol's server out of the settings,
+    /// starting it if enabled and stopping it otherwise
+    ///
+    /// # Returns
+    /// `None` for protocols without settings of their own, i.e. custom ones
+    pub fn from_config(config: &Config, prot: &Protocol) -> Option<Self> {
+        let (start, port) = config.server(prot)?;
+        Some(Self {
+            action: if start { ServerAction::Start } else { ServerAction::Stop },
+            port,
+            protocol: prot.clone(),
+            bind_ip: config.bind_ip_for(prot).to_string(),
+            path: config.serve_dir_for(prot).to_string(),
+            mounts: config.mounts.clone(),
+            symlinks: config.symlinks,
+            access: config.access.clone(),
+        })
+    }
 }
 
 // Define a struct to hold both the sender and receiver
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, error, info};
+use prometheus::{Encoder, TextEncoder};
+use tokio::net::TcpListener;
+
+use crate::servers::{Protocol, ServerStatus, PROTOCOL_LIST};
+use crate::utils::{net, validation};
+use crate::{Config, QuickServeError, QuickServeResult, Stats};
+
+/// Appends a metric family, with one sample per protocol
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(&Protocol, u64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (protocol, value) in samples {
+        let _ = writeln!(out, "{}{{protocol=\"{}\"}} {}", name, protocol.to_string(), value);
+    }
+}
+
+/// Renders the metrics in the Prometheus text format
+///
+/// Along with the counters of each protocol, includes the metrics libunftp
+/// collects for FTP (the `ftp_*` families).
+pub fn render(stats: &Stats) -> String {
+    let counters = stats.snapshot();
+    let mut active: HashMap<Protocol, u64> = HashMap::new();
+    for transfer in stats.active() {
+        *active.entry(transfer.protocol).or_default() += 1;
+    }
+
+    let samples = |value: &dyn Fn(&Protocol) -> u64| -> Vec<(&Protocol, u64)> {
+        PROTOCOL_LIST.iter().map(|p| (*p, value(p))).collect()
+    };
+    let counter = |f: fn(&crate::ProtocolStats) -> u64| {
+        samples(&|p| counters.get(p).map(f).unwrap_or_default())
+    };
+
+    let mut out = String::new();
+    family(&mut out, "quick_serve_up", "gauge", "Whether the server is listening",
+        &samples(&|p| matches!(stats.status(p), ServerStatus::Listening(_)) as u64));
+    family(&mut out, "quick_serve_requests_total", "counter", "Requests received",
+        &counter(|s| s.requests));
+    family(&mut out, "quick_serve_errors_total", "counter", "Requests that could not be served, and transfers interrupted",
+        &counter(|s| s.errors));
+    family(&mut out, "quick_serve_downloads_total", "counter", "Files fully downloaded",
+        &counter(|s| s.transfers));
+    family(&mut out, "quick_serve_sent_bytes_total", "counter", "Bytes sent to clients",
+        &counter(|s| s.bytes_sent));
+    family(&mut out, "quick_serve_received_bytes_total", "counter", "Bytes received from clients",
+        &counter(|s| s.bytes_received));
+    family(&mut out, "quick_serve_active_transfers", "gauge", "Transfers going on",
+        &samples(&|p| active.get(p).copied().unwrap_or_default()));
+
+    if let Some(pool) = stats.dhcp_pool() {
+        let _ = writeln!(out, "# HELP quick_serve_dhcp_pool_size Addresses in the DHCP pool");
+        let _ = writeln!(out, "# TYPE quick_serve_dhcp_pool_size gauge");
+        let _ = writeln!(out, "quick_serve_dhcp_pool_size {}", pool.size);
+        let _ = writeln!(out, "# HELP quick_serve_dhcp_pool_leased Addresses of the DHCP pool currently leased");
+        let _ = writeln!(out, "# TYPE quick_serve_dhcp_pool_leased gauge");
+        let _ = writeln!(out, "quick_serve_dhcp_pool_leased {}", pool.leased);
+    }
+
+    // Registered by libunftp in the default registry
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode FTP metrics: {}", e);
+    }
+    out.push_str(&String::from_utf8_lossy(&buffer));
+    out
+}
+
+async fn receive_request<B>(req: Request<B>, stats: Stats) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if req.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::new(Bytes::from("Metrics are served on /metrics")))
+            .unwrap());
+    }
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Full::new(Bytes::from(render(&stats))))
+        .unwrap())
+}
+
+async fn accept_loop(listener: TcpListener, stats: Stats) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                debug!("New metrics connection from {}", addr);
+                let stats = stats.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service_fn(move |req| receive_request(req, stats.clone())))
+                        .await
+                    {
+                        error!("Error serving metrics to {}: {:?}", addr, err);
+                    }
+                });
+            }
+            Err(e) => error!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+/// Serves the metrics on `/metrics`, if enabled, for as long as the process runs
+///
+/// Listens on the global bind addresses, on the `metrics` port.
+///
+/// # Returns
+/// * `Ok(Vec<SocketAddr>)` - The addresses listened on, none if disabled
+/// * `Err(QuickServeError)` - If an address cannot be bound
+pub fn start_metrics(config: &Config, stats: &Stats) -> QuickServeResult<Vec<SocketAddr>> {
+    let Some(port) = config.metrics else { return Ok(Vec::new()) };
+
+    // Bind all addresses first, so that none is served if any fails
+    let mut listeners = Vec::new();
+    for ip in validation::parse_bind_addresses(&config.bind_ip, port)? {
+        let addr = SocketAddr::new(ip, port);
+        let listener = net::bind_tcp(addr)
+            .and_then(TcpListener::from_std)
+            .map_err(|e| QuickServeError::Network(format!("Failed to bind metrics endpoint to {}: {}", addr, e)))?;
+        listeners.push(listener);
+    }
+
+    let mut addrs = Vec::new();
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        info!("Serving metrics on http://{}/metrics", addr);
+        addrs.push(addr);
+        tokio::spawn(accept_loop(listener, stats.clone()));
+    }
+    Ok(addrs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, PoolUsage};
+
+    #[test]
+    fn test_render() {
+        let stats = Stats::default();
+        stats.set_status(&Protocol::Http, ServerStatus::Listening(vec!["127.0.0.1:8080".parse().unwrap()]));
+        stats.request(&Protocol::Http);
+        let transfer = stats.begin(&Protocol::Http, None, "a.bin", Direction::Download, None);
+        transfer.progress(42);
+        stats.set_dhcp_pool(PoolUsage { leased: 3, size: 10 });
+
+        let text = render(&stats);
+        for line in [
+            "# TYPE quick_serve_up gauge",
+            "quick_serve_up{protocol=\"http\"} 1",
+            "quick_serve_up{protocol=\"tftp\"} 0",
+            "quick_serve_requests_total{protocol=\"http\"} 1",
+            "quick_serve_sent_bytes_total{protocol=\"http\"} 42",
+            "quick_serve_active_transfers{protocol=\"http\"} 1",
+            "quick_serve_downloads_total{protocol=\"ftp\"} 0",
+            "quick_serve_dhcp_pool_leased 3",
+            "quick_serve_dhcp_pool_size 10",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {} in:\n{}", line, text);
+        }
+
+        transfer.complete();
+        let text = render(&stats);
+        assert!(text.contains("quick_serve_downloads_total{protocol=\"http\"} 1"));
+        assert!(text.contains("quick_serve_active_transfers{protocol=\"http\"} 0"));
+    }
+}
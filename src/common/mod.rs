@@ -1,10 +1,21 @@
+pub use admin::*;
 pub use args::*;
+pub use config::*;
+pub use ctl::*;
 pub use errors::*;
 pub use messages::*;
+pub use metrics::*;
+pub use stats::*;
 pub use utils::*;
 
 // Import and re-export the submodule files.
+pub mod access_log;
+pub mod admin;
 pub mod args;
+pub mod config;
+pub mod ctl;
 pub mod errors;
 pub mod messages;
+pub mod metrics;
+pub mod stats;
 pub mod utils;
@@ -0,0 +1,547 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::servers::{Protocol, ServerStatus};
+
+/// Counters kept for each protocol
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProtocolStats {
+    /// Number of requests received
+    pub requests: u64,
+    /// Number of bytes sent to clients
+    pub bytes_sent: u64,
+    /// Number of bytes received from clients
+    pub bytes_received: u64,
+    /// Number of requests that could not be served
+    pub errors: u64,
+    /// Number of files fully downloaded
+    pub transfers: u64,
+}
+
+/// Direction of a transfer, seen from the server
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// File sent to a client
+    Download,
+    /// File received from a client
+    Upload,
+}
+
+/// Where a transfer stands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferState {
+    /// Still going on
+    Active,
+    /// The whole file went through
+    Completed,
+    /// Interrupted before the end
+    Failed,
+}
+
+/// A file sent to, or received from, a client
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    pub protocol: Protocol,
+    /// Address of the client, if known to the backend. FTP only tells the IP, the port being 0
+    pub client: Option<SocketAddr>,
+    /// Path of the file within the served tree
+    pub path: String,
+    pub direction: Direction,
+    /// Number of bytes expected, if known beforehand
+    pub size: Option<u64>,
+    /// Number of bytes transferred so far
+    pub bytes: u64,
+    pub started: Instant,
+    /// When it completed or failed
+    pub finished: Option<Instant>,
+    pub state: TransferState,
+}
+
+impl Transfer {
+    /// The client, as shown to the user: its IP when the port is unknown, `-` if unknown
+    pub fn client_label(&self) -> String {
+        match self.client {
+            Some(client) if client.port() == 0 => client.ip().to_string(),
+            Some(client) => client.to_string(),
+            None => "-".into(),
+        }
+    }
+
+    /// Time spent so far, or until finished
+    pub fn elapsed(&self) -> Duration {
+        self.finished.unwrap_or_else(Instant::now) - self.started
+    }
+
+    /// Average throughput, in bytes per second
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed().as_secs_f64();
+        if secs > 0.0 { self.bytes as f64 / secs } else { 0.0 }
+    }
+
+    /// Share of the file transferred so far (0 to 1), if its size is known
+    pub fn progress(&self) -> Option<f32> {
+        self.size.filter(|size| *size > 0)
+            .map(|size| (self.bytes as f64 / size as f64).min(1.0) as f32)
+    }
+}
+
+/// Totals kept for each client
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Number of transfers that went through
+    pub completed: u64,
+    /// Number of transfers interrupted
+    pub failed: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Usage of the DHCP address pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolUsage {
+    /// Addresses currently leased
+    pub leased: u64,
+    /// Addresses in the pool
+    pub size: u64,
+}
+
+/// An address leased by the DHCP server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DhcpLease {
+    pub ip: Ipv4Addr,
+    /// Hardware address of the client
+    pub mac: [u8; 6],
+    pub expiry: Instant,
+}
+
+/// Number of finished transfers remembered, the oldest being dropped first
+const HISTORY: usize = 100;
+
+/// Transfers going on and done, with their totals per client and file
+#[derive(Debug, Default)]
+struct Transfers {
+    next_id: u64,
+    active: BTreeMap<u64, Transfer>,
+    finished: VecDeque<Transfer>,
+    clients: HashMap<IpAddr, ClientStats>,
+    /// Number of complete downloads of each file, by path within the served tree
+    files: HashMap<String, u64>,
+}
+
+/// Follows a transfer on behalf of a backend
+///
+/// Dropping it before calling [`TransferHandle::complete`] records the
+/// transfer as failed, e.g. when the client goes away midway.
+#[derive(Debug)]
+pub struct TransferHandle {
+    stats: Stats,
+    id: u64,
+    protocol: Protocol,
+    client: Option<IpAddr>,
+    direction: Direction,
+    done: bool,
+}
+
+impl TransferHandle {
+    /// Records bytes sent or received
+    pub fn progress(&self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        if let Ok(mut transfers) = self.stats.transfers.lock() {
+            if let Some(transfer) = transfers.active.get_mut(&self.id) {
+                transfer.bytes += bytes;
+            }
+            if let Some(client) = self.client {
+                let totals = transfers.clients.entry(client).or_default();
+                match self.direction {
+                    Direction::Download => totals.bytes_sent += bytes,
+                    Direction::Upload => totals.bytes_received += bytes,
+                }
+            }
+        }
+        self.stats.update(&self.protocol, |s| match self.direction {
+            Direction::Download => s.bytes_sent += bytes,
+            Direction::Upload => s.bytes_received += bytes,
+        });
+    }
+
+    /// Returns the transfer as it stands, unless already finished
+    pub fn snapshot(&self) -> Option<Transfer> {
+        self.stats.transfers.lock().ok()?.active.get(&self.id).cloned()
+    }
+
+    /// Records the whole file as transferred
+    pub fn complete(mut self) {
+        self.done = true;
+        self.finish(TransferState::Completed);
+    }
+
+    fn finish(&self, state: TransferState) {
+        let mut download = None;
+        if let Ok(mut transfers) = self.stats.transfers.lock() {
+            let Some(mut transfer) = transfers.active.remove(&self.id) else { return };
+            transfer.state = state;
+            transfer.finished = Some(Instant::now());
+
+            if let Some(client) = self.client {
+                let totals = transfers.clients.entry(client).or_default();
+                match state {
+                    TransferState::Completed => totals.completed += 1,
+                    _ => totals.failed += 1,
+                }
+            }
+            if state == TransferState::Completed && self.direction == Direction::Download {
+                *transfers.files.entry(transfer.path.clone()).or_default() += 1;
+                download = Some(transfer.path.clone());
+            }
+
+            transfers.finished.push_back(transfer);
+            if transfers.finished.len() > HISTORY {
+                transfers.finished.pop_front();
+            }
+        }
+
+        match state {
+            TransferState::Completed if download.is_some() => self.stats.update(&self.protocol, |s| s.transfers += 1),
+            TransferState::Failed => self.stats.update(&self.protocol, |s| s.errors += 1),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for TransferHandle {
+    fn drop(&mut self) {
+        if !self.done {
+            self.finish(TransferState::Failed);
+        }
+    }
+}
+
+/// Formats a hardware address, e.g. `52:54:00:12:34:56`
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// Formats a number of bytes for humans, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Statistics and state shared by all servers of an instance
+///
+/// Cheap to clone: all clones update the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    inner: Arc<Mutex<HashMap<Protocol, ProtocolStats>>>,
+    status: Arc<Mutex<HashMap<Protocol, ServerStatus>>>,
+    transfers: Arc<Mutex<Transfers>>,
+    dhcp_pool: Arc<Mutex<Option<PoolUsage>>>,
+    dhcp_leases: Arc<Mutex<Vec<DhcpLease>>>,
+}
+
+impl Stats {
+    fn update(&self, protocol: &Protocol, f: impl FnOnce(&mut ProtocolStats)) {
+        if let Ok(mut inner) = self.inner.lock() {
+            f(inner.entry(protocol.clone()).or_default());
+        }
+    }
+
+    /// Records a request received by a server
+    pub fn request(&self, protocol: &Protocol) {
+        self.update(protocol, |s| s.requests += 1);
+    }
+
+    /// Records bytes sent to a client
+    pub fn sent(&self, protocol: &Protocol, bytes: u64) {
+        self.update(protocol, |s| s.bytes_sent += bytes);
+    }
+
+    /// Records a request that could not be served
+    pub fn error(&self, protocol: &Protocol) {
+        self.update(protocol, |s| s.errors += 1);
+    }
+
+    /// Records the start of a transfer, to be followed with the returned handle
+    ///
+    /// # Arguments
+    /// * `protocol` - The protocol used
+    /// * `client` - The client's address, if known
+    /// * `path` - The path of the file within the served tree
+    /// * `direction` - Whether the file is sent or received
+    /// * `size` - The number of bytes expected, if known
+    pub fn begin(&self, protocol: &Protocol, client: Option<SocketAddr>, path: &str, direction: Direction, size: Option<u64>) -> TransferHandle {
+        let mut id = 0;
+        if let Ok(mut transfers) = self.transfers.lock() {
+            id = transfers.next_id;
+            transfers.next_id += 1;
+            transfers.active.insert(id, Transfer {
+                protocol: protocol.clone(),
+                client,
+                path: path.trim_start_matches('/').to_string(),
+                direction,
+                size,
+                bytes: 0,
+                started: Instant::now(),
+                finished: None,
+                state: TransferState::Active,
+            });
+        }
+        TransferHandle {
+            stats: self.clone(),
+            id,
+            protocol: protocol.clone(),
+            client: client.map(|c| c.ip()),
+            direction,
+            done: false,
+        }
+    }
+
+    /// Returns the transfers going on, oldest first
+    pub fn active(&self) -> Vec<Transfer> {
+        self.transfers.lock()
+            .map(|transfers| transfers.active.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the last finished transfers, oldest first
+    pub fn finished(&self) -> Vec<Transfer> {
+        self.transfers.lock()
+            .map(|transfers| transfers.finished.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the totals of each client
+    pub fn clients(&self) -> HashMap<IpAddr, ClientStats> {
+        self.transfers.lock()
+            .map(|transfers| transfers.clients.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of files fully downloaded, over all protocols
+    pub fn transfers(&self) -> u64 {
+        self.snapshot().values().map(|s| s.transfers).sum()
+    }
+
+    /// Returns how many times a file was fully downloaded, over all protocols
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file within the served tree
+    pub fn downloads(&self, path: &str) -> u64 {
+        self.transfers.lock()
+            .map(|transfers| transfers.files.get(path.trim_start_matches('/')).copied().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Describes what was served, per protocol, client and file, one line each
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut protocols: Vec<_> = self.snapshot().into_iter().collect();
+        protocols.sort_by(|(a, _), (b, _)| a.to_string().cmp(b.to_string()));
+        for (protocol, s) in protocols {
+            lines.push(format!("{}: {} request(s), {} download(s), {} error(s), {} sent, {} received",
+                protocol.to_string().to_uppercase(), s.requests, s.transfers, s.errors,
+                format_bytes(s.bytes_sent), format_bytes(s.bytes_received)));
+        }
+
+        let mut clients: Vec<_> = self.clients().into_iter().collect();
+        clients.sort_by_key(|(ip, _)| *ip);
+        for (ip, c) in clients {
+            lines.push(format!("Client {}: {} completed, {} failed, {} sent, {} received",
+                ip, c.completed, c.failed, format_bytes(c.bytes_sent), format_bytes(c.bytes_received)));
+        }
+
+        let mut files: Vec<_> = self.transfers.lock()
+            .map(|transfers| transfers.files.clone().into_iter().collect())
+            .unwrap_or_default();
+        files.sort();
+        for (path, count) in files {
+            lines.push(format!("File {}: downloaded {} time(s)", path, count));
+        }
+        lines
+    }
+
+    /// Returns a copy of the counters of a single protocol
+    pub fn protocol(&self, protocol: &Protocol) -> ProtocolStats {
+        self.inner.lock()
+            .map(|inner| inner.get(protocol).cloned().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Returns a copy of all counters collected so far
+    pub fn snapshot(&self) -> HashMap<Protocol, ProtocolStats> {
+        self.inner.lock().map(|inner| inner.clone()).unwrap_or_default()
+    }
+
+    /// Records the usage of the DHCP address pool
+    pub fn set_dhcp_pool(&self, usage: PoolUsage) {
+        if let Ok(mut pool) = self.dhcp_pool.lock() {
+            *pool = Some(usage);
+        }
+    }
+
+    /// Returns the usage of the DHCP address pool, once a DHCP server was started
+    pub fn dhcp_pool(&self) -> Option<PoolUsage> {
+        self.dhcp_pool.lock().ok().and_then(|pool| *pool)
+    }
+
+    /// Records the addresses currently leased by the DHCP server
+    pub fn set_dhcp_leases(&self, leases: Vec<DhcpLease>) {
+        if let Ok(mut current) = self.dhcp_leases.lock() {
+            *current = leases;
+        }
+    }
+
+    /// Returns the addresses currently leased by the DHCP server
+    pub fn dhcp_leases(&self) -> Vec<DhcpLease> {
+        self.dhcp_leases.lock().map(|leases| leases.clone()).unwrap_or_default()
+    }
+
+    /// Records the lifecycle state of a protocol's server
+    pub fn set_status(&self, protocol: &Protocol, status: ServerStatus) {
+        if let Ok(mut map) = self.status.lock() {
+            map.insert(protocol.clone(), status);
+        }
+    }
+
+    /// Returns the last known lifecycle state of a protocol's server
+    pub fn status(&self, protocol: &Protocol) -> ServerStatus {
+        self.status.lock()
+            .map(|map| map.get(protocol).cloned().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Returns the (first) address a protocol's server is listening on, if running
+    pub fn bound_addr(&self, protocol: &Protocol) -> Option<SocketAddr> {
+        self.bound_addrs(protocol).first().copied()
+    }
+
+    /// Returns all addresses a protocol's server is listening on, if running
+    pub fn bound_addrs(&self, protocol: &Protocol) -> Vec<SocketAddr> {
+        match self.status(protocol) {
+            ServerStatus::Listening(addrs) => addrs,
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_per_protocol() {
+        let stats = Stats::default();
+        stats.request(&Protocol::Http);
+        stats.request(&Protocol::Http);
+        stats.sent(&Protocol::Http, 100);
+        stats.error(&Protocol::Tftp);
+
+        let http = stats.protocol(&Protocol::Http);
+        assert_eq!(http, ProtocolStats { requests: 2, bytes_sent: 100, errors: 0, ..Default::default() });
+
+        let tftp = stats.protocol(&Protocol::Tftp);
+        assert_eq!(tftp, ProtocolStats { errors: 1, ..Default::default() });
+
+        assert_eq!(stats.protocol(&Protocol::Ftp), ProtocolStats::default());
+    }
+
+    #[test]
+    fn test_completed_transfers() {
+        let stats = Stats::default();
+        let client: SocketAddr = "192.168.1.10:40000".parse().unwrap();
+        stats.begin(&Protocol::Http, Some(client), "fw/a.bin", Direction::Download, None).complete();
+        stats.begin(&Protocol::Tftp, None, "/fw/a.bin", Direction::Download, None).complete();
+        stats.begin(&Protocol::Ftp, None, "b.bin", Direction::Download, None).complete();
+        stats.begin(&Protocol::Ftp, None, "up.bin", Direction::Upload, None).complete();
+
+        assert_eq!(stats.transfers(), 3, "uploads are not downloads");
+        assert_eq!(stats.protocol(&Protocol::Http).transfers, 1);
+        assert_eq!(stats.downloads("fw/a.bin"), 2, "leading slashes are ignored");
+        assert_eq!(stats.downloads("/b.bin"), 1);
+        assert_eq!(stats.downloads("c.bin"), 0);
+        assert_eq!(stats.clients()[&client.ip()].completed, 1);
+    }
+
+    #[test]
+    fn test_transfer_progress_and_failure() {
+        let stats = Stats::default();
+        let client: SocketAddr = "192.168.1.10:40000".parse().unwrap();
+
+        let download = stats.begin(&Protocol::Tftp, Some(client), "/a.bin", Direction::Download, Some(400));
+        download.progress(100);
+        let active = stats.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!((active[0].path.as_str(), active[0].bytes, active[0].state), ("a.bin", 100, TransferState::Active));
+        assert_eq!(active[0].progress(), Some(0.25));
+
+        let upload = stats.begin(&Protocol::Ftp, Some(client), "b.bin", Direction::Upload, None);
+        upload.progress(50);
+        upload.complete();
+
+        // Client gone midway
+        drop(download);
+        assert!(stats.active().is_empty());
+        let states: Vec<_> = stats.finished().iter().map(|t| (t.path.clone(), t.state)).collect();
+        assert_eq!(states, [("b.bin".to_string(), TransferState::Completed), ("a.bin".to_string(), TransferState::Failed)]);
+
+        assert_eq!(stats.protocol(&Protocol::Tftp), ProtocolStats { bytes_sent: 100, errors: 1, ..Default::default() });
+        assert_eq!(stats.protocol(&Protocol::Ftp).bytes_received, 50);
+        assert_eq!(stats.clients()[&client.ip()], ClientStats { completed: 1, failed: 1, bytes_sent: 100, bytes_received: 50 });
+        assert_eq!(stats.transfers(), 0);
+    }
+
+    #[test]
+    fn test_summary() {
+        let stats = Stats::default();
+        stats.request(&Protocol::Http);
+        let transfer = stats.begin(&Protocol::Http, Some("10.0.0.2:1234".parse().unwrap()), "a.bin", Direction::Download, None);
+        transfer.progress(1536);
+        transfer.complete();
+
+        assert_eq!(stats.summary(), [
+            "HTTP: 1 request(s), 1 download(s), 0 error(s), 1.5 KiB sent, 0 B received",
+            "Client 10.0.0.2: 1 completed, 0 failed, 1.5 KiB sent, 0 B received",
+            "File a.bin: downloaded 1 time(s)",
+        ]);
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_status_tracking() {
+        let stats = Stats::default();
+        assert_eq!(stats.status(&Protocol::Http), ServerStatus::Idle);
+        assert_eq!(stats.bound_addr(&Protocol::Http), None);
+
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let addr6: SocketAddr = "[::1]:40000".parse().unwrap();
+        stats.set_status(&Protocol::Http, ServerStatus::Listening(vec![addr, addr6]));
+        assert_eq!(stats.bound_addr(&Protocol::Http), Some(addr));
+        assert_eq!(stats.bound_addrs(&Protocol::Http), vec![addr, addr6]);
+
+        stats.set_status(&Protocol::Http, ServerStatus::Stopped);
+        assert_eq!(stats.bound_addr(&Protocol::Http), None);
+    }
+
+    #[test]
+    fn test_clones_share_counters() {
+        let stats = Stats::default();
+        let clone = stats.clone();
+        clone.request(&Protocol::Ftp);
+
+        assert_eq!(stats.snapshot().get(&Protocol::Ftp).map(|s| s.requests), Some(1));
+    }
+}
@@ -1,61 +1,70 @@
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use log::info;
 use log::warn;
 use tokio::sync::broadcast::Sender;
 
-use crate::{CommandMsg, PROTOCOL_LIST};
+use crate::{stop_servers, CommandMsg, Protocol, Stats, PROTOCOL_LIST};
+
+/// Exit status when a second Ctrl+C cuts the shutdown short (128 + SIGINT)
+pub const EXIT_INTERRUPTED: i32 = 130;
 
 /// Sets up the Ctrl+C signal handler for graceful shutdown
 ///
-/// When Ctrl+C is received, sends stop messages to all running servers
-/// and waits for them to shut down gracefully before exiting.
+/// When Ctrl+C is received, stops all running servers and waits for them to
+/// be done with the transfers going on, then prints what was served before
+/// exiting. A second Ctrl+C exits right away.
 ///
 /// # Arguments
 /// * `sender` - The broadcast sender to send stop commands to all servers
-pub fn setup_ctrlc_handler(sender: Sender<CommandMsg>) {
+/// * `stats` - Statistics shared by all servers, summarized on exit
+/// * `grace_period` - The longest time the servers give transfers to complete
+pub fn setup_ctrlc_handler(sender: Sender<CommandMsg>, stats: Stats, grace_period: Duration) {
     ////////////////////////////////////////////////////////////////////////
     // Ctrl+c handler - gracefully stop all servers before exiting
     ////////////////////////////////////////////////////////////////////////
+    let stopping = AtomicBool::new(false);
     tokio::spawn(async move {
         ctrlc::set_handler(move || {
-            warn!("Ctrl+C received. Stopping all servers and exiting...");
-            
-            // Send stop messages to all servers
-            for protocol in PROTOCOL_LIST {
-                let stop_msg = CommandMsg {
-                    start: false,
-                    protocol: protocol.clone(),
-                    ..Default::default()
-                };
-                
-                // Send twice to ensure both the server task and runner exit
-                let _ = sender.send(stop_msg.clone());
-                let _ = sender.send(stop_msg);
+            if stopping.swap(true, Ordering::SeqCst) {
+                warn!("Ctrl+C received again. Exiting now");
+                exit(EXIT_INTERRUPTED);
             }
-            
-            // Give servers a moment to shut down gracefully
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            
-            info!("All servers stopped. Exiting.");
-            exit(0);
+            warn!("Ctrl+C received. Stopping all servers and exiting (Ctrl+C again to exit now)...");
+
+            // Stopped from another thread, for the handler to catch a second Ctrl+C meanwhile
+            let (sender, stats) = (sender.clone(), stats.clone());
+            std::thread::spawn(move || {
+                let protocols: Vec<Protocol> = PROTOCOL_LIST.iter().map(|p| (*p).clone()).collect();
+                stop_servers(&sender, &stats, &protocols, grace_period);
+
+                for line in stats.summary() {
+                    info!("{}", line);
+                }
+                info!("All servers stopped. Exiting.");
+                exit(0);
+            });
         }).expect("Error setting Ctrl+C handler");
         info!("Press Ctrl+C to exit.");
     });
 }
 
+/// Compares two secrets, taking the same time wherever they differ
+pub fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 /// Helper function for testing - sends stop messages without exiting
 #[cfg(test)]
 pub fn send_shutdown_signals(sender: &Sender<CommandMsg>) {
     for protocol in PROTOCOL_LIST {
         let stop_msg = CommandMsg {
-            start: false,
+            action: crate::ServerAction::Stop,
             protocol: protocol.clone(),
             ..Default::default()
         };
-        
-        // Send twice to ensure both the server task and runner exit
-        let _ = sender.send(stop_msg.clone());
         let _ = sender.send(stop_msg);
     }
 }
@@ -63,9 +72,16 @@ pub fn send_shutdown_signals(sender: &Sender<CommandMsg>) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::DefaultChannel;
+    use crate::{DefaultChannel, ServerAction};
     use tokio::time::{timeout, Duration};
 
+    #[test]
+    fn test_same_secret() {
+        assert!(same_secret(b"secret", b"secret"));
+        assert!(!same_secret(b"secret", b"secreT"));
+        assert!(!same_secret(b"secret", b"secret2"));
+    }
+
     #[tokio::test]
     async fn test_shutdown_signals() {
         let channel: DefaultChannel<CommandMsg> = Default::default();
@@ -75,6 +91,7 @@ mod tests {
         
         // Collect all messages
         let mut received_messages = Vec::new();
+        // Room for more than expected, for duplicates to be caught
         let max_messages = PROTOCOL_LIST.len() * 2;
         
         for _ in 0..max_messages {
@@ -85,24 +102,24 @@ mod tests {
         }
         
         // Verify count and structure
-        assert_eq!(received_messages.len(), max_messages, 
-            "Should receive 2 stop messages per protocol");
+        assert_eq!(received_messages.len(), PROTOCOL_LIST.len(),
+            "Should receive 1 stop message per protocol");
         
         // Count per protocol and verify message structure
         let mut protocol_counts = std::collections::HashMap::new();
         
         for msg in &received_messages {
-            assert!(!msg.start, "All messages should be stop signals");
+            assert_eq!(msg.action, ServerAction::Stop, "All messages should be stop signals");
             assert!(msg.bind_ip.is_empty() && msg.path.is_empty() && msg.port == 0,
                 "Stop messages should have default values");
             
             *protocol_counts.entry(&msg.protocol).or_insert(0) += 1;
         }
         
-        // Each protocol should receive exactly 2 messages
+        // Each protocol should receive exactly 1 message
         assert_eq!(protocol_counts.len(), PROTOCOL_LIST.len());
         for count in protocol_counts.values() {
-            assert_eq!(*count, 2, "Each protocol should receive exactly 2 stop messages");
+            assert_eq!(*count, 1, "Each protocol should receive exactly 1 stop message");
         }
     }
 }
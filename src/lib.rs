@@ -0,0 +1,31 @@
+//! Quick-Serve: zero-config, multi-protocol file serving.
+//!
+//! Besides the `quick-serve` and `quick-serve-gui` binaries, the servers can be
+//! embedded in other applications (e.g. test harnesses):
+//!
+//! ``` no_run
+//! # async fn run() -> quick_serve::QuickServeResult<()> {
+//! use quick_serve::{Protocol, QuickServe};
+//!
+//! let handle = QuickServe::builder()
+//!     .dir("/srv/images")
+//!     .http(8080)
+//!     .tftp(6969)
+//!     .spawn()
+//!     .await?;
+//!
+//! println!("HTTP on {:?}", handle.bound_addr(&Protocol::Http));
+//! handle.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod builder;
+pub mod client;
+pub mod common;
+pub mod servers;
+pub mod utils;
+
+pub use builder::*;
+pub use common::*;
+pub use servers::*;
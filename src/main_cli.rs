@@ -1,32 +1,54 @@
 // #![allow(warnings)]
 
-use log::LevelFilter;
+use log::{error, LevelFilter};
 use std::process::exit;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::Sender;
 
-mod utils;
-use utils::logger::*;
-
-mod servers;
-use crate::servers::*;
-
-mod common;
-use crate::common::*;
+use quick_serve::*;
+use quick_serve::utils::logger::*;
 
 use clap::Parser;
 
+#[cfg(feature = "tui")]
+mod tui;
+
 extern crate ctrlc;
 extern crate core;
 
 #[tokio::main]
 async fn main() {
     let cli_args = Cli::parse();
+    if let Some(Command::Ctl(args)) = &cli_args.command {
+        exit(run_ctl(args).await);
+    }
+    if let Some(Command::Client(command)) = &cli_args.command {
+        exit(client::run_client(command).await);
+    }
 
     let mut log_level = LevelFilter::Info;
     if cli_args.verbose > 0 {
         log_level = LevelFilter::Debug;
     }
 
-    let logger = Box::new(MyLogger::new(log_level));
+    let mut logger = MyLogger::new(log_level)
+        .with_format(cli_args.log_format.unwrap_or_default())
+        .with_filter(cli_args.log_filter.clone().unwrap_or_default())
+        // The terminal interface shows the logs itself
+        .with_stdout(!cli_args.tui);
+    if let Some(path) = &cli_args.log_file {
+        let max_size = cli_args.log_max_size.map_or(LOG_FILE_MAX_SIZE, |mib| mib * 1024 * 1024);
+        match RotatingFile::open(std::path::Path::new(path), max_size, LOG_FILES_KEPT) {
+            Ok(file) => logger = logger.with_file(file),
+            Err(e) => {
+                eprintln!("Failed to open log file {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    let logger = Box::new(logger);
+    // Kept in memory for the web and terminal interfaces
+    let logs = logger.logs.clone();
 
     // Define the channel used to control the servers
     let channel: DefaultChannel<CommandMsg> = Default::default();
@@ -34,20 +56,62 @@ async fn main() {
     log::set_boxed_logger(logger).unwrap();
     log::set_max_level(LevelFilter::Trace); // Set the maximum log level
 
+    // Settings from the configuration file, overridden by the command line
+    let config = match Config::resolve(&cli_args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            exit(2);
+        }
+    };
+
+
+    ////////////////////////////////////////////////////////////////////////
+    let stats = Stats::default();
+    let registry = ServerRegistry::from_config(&config);
+    server_starter_receiver(&channel, &registry, &stats);
+    if let Err(e) = start_metrics(&config, &stats)
+        .and_then(|_| start_admin(&config, channel.sender.clone(), &stats, logs.clone()))
+        .and_then(|_| access_log::start(&config))
+    {
+        error!("{}", e);
+        exit(2);
+    }
+
+    // Reloaded on SIGHUP or changes to the file, whichever interface runs
+    spawn_config_reloader(&cli_args, &registry, channel.sender.clone());
 
     ////////////////////////////////////////////////////////////////////////
-    server_starter_receiver(&channel);
+    if cli_args.tui {
+        exit(run_tui(&config, channel.sender.clone(), logs, stats));
+    }
 
     ////////////////////////////////////////////////////////////////////////
-    setup_ctrlc_handler(channel.sender.clone());
+    setup_ctrlc_handler(channel.sender.clone(), stats.clone(), config.grace_period);
 
     ////////////////////////////////////////////////////////////////////////
-    server_starter_sender(&cli_args, &channel);
+    let status = server_starter_sender(&config, cli_args.ready_file.as_deref(), &ExitConditions::from_cli(&cli_args), &channel, &stats);
 
     // futures::future::join_all(spawned_runners).await;
-    exit(0);
+    exit(status);
 }
 
+/// Runs the terminal interface until the user quits, returning the exit status
+#[cfg(feature = "tui")]
+fn run_tui(config: &Config, sender: Sender<CommandMsg>, logs: Arc<Mutex<Vec<LogEntry>>>, stats: Stats) -> i32 {
+    let app = tui::app::App::new(config, sender, logs, stats.clone());
+    if let Err(e) = app.run() {
+        eprintln!("Failed to run the terminal interface: {}", e);
+        return 1;
+    }
+    for line in stats.summary() {
+        println!("{}", line);
+    }
+    0
+}
 
-
-
+#[cfg(not(feature = "tui"))]
+fn run_tui(_config: &Config, _sender: Sender<CommandMsg>, _logs: Arc<Mutex<Vec<LogEntry>>>, _stats: Stats) -> i32 {
+    eprintln!("Built without the terminal interface (tui feature)");
+    2
+}
@@ -3,17 +3,11 @@
 #![cfg_attr(not(feature = "ui"), allow(dead_code))]
 
 
-use log::LevelFilter;
+use log::{error, LevelFilter};
 use std::process::exit;
 
-mod utils;
-use utils::logger::*;
-
-mod servers;
-use crate::servers::*;
-
-mod common;
-use crate::common::*;
+use quick_serve::*;
+use quick_serve::utils::logger::*;
 
 use clap::Parser;
 
@@ -27,15 +21,34 @@ use crate::ui::window::*;
 #[tokio::main]
 async fn main() {
     let cli_args = Cli::parse();
+    if let Some(Command::Ctl(args)) = &cli_args.command {
+        exit(run_ctl(args).await);
+    }
+    if let Some(Command::Client(command)) = &cli_args.command {
+        exit(client::run_client(command).await);
+    }
 
     let mut log_level = LevelFilter::Info;
     if cli_args.verbose > 0 {
         log_level = LevelFilter::Debug;
     }
 
-    let logger = Box::new(MyLogger::new(log_level));
-    // Clone the producer, so that we can pass it to the consumer inside the UI
-    #[cfg(feature = "ui")]
+    let mut logger = MyLogger::new(log_level)
+        .with_format(cli_args.log_format.unwrap_or_default())
+        .with_filter(cli_args.log_filter.clone().unwrap_or_default());
+    if let Some(path) = &cli_args.log_file {
+        let max_size = cli_args.log_max_size.map_or(LOG_FILE_MAX_SIZE, |mib| mib * 1024 * 1024);
+        match RotatingFile::open(std::path::Path::new(path), max_size, LOG_FILES_KEPT) {
+            Ok(file) => logger = logger.with_file(file),
+            Err(e) => {
+                eprintln!("Failed to open log file {}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+    let logger = Box::new(logger);
+    // Clone the producer, so that we can pass it to the consumers inside the UI
+    // and the web interface
     let logs = logger.logs.clone();
 
     // Define the channel used to control the servers
@@ -44,18 +57,38 @@ async fn main() {
     log::set_boxed_logger(logger).unwrap();
     log::set_max_level(LevelFilter::Trace); // Set the maximum log level
 
+    // Settings from the configuration file, overridden by the command line
+    let config = match Config::resolve(&cli_args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            exit(2);
+        }
+    };
+
 
     ////////////////////////////////////////////////////////////////////////
-    server_starter_receiver(&channel);
+    let stats = Stats::default();
+    let registry = ServerRegistry::from_config(&config);
+    server_starter_receiver(&channel, &registry, &stats);
+    if let Err(e) = start_metrics(&config, &stats)
+        .and_then(|_| start_admin(&config, channel.sender.clone(), &stats, logs.clone()))
+        .and_then(|_| access_log::start(&config))
+    {
+        error!("{}", e);
+        exit(2);
+    }
 
     ////////////////////////////////////////////////////////////////////////
-    setup_ctrlc_handler(channel.sender.clone());
+    setup_ctrlc_handler(channel.sender.clone(), stats.clone(), config.grace_period);
 
     ////////////////////////////////////////////////////////////////////////
     // HEADLESS related code from here on
     ////////////////////////////////////////////////////////////////////////
     if cli_args.headless {
-        server_starter_sender(&cli_args, &channel);
+        spawn_config_reloader(&cli_args, &registry, channel.sender.clone());
+        let status = server_starter_sender(&config, cli_args.ready_file.as_deref(), &ExitConditions::from_cli(&cli_args), &channel, &stats);
+        exit(status);
     }
     ////////////////////////////////////////////////////////////////////////
     // UI related code from here on
@@ -67,6 +100,9 @@ async fn main() {
                 ..Default::default()
         };
 
+        // Kept to stop the servers once the window is closed
+        let (sender, all_stats, grace_period) = (channel.sender.clone(), stats.clone(), config.grace_period);
+
         let _ = eframe::run_native(
             "Quick-Serve",
             options,
@@ -75,11 +111,16 @@ async fn main() {
 
                 let mut ui = UI::new(cc);
                 ui.logs = logs;
+                ui.stats = stats;
+                ui.load_config(config, Config::path(&cli_args));
 
                 ui.channel.sender = channel.sender;
                 Ok(Box::new(ui))
             }),
         );
+
+        let protocols: Vec<Protocol> = PROTOCOL_LIST.iter().map(|p| (*p).clone()).collect();
+        stop_servers(&sender, &all_stats, &protocols, grace_period);
     }
 
     // futures::future::join_all(spawned_runners).await;
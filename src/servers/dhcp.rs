@@ -1,101 +1,119 @@
-use std::path::PathBuf;
-use super::Server;
-use crate::utils::validation;
-use std::net::{IpAddr, Ipv4Addr};
+use super::{reloaded_backend, Live, ProtocolServer, ServeContext, ServerConfig};
+use std::io::ErrorKind;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use crate::servers::Protocol;
+use crate::utils::net;
+use crate::{QuickServeError, QuickServeResult};
 
-use std::str::FromStr;
-use log::{debug, info, error};
+use log::{debug, info};
 
-use std::net::UdpSocket;
+use async_trait::async_trait;
 use dhcp4r::server as dhcp_server;
-use crate::servers::dhcp_server::DhcpServer;
+use crate::servers::dhcp_server::{DhcpHandler, DhcpPool};
 
-pub trait DHCPRunner {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> where Self: Sized;
-    fn runner(&self);
-}
+/// How often the blocking DHCP loop checks whether it was asked to stop
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-impl DHCPRunner for Server {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> {
-        let mut s = Server::default();
+/// DHCP specific settings
+#[derive(Clone, Debug, Default)]
+pub struct DhcpConfig {
+    pub server: ServerConfig,
+    /// Addresses and options handed out to clients
+    pub pool: DhcpPool,
+}
 
-        // Validate inputs with proper error handling
-        validation::validate_ip_port(&bind_ip, port)?;
+impl From<ServerConfig> for DhcpConfig {
+    fn from(server: ServerConfig) -> Self {
+        DhcpConfig { server, ..Default::default() }
+    }
+}
 
-        let path = validation::ensure_trailing_slash(&path);
-        s.path = Arc::new(path);
-        s.bind_address = IpAddr::from_str(&bind_ip)
-            .map_err(|e| crate::QuickServeError::validation(format!("Invalid IP address '{}': {}", bind_ip, e)))?;
-        s.port = port;
+/// Hands out IPv4 leases, backed by dhcp4r
+pub struct DhcpServer {
+    config: DhcpConfig,
+    /// The pool, replaced on reload
+    pool: Live<DhcpPool>,
+}
 
-        s.protocol = Protocol::Dhcp;
-        DHCPRunner::runner(&s);
-        Ok(s)
+impl DhcpServer {
+    pub fn new(config: DhcpConfig) -> Result<Self, crate::QuickServeError> {
+        // The bind address is announced as the server identifier, so it must
+        // be a single IPv4 one
+        match config.server.bind_addresses.as_slice() {
+            [IpAddr::V4(_)] => {}
+            [addr] => return Err(QuickServeError::validation(format!(
+                "DHCP requires an IPv4 bind address, got {}", addr))),
+            addrs => return Err(QuickServeError::validation(format!(
+                "DHCP serves a single bind address, got {}", addrs.len()))),
+        }
+        config.pool.validate()?;
+        Ok(DhcpServer { pool: Live::new(config.pool.clone()), config })
     }
+}
 
-    fn runner(&self) {
-        let mut receiver = self.sender.subscribe();
+#[async_trait]
+impl ProtocolServer for DhcpServer {
+    fn protocol(&self) -> Protocol {
+        Protocol::Dhcp
+    }
 
-        let bind_address = self.bind_address;
-        let port = self.port;
-        let ip_port = format!("{}:{}", bind_address, port);
-        let socket_bind = format!("0.0.0.0:{}", port);
+    fn config(&self) -> &ServerConfig {
+        &self.config.server
+    }
 
-        tokio::spawn(async move {
+    async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+        let [IpAddr::V4(ipv4)] = self.config.server.bind_addresses[..] else {
+            return Err(QuickServeError::validation("DHCP requires an IPv4 bind address"));
+        };
+        let socket_bind = format!("0.0.0.0:{}", self.config.server.port);
+
+        info!("Starting DHCP server on {}:{}", ipv4, self.config.server.port);
+
+        // Bound to all addresses to receive broadcasts, but only answering on
+        // the interface of the bind address
+        let socket = net::bind_udp_broadcast(ipv4, self.config.server.port)
+            .map_err(|e| QuickServeError::Network(format!("Failed to bind DHCP server to {} on the interface of {}: {}", socket_bind, ipv4, e)))?;
+        info!("DHCP server bound to {}", socket_bind);
+
+        // dhcp4r only offers a blocking loop, which returns on the first receive
+        // error. Time out periodically to get the chance to check for shutdown.
+        socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+
+        info!("DHCP server serving on {} with IP {}", socket_bind, ipv4);
+        ctx.listening(vec![socket.local_addr()?]);
+
+        let shutdown = ctx.shutdown.clone();
+        let stats = ctx.stats.clone();
+        let live = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut pool = live.get();
+            let mut handler = DhcpHandler { pool: (*pool).clone(), stats, ..Default::default() };
+            handler.report_pool();
             loop {
-                debug!("DHCP runner started... Waiting command to connect...");
-                
-                let m = match receiver.recv().await {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        error!("Failed to receive message in DHCP runner: {}", e);
-                        break;
-                    }
-                };
-                debug!("Message received");
-
-                if m.connect {
-                    info!("Starting DHCP server on {}", ip_port);
-
-                    let server = DhcpServer::default();
-
-                    // Bind socket with proper error handling
-                    let socket = match UdpSocket::bind(&socket_bind) {
-                        Ok(socket) => {
-                            info!("DHCP server bound to {}", socket_bind);
-                            socket
-                        }
-                        Err(e) => {
-                            error!("Failed to bind DHCP server to {}: {}", socket_bind, e);
-                            break;
-                        }
-                    };
-
-                    // Set broadcast with error handling
-                    if let Err(e) = socket.set_broadcast(true) {
-                        error!("Failed to set broadcast on DHCP socket: {}", e);
-                        break;
-                    }
-
-                    // Parse IPv4 address with error handling
-                    let ipv4 = match bind_address.to_string().parse::<Ipv4Addr>() {
-                        Ok(addr) => addr,
-                        Err(e) => {
-                            error!("Failed to parse IPv4 address '{}': {}", bind_address, e);
-                            break;
-                        }
-                    };
-
-                    info!("DHCP server serving on {} with IP {}", socket_bind, ipv4);
-                    dhcp_server::Server::serve(socket, ipv4, server);
-
-                    info!("DHCP server stopped");
-                    break;
+                let err = dhcp_server::Server::serve(socket.try_clone()?, ipv4, &mut handler);
+                if shutdown.is_cancelled() {
+                    debug!("DHCP server stopped");
+                    return Ok(());
+                }
+                // Leases already given go on until they expire
+                if !Arc::ptr_eq(&pool, &live.get()) {
+                    pool = live.get();
+                    handler.pool = (*pool).clone();
+                    handler.report_pool();
+                }
+                match err.kind() {
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut => continue,
+                    _ => return Err(QuickServeError::Network(format!("DHCP server error: {}", err))),
                 }
             }
-        });
+        }).await?
     }
-}
 
+    fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+        let next = reloaded_backend::<DhcpServer>(next)?;
+        self.pool.set(next.config.pool.clone());
+        Ok(())
+    }
+}
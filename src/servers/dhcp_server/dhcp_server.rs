@@ -1,5 +1,5 @@
 use dhcp4r::{options, packet, server};
-use dhcp4r::bytes_u32;
+use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 use std::net::Ipv4Addr;
@@ -7,43 +7,105 @@ use std::time::{Duration, Instant};
 use std::ops::Add;
 use log::{debug, info};
 
+use crate::servers::Protocol;
+use crate::{DhcpLease, PoolUsage, QuickServeError, QuickServeResult, Stats};
 
-// Server configuration
-const LEASE_DURATION_SECS: u32 = 7200;
-const IP_START: [u8; 4] = [172, 12, 1, 100];
-const ROUTER_IP: Ipv4Addr = Ipv4Addr::new(172, 12, 1, 254);
-const SUBNET_MASK: Ipv4Addr = Ipv4Addr::new(255, 255, 255, 0);
-const DNS_IPS: [Ipv4Addr; 2] = [
-    // Google DNS servers
-    Ipv4Addr::new(8, 8, 8, 8),
-    Ipv4Addr::new(4, 4, 4, 4),
-];
-const LEASE_NUM: u32 = 100;
 
-// Derived constants
-const IP_START_NUM: u32 = bytes_u32!(IP_START);
-
-
-
-pub struct DhcpServer {
-    pub leases: HashMap<Ipv4Addr, ([u8; 6], Instant)>,
-    pub last_lease: u32,
+/// Addresses and options handed out by the DHCP server
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DhcpPool {
+    /// First address leased
+    pub start: Ipv4Addr,
+    /// Number of addresses leased, starting from `start`
+    pub size: u32,
+    pub router: Ipv4Addr,
+    pub subnet_mask: Ipv4Addr,
+    pub dns: Vec<Ipv4Addr>,
+    /// Lease duration, in seconds
+    pub lease_secs: u32,
 }
 
-impl Default for DhcpServer {
+impl Default for DhcpPool {
     fn default() -> Self {
-        DhcpServer {
-            leases: HashMap::new(),
-            last_lease: 0,
+        DhcpPool {
+            start: Ipv4Addr::new(172, 12, 1, 100),
+            size: 100,
+            router: Ipv4Addr::new(172, 12, 1, 254),
+            subnet_mask: Ipv4Addr::new(255, 255, 255, 0),
+            dns: vec![
+                // Google DNS servers
+                Ipv4Addr::new(8, 8, 8, 8),
+                Ipv4Addr::new(4, 4, 4, 4),
+            ],
+            lease_secs: 7200,
+        }
+    }
+}
+
+impl DhcpPool {
+    /// Checks that the pool describes a usable range of addresses
+    pub fn validate(&self) -> QuickServeResult<()> {
+        if self.size == 0 {
+            return Err(QuickServeError::validation("DHCP pool size must be greater than 0"));
+        }
+        if u32::from(self.start).checked_add(self.size - 1).is_none() {
+            return Err(QuickServeError::validation(format!(
+                "DHCP pool of {} addresses starting at {} overflows the address space", self.size, self.start)));
         }
+        Ok(())
     }
+
+    /// A pool leasing the half of a subnet the server's own address is not in,
+    /// the server being the router
+    ///
+    /// # Arguments
+    /// * `ip` - The server's address in the subnet, e.g. an interface's
+    /// * `prefix_len` - Length of the subnet prefix, e.g. 24
+    ///
+    /// # Returns
+    /// `None` for subnets too small to lease anything (/31 and /32)
+    pub fn for_subnet(ip: Ipv4Addr, prefix_len: u8) -> Option<Self> {
+        if prefix_len > 30 {
+            return None;
+        }
+        let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+        let network = u32::from(ip) & mask;
+        // Leaving out the network and broadcast addresses
+        let hosts = !mask - 1;
+        let offset = u32::from(ip) - network;
+        let (first, size) = if offset <= hosts / 2 { (hosts / 2 + 1, hosts - hosts / 2) } else { (1, hosts / 2) };
+        Some(DhcpPool {
+            start: (network + first).into(),
+            size,
+            router: ip,
+            subnet_mask: mask.into(),
+            ..Default::default()
+        })
+    }
+
+    fn start_num(&self) -> u32 {
+        self.start.into()
+    }
+}
+
+
+#[derive(Default)]
+pub struct DhcpHandler {
+    pub leases: HashMap<Ipv4Addr, ([u8; 6], Instant)>,
+    pub last_lease: u32,
+    pub pool: DhcpPool,
+    pub stats: Stats,
 }
 
 
-impl server::Handler for DhcpServer {
+// Implemented on a mutable reference, so the same leases survive the
+// blocking serve loop being restarted
+impl server::Handler for &mut DhcpHandler {
     fn handle_request(&mut self, server: &server::Server, in_packet: packet::Packet) {
 
         debug!("Request received");
+        self.stats.request(&Protocol::Dhcp);
 
         match in_packet.message_type() {
             Ok(options::MessageType::Discover) => {
@@ -53,28 +115,21 @@ impl server::Handler for DhcpServer {
                 {
                     let addr = *addr;
                     if self.available(&in_packet.chaddr, &addr) {
-                        reply(server, options::MessageType::Offer, in_packet, &addr);
+                        self.reply(server, options::MessageType::Offer, in_packet, &addr);
                         return;
                     }
                 }
                 // Otherwise prefer existing (including expired if available)
                 if let Some(ip) = self.current_lease(&in_packet.chaddr) {
-                    reply(server, options::MessageType::Offer, in_packet, &ip);
+                    self.reply(server, options::MessageType::Offer, in_packet, &ip);
                     return;
                 }
                 // Otherwise choose a free ip if available
-                for _ in 0..LEASE_NUM {
-                    self.last_lease = (self.last_lease + 1) % LEASE_NUM;
-                    if self.available(
-                        &in_packet.chaddr,
-                        &((IP_START_NUM + &self.last_lease).into()),
-                    ) {
-                        reply(
-                            server,
-                            options::MessageType::Offer,
-                            in_packet,
-                            &((IP_START_NUM + &self.last_lease).into()),
-                        );
+                for _ in 0..self.pool.size {
+                    self.last_lease = (self.last_lease + 1) % self.pool.size;
+                    let candidate = (self.pool.start_num() + self.last_lease).into();
+                    if self.available(&in_packet.chaddr, &candidate) {
+                        self.reply(server, options::MessageType::Offer, in_packet, &candidate);
                         break;
                     }
                 }
@@ -96,10 +151,11 @@ impl server::Handler for DhcpServer {
                 self.leases.insert(
                     req_ip,
                     (in_packet.chaddr, Instant::now().add(
-                        Duration::new(LEASE_DURATION_SECS as u64, 0))
+                        Duration::new(self.pool.lease_secs as u64, 0))
                     ),
                 );
-                reply(server, options::MessageType::Ack, in_packet, &req_ip);
+                self.reply(server, options::MessageType::Ack, in_packet, &req_ip);
+                self.report_pool();
             }
 
             Ok(options::MessageType::Release) | Ok(options::MessageType::Decline) => {
@@ -110,6 +166,7 @@ impl server::Handler for DhcpServer {
                 if let Some(ip) = self.current_lease(&in_packet.chaddr) {
                     self.leases.remove(&ip);
                 }
+                self.report_pool();
             }
 
             // TODO - not necessary but support for dhcp4r::INFORM might be nice
@@ -118,11 +175,23 @@ impl server::Handler for DhcpServer {
     }
 }
 
-impl DhcpServer {
+impl DhcpHandler {
+    /// Publishes the addresses of the pool currently leased
+    pub fn report_pool(&self) {
+        let now = Instant::now();
+        let mut leases: Vec<DhcpLease> = self.leases.iter()
+            .filter(|(_, (_, expiry))| *expiry > now)
+            .map(|(ip, (mac, expiry))| DhcpLease { ip: *ip, mac: *mac, expiry: *expiry })
+            .collect();
+        leases.sort_by_key(|lease| lease.ip);
+        self.stats.set_dhcp_pool(PoolUsage { leased: leases.len() as u64, size: self.pool.size as u64 });
+        self.stats.set_dhcp_leases(leases);
+    }
+
     fn available(&self, chaddr: &[u8; 6], addr: &Ipv4Addr) -> bool {
         let pos: u32 = (*addr).into();
-        pos >= IP_START_NUM
-            && pos < IP_START_NUM + LEASE_NUM
+        let start = self.pool.start_num();
+        (start..=start + (self.pool.size - 1)).contains(&pos)
             && match self.leases.get(addr) {
                 Some(x) => x.0 == *chaddr || Instant::now().gt(&x.1),
                 None => true,
@@ -135,28 +204,29 @@ impl DhcpServer {
                 return Some(*i);
             }
         }
-        return None;
+        None
     }
-}
 
-fn reply(
-    s: &server::Server,
-    msg_type: options::MessageType,
-    req_packet: packet::Packet,
-    offer_ip: &Ipv4Addr,
-) {
-    let _ = s.reply(
-        msg_type,
-        vec![
-            options::DhcpOption::IpAddressLeaseTime(LEASE_DURATION_SECS),
-            options::DhcpOption::SubnetMask(SUBNET_MASK),
-            options::DhcpOption::Router(vec![ROUTER_IP]),
-            options::DhcpOption::DomainNameServer(DNS_IPS.to_vec()),
-        ],
-        *offer_ip,
-        req_packet,
-    );
-    info!("offered {:?}", offer_ip);
+    fn reply(
+        &self,
+        s: &server::Server,
+        msg_type: options::MessageType,
+        req_packet: packet::Packet,
+        offer_ip: &Ipv4Addr,
+    ) {
+        let _ = s.reply(
+            msg_type,
+            vec![
+                options::DhcpOption::IpAddressLeaseTime(self.pool.lease_secs),
+                options::DhcpOption::SubnetMask(self.pool.subnet_mask),
+                options::DhcpOption::Router(vec![self.pool.router]),
+                options::DhcpOption::DomainNameServer(self.pool.dns.clone()),
+            ],
+            *offer_ip,
+            req_packet,
+        );
+        info!(protocol = "dhcp", event = "offer", ip:% = offer_ip; "offered {:?}", offer_ip);
+    }
 }
 
 fn nak(s: &server::Server, req_packet: packet::Packet, message: &str) {
@@ -169,4 +239,65 @@ fn nak(s: &server::Server, req_packet: packet::Packet, message: &str) {
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_availability() {
+        let handler = DhcpHandler {
+            pool: DhcpPool { start: Ipv4Addr::new(10, 0, 0, 10), size: 3, ..Default::default() },
+            ..Default::default()
+        };
+        let mac = [1, 2, 3, 4, 5, 6];
+
+        assert!(!handler.available(&mac, &Ipv4Addr::new(10, 0, 0, 9)));
+        assert!(handler.available(&mac, &Ipv4Addr::new(10, 0, 0, 10)));
+        assert!(handler.available(&mac, &Ipv4Addr::new(10, 0, 0, 12)));
+        assert!(!handler.available(&mac, &Ipv4Addr::new(10, 0, 0, 13)));
+    }
 
+    #[test]
+    fn test_pool_usage() {
+        let mut handler = DhcpHandler {
+            pool: DhcpPool { size: 4, ..Default::default() },
+            ..Default::default()
+        };
+        handler.report_pool();
+        assert_eq!(handler.stats.dhcp_pool(), Some(PoolUsage { leased: 0, size: 4 }));
+
+        let now = Instant::now();
+        handler.leases.insert(Ipv4Addr::new(172, 12, 1, 100), ([1; 6], now + Duration::from_secs(60)));
+        handler.leases.insert(Ipv4Addr::new(172, 12, 1, 101), ([2; 6], now - Duration::from_secs(1)));
+        handler.report_pool();
+        assert_eq!(handler.stats.dhcp_pool(), Some(PoolUsage { leased: 1, size: 4 }), "expired leases are free");
+        let leases = handler.stats.dhcp_leases();
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].ip, Ipv4Addr::new(172, 12, 1, 100));
+        assert_eq!(crate::format_mac(&leases[0].mac), "01:01:01:01:01:01");
+    }
+
+    #[test]
+    fn test_pool_for_subnet() {
+        let pool = DhcpPool::for_subnet(Ipv4Addr::new(192, 168, 1, 1), 24).unwrap();
+        assert_eq!((pool.start, pool.size), (Ipv4Addr::new(192, 168, 1, 128), 127));
+        assert_eq!((pool.router, pool.subnet_mask), (Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(255, 255, 255, 0)));
+        assert!(pool.validate().is_ok());
+
+        // The server's address is left out, as is the broadcast one
+        let pool = DhcpPool::for_subnet(Ipv4Addr::new(10, 0, 0, 200), 24).unwrap();
+        assert_eq!((pool.start, pool.size), (Ipv4Addr::new(10, 0, 0, 1), 127));
+        let pool = DhcpPool::for_subnet(Ipv4Addr::new(10, 0, 0, 1), 30).unwrap();
+        assert_eq!((pool.start, pool.size), (Ipv4Addr::new(10, 0, 0, 2), 1));
+
+        assert!(DhcpPool::for_subnet(Ipv4Addr::new(10, 0, 0, 1), 31).is_none());
+        assert!(DhcpPool::for_subnet(Ipv4Addr::new(10, 0, 0, 1), 0).unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn test_pool_validation() {
+        assert!(DhcpPool::default().validate().is_ok());
+        assert!(DhcpPool { size: 0, ..Default::default() }.validate().is_err());
+        assert!(DhcpPool { start: Ipv4Addr::new(255, 255, 255, 250), size: 10, ..Default::default() }.validate().is_err());
+    }
+}
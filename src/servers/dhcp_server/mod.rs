@@ -1,3 +1,4 @@
 pub use dhcp_server::*;
 
+#[allow(clippy::module_inception)]
 pub mod dhcp_server;
@@ -1,107 +1,639 @@
-use std::net::IpAddr;
-use std::path::PathBuf;
-use std::str::FromStr;
-use log::{debug, info, error};
-use unftp_sbe_fs::Filesystem;
-use std::time::Duration;
-use super::Server;
-use crate::servers::Protocol;
-use crate::utils::validation;
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use log::{debug, info};
+use std::time::SystemTime;
+use super::{reloaded_backend, Live, ProtocolServer, ServeContext, ServerConfig, Vfs};
+use crate::servers::Protocol;
+use crate::utils::net;
+use crate::{same_secret, Credentials, Direction, QuickServeError, QuickServeResult, Stats, TransferHandle};
+use crate::access_log;
+use async_trait::async_trait;
+use unftp_core::auth::{self, AuthenticationError, Authenticator, Principal, UserDetail, UserDetailError, UserDetailProvider};
+use unftp_core::storage::{self, ErrorKind, Fileinfo, Metadata, Permissions, StorageBackend, FEATURE_RESTART, FEATURE_SITEMD5};
+use tokio::io::{AsyncRead, AsyncSeekExt, AsyncWrite, ReadBuf};
+use tokio::task::JoinSet;
+
+/// Times an ephemeral port is picked again, when taken before libunftp binds it
+const EPHEMERAL_ATTEMPTS: usize = 5;
+
+/// A libunftp server listening, until stopped
+type Listening = Pin<Box<dyn Future<Output = Result<(), libunftp::ServerError>> + Send>>;
+
+/// Whether libunftp failed to bind an address because it is taken
+fn in_use(err: &libunftp::ServerError) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::AddrInUse) {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+
+/// FTP specific settings
+#[derive(Clone, Debug)]
+pub struct FtpConfig {
+    pub server: ServerConfig,
+    /// Credentials required to log in. Anyone is accepted if not set
+    pub auth: Option<Credentials>,
+    /// Range of ports offered to clients for passive data connections
+    pub passive_ports: RangeInclusive<u16>,
+}
+
+impl Default for FtpConfig {
+    fn default() -> Self {
+        FtpConfig {
+            server: ServerConfig::default(),
+            auth: None,
+            passive_ports: 50000..=65535,
+        }
+    }
+}
+
+impl From<ServerConfig> for FtpConfig {
+    fn from(server: ServerConfig) -> Self {
+        FtpConfig { server, ..Default::default() }
+    }
+}
+
+/// Reader feeding a transfer's progress with whatever goes through it
+struct ProgressReader<'a, R> {
+    inner: R,
+    transfer: &'a TransferHandle,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            self.transfer.progress((buf.filled().len() - before) as u64);
+        }
+        res
+    }
+}
+
+/// Metadata of a file or directory of the served tree
+#[derive(Debug)]
+struct VfsMetadata(std::fs::Metadata);
+
+impl Metadata for VfsMetadata {
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+
+    fn is_file(&self) -> bool {
+        self.0.is_file()
+    }
+
+    fn is_symlink(&self) -> bool {
+        // Links are followed, as the served tree is checked by path
+        false
+    }
+
+    fn modified(&self) -> storage::Result<SystemTime> {
+        Ok(self.0.modified()?)
+    }
+
+    fn gid(&self) -> u32 {
+        #[cfg(unix)]
+        return std::os::unix::fs::MetadataExt::gid(&self.0);
+        #[cfg(not(unix))]
+        return 0;
+    }
+
+    fn uid(&self) -> u32 {
+        #[cfg(unix)]
+        return std::os::unix::fs::MetadataExt::uid(&self.0);
+        #[cfg(not(unix))]
+        return 0;
+    }
 
+    fn permissions(&self) -> Permissions {
+        #[cfg(unix)]
+        return Permissions(std::os::unix::fs::PermissionsExt::mode(&self.0.permissions()));
+        #[cfg(not(unix))]
+        return Permissions(0o755);
+    }
+}
 
-pub trait FTPRunner {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> where Self: Sized;
-    fn runner(&self);
+/// Storage backend serving the [`Vfs`], i.e. the root directory along with its mounts
+///
+/// Each command takes the tree current when it comes in, transfers going
+/// on with the file they opened.
+#[derive(Debug)]
+struct VfsStorage {
+    vfs: Live<Vfs>,
+    stats: Stats,
+}
+
+impl VfsStorage {
+    /// Maps a path requested by the client to the file system
+    fn resolve(&self, path: &Path) -> storage::Result<PathBuf> {
+        let requested = path.to_str().ok_or(ErrorKind::FileNameNotAllowedError)?;
+        self.vfs.get().resolve(requested).map_err(|e| {
+            debug!("FTP path validation failed for '{}': {}", requested, e);
+            ErrorKind::PermanentFileNotAvailable.into()
+        })
+    }
+
+    /// Refuses changes to mount points and the directories holding them, be it
+    /// removing, renaming or writing over them
+    fn check_removable(&self, path: &Path) -> storage::Result<()> {
+        let requested = path.to_string_lossy();
+        let vfs = self.vfs.get();
+        if vfs.is_mount_point(&requested) || !vfs.mount_points(&requested).is_empty() {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+        Ok(())
+    }
+
+    /// Opens a file to be uploaded, from the given position on
+    async fn open_upload(&self, path: &Path, start_pos: u64) -> storage::Result<tokio::fs::File> {
+        // The source of a file mount lies outside the served tree
+        self.check_removable(path)?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.resolve(path)?)
+            .await?;
+        file.set_len(start_pos).await?;
+        file.seek(std::io::SeekFrom::Start(start_pos)).await?;
+        Ok(file)
+    }
 }
 
-impl FTPRunner for Server {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> {
-        let mut s = Server::default();
+#[async_trait]
+impl StorageBackend<FtpUser> for VfsStorage {
+    type Metadata = VfsMetadata;
+
+    fn supported_features(&self) -> u32 {
+        FEATURE_RESTART | FEATURE_SITEMD5
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, path: P) -> storage::Result<Self::Metadata> {
+        let requested = path.as_ref().to_string_lossy();
+        // Directories only holding mount points show up like the root
+        let fs_path = if self.vfs.get().is_virtual_dir(&requested) {
+            self.resolve(Path::new(""))?
+        } else {
+            self.resolve(path.as_ref())?
+        };
+        let meta = tokio::fs::metadata(fs_path).await
+            .map_err(|_| storage::Error::from(ErrorKind::PermanentFileNotAvailable))?;
+        Ok(VfsMetadata(meta))
+    }
+
+    async fn list<P>(&self, user: &FtpUser, path: P) -> storage::Result<Vec<Fileinfo<PathBuf, Self::Metadata>>>
+    where
+        P: AsRef<Path> + Send + Debug,
+    {
+        let requested = path.as_ref().to_string_lossy().to_string();
+        let mount_points = self.vfs.get().mount_points(&requested);
+        let dir = self.resolve(path.as_ref())?;
+
+        let mut list = Vec::new();
+        if dir.is_dir() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Mount points shadow whatever is found at the same place
+                if mount_points.contains(&name) {
+                    continue;
+                }
+                // Hide what could not be accessed anyway, e.g. links out of the tree
+                let at = format!("{}/{}", requested.trim_end_matches('/'), name);
+                if self.resolve(Path::new(&at)).is_err() {
+                    continue;
+                }
+                if let Ok(meta) = tokio::fs::metadata(entry.path()).await {
+                    list.push(Fileinfo { path: PathBuf::from(name), metadata: VfsMetadata(meta) });
+                }
+            }
+        } else if mount_points.is_empty() {
+            return Err(ErrorKind::PermanentDirectoryNotAvailable.into());
+        }
+
+        for name in mount_points {
+            let at = format!("{}/{}", requested.trim_end_matches('/'), name);
+            if let Ok(metadata) = self.metadata(user, at).await {
+                list.push(Fileinfo { path: PathBuf::from(name), metadata });
+            }
+        }
+        Ok(list)
+    }
+
+    async fn get<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, path: P, start_pos: u64) -> storage::Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        let mut file = tokio::fs::File::open(self.resolve(path.as_ref())?).await?;
+        if start_pos > 0 {
+            file.seek(std::io::SeekFrom::Start(start_pos)).await?;
+        }
+        Ok(Box::new(tokio::io::BufReader::with_capacity(4096, file)))
+    }
+
+    // Used for downloads, whereas `get` is also used to compute checksums
+    async fn get_into<'a, P, W: ?Sized>(&self, user: &FtpUser, path: P, start_pos: u64, output: &'a mut W) -> storage::Result<u64>
+    where
+        W: AsyncWrite + Unpin + Sync + Send,
+        P: AsRef<Path> + Send + Debug,
+    {
+        self.stats.request(&Protocol::Ftp);
+        let requested = path.as_ref().to_string_lossy().to_string();
+        let size = self.metadata(user, path.as_ref()).await.ok().map(|meta| meta.len().saturating_sub(start_pos));
+        let reader = match self.get(user, path, start_pos).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                self.stats.error(&Protocol::Ftp);
+                return Err(e);
+            }
+        };
+
+        let transfer = self.stats.begin(&Protocol::Ftp, Some(user.client()), &requested, Direction::Download, size);
+        let copied = tokio::io::copy(&mut ProgressReader { inner: reader, transfer: &transfer }, output).await;
+        if let Some(snapshot) = transfer.snapshot() {
+            access_log::xferlog(&snapshot, user.name.as_deref(), copied.is_ok());
+        }
+        let copied = copied?;
+        info!(protocol = "ftp", client:% = user.ip, event = "download", path:% = requested, bytes = copied; "FTP transfer of {} completed ({} bytes)", requested, copied);
+        transfer.complete();
+        Ok(copied)
+    }
+
+    async fn put<P: AsRef<Path> + Send + Debug, R: AsyncRead + Send + Sync + Unpin + 'static>(
+        &self,
+        user: &FtpUser,
+        input: R,
+        path: P,
+        start_pos: u64,
+    ) -> storage::Result<u64> {
+        self.stats.request(&Protocol::Ftp);
+        let file = match self.open_upload(path.as_ref(), start_pos).await {
+            Ok(file) => file,
+            Err(e) => {
+                self.stats.error(&Protocol::Ftp);
+                return Err(e);
+            }
+        };
+
+        let transfer = self.stats.begin(&Protocol::Ftp, Some(user.client()), &path.as_ref().to_string_lossy(), Direction::Upload, None);
+        let mut reader = tokio::io::BufReader::with_capacity(4096, ProgressReader { inner: input, transfer: &transfer });
+        let mut writer = tokio::io::BufWriter::with_capacity(4096, file);
+        let copied = match tokio::io::copy(&mut reader, &mut writer).await {
+            Ok(copied) => tokio::io::AsyncWriteExt::flush(&mut writer).await.map(|_| copied),
+            Err(e) => Err(e),
+        };
+        if let Some(snapshot) = transfer.snapshot() {
+            access_log::xferlog(&snapshot, user.name.as_deref(), copied.is_ok());
+        }
+        let copied = copied?;
+        info!(protocol = "ftp", client:% = user.ip, event = "upload", path:% = path.as_ref().display(), bytes = copied; "FTP upload of {} completed ({} bytes)", path.as_ref().display(), copied);
+        transfer.complete();
+        Ok(copied)
+    }
+
+    async fn del<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, path: P) -> storage::Result<()> {
+        self.check_removable(path.as_ref())?;
+        Ok(tokio::fs::remove_file(self.resolve(path.as_ref())?).await?)
+    }
+
+    async fn mkd<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, path: P) -> storage::Result<()> {
+        self.check_removable(path.as_ref())?;
+        Ok(tokio::fs::create_dir(self.resolve(path.as_ref())?).await?)
+    }
 
+    async fn rename<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, from: P, to: P) -> storage::Result<()> {
+        self.check_removable(from.as_ref())?;
+        self.check_removable(to.as_ref())?;
+        Ok(tokio::fs::rename(self.resolve(from.as_ref())?, self.resolve(to.as_ref())?).await?)
+    }
+
+    async fn rmd<P: AsRef<Path> + Send + Debug>(&self, _user: &FtpUser, path: P) -> storage::Result<()> {
+        self.check_removable(path.as_ref())?;
+        Ok(tokio::fs::remove_dir(self.resolve(path.as_ref())?).await?)
+    }
+
+    async fn cwd<P: AsRef<Path> + Send + Debug>(&self, user: &FtpUser, path: P) -> storage::Result<()> {
+        if self.metadata(user, path).await?.is_dir() {
+            Ok(())
+        } else {
+            Err(ErrorKind::PermanentDirectoryNotAvailable.into())
+        }
+    }
+}
+
+/// Accepts the configured user, or anyone if none is
+#[derive(Debug)]
+struct ConfiguredAuthenticator {
+    /// Replaced on reload, for the next logins
+    credentials: Live<Option<Credentials>>,
+}
+
+#[async_trait]
+impl Authenticator for ConfiguredAuthenticator {
+    async fn authenticate(&self, username: &str, creds: &auth::Credentials) -> Result<Principal, AuthenticationError> {
+        let Some(credentials) = &*self.credentials.get() else {
+            return Ok(FtpUser { name: None, ip: creds.source_ip }.principal());
+        };
+        // Both checked in constant time, and failing the same way, not to tell valid user names
+        let user_ok = same_secret(username.as_bytes(), credentials.username.as_bytes());
+        let password_ok = same_secret(creds.password.as_deref().unwrap_or_default().as_bytes(), credentials.password.as_bytes());
+        if !(user_ok & password_ok) {
+            return Err(AuthenticationError::BadPassword);
+        }
+        Ok(FtpUser { name: Some(username.to_string()), ip: creds.source_ip }.principal())
+    }
+}
+
+/// User logged in, along with the address of its client, handed by libunftp
+/// to the storage backend for the statistics and the access log
+///
+/// libunftp only keeps a name out of the authentication, the [`Principal`]
+/// the authenticator returns, so the client's address is carried along in
+/// it as `<ip>` or `<ip>\n<name>`, FTP commands never holding line breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FtpUser {
+    /// The name logged in with. Anonymous access if none, without credentials configured
+    name: Option<String>,
+    ip: IpAddr,
+}
+
+impl FtpUser {
+    fn principal(&self) -> Principal {
+        let username = match &self.name {
+            Some(name) => format!("{}\n{}", self.ip, name),
+            None => self.ip.to_string(),
+        };
+        Principal { username }
+    }
+
+    fn from_principal(principal: &Principal) -> Option<Self> {
+        let (ip, name) = match principal.username.split_once('\n') {
+            Some((ip, name)) => (ip, Some(name.to_string())),
+            None => (principal.username.as_str(), None),
+        };
+        Some(FtpUser { name, ip: ip.parse().ok()? })
+    }
+
+    /// The client's address, its port being unknown to libunftp's users
+    fn client(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, 0)
+    }
+}
+
+impl UserDetail for FtpUser {}
+
+impl std::fmt::Display for FtpUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name.as_deref().unwrap_or("anonymous"))
+    }
+}
+
+/// Turns what [`ConfiguredAuthenticator`] returns back into an [`FtpUser`]
+#[derive(Debug)]
+struct FtpUserProvider;
+
+#[async_trait]
+impl UserDetailProvider for FtpUserProvider {
+    type User = FtpUser;
+
+    async fn provide_user_detail(&self, principal: &Principal) -> Result<FtpUser, UserDetailError> {
+        FtpUser::from_principal(principal)
+            .ok_or_else(|| UserDetailError::Generic(format!("Unexpected FTP principal {:?}", principal.username)))
+    }
+}
+
+/// Serves files over FTP, backed by libunftp
+///
+/// libunftp binds the control port by itself. Depending on the OS, a socket
+/// bound to `::` may then already accept IPv4 clients too.
+pub struct FtpServer {
+    config: FtpConfig,
+    /// The served tree and the credentials, replaced on reload
+    vfs: Live<Vfs>,
+    auth: Live<Option<Credentials>>,
+}
+
+impl FtpServer {
+    pub fn new(config: FtpConfig) -> Result<Self, crate::QuickServeError> {
         // Validate inputs with proper error handling
-        validation::validate_path(&path)?;
-        validation::validate_ip_port(&bind_ip, port)?;
-
-        let path = validation::ensure_trailing_slash(&path);
-        s.path = Arc::new(path);
-        s.bind_address = IpAddr::from_str(&bind_ip)
-            .map_err(|e| crate::QuickServeError::validation(format!("Invalid IP address '{}': {}", bind_ip, e)))?;
-        s.port = port;
-
-        s.protocol = Protocol::Ftp;
-        FTPRunner::runner(&s);
-        Ok(s)
-    }
-
-    fn runner(&self) {
-        let mut receiver = self.sender.subscribe();
-
-        let bind_address = self.bind_address;
-        let port = self.port;
-        let path = self.path.to_string_lossy().to_string();
-
-        tokio::spawn(async move {
-            loop {
-                debug!("FTP runner started... Waiting command to connect...");
-                
-                let m = match receiver.recv().await {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        error!("Failed to receive message in FTP runner: {}", e);
-                        break;
-                    }
-                };
-                debug!("Message received");
-
-                if m.connect {
-                    info!("Starting FTP server on {}:{}", bind_address, port);
-                    
-                    // Define new server with proper error handling
-                    let path_for_factory = path.clone();
-                    let server_result = libunftp::ServerBuilder::new(Box::new(move || {
-                        Filesystem::new(&path_for_factory).expect("Failed to create FTP filesystem backend")
-                    }))
-                        .passive_ports(50000..=65535)
-                        .metrics()
-                        .shutdown_indicator(async move {
-                            loop {
-                                info!("FTP server connected. Waiting command to disconnect...");
-                                match receiver.recv().await {
-                                    Ok(_) => break,
-                                    Err(e) => {
-                                        error!("Failed to receive stop command: {}", e);
-                                        break;
-                                    }
-                                }
-                            }
-                            debug!("Gracefully terminating the FTP server");
-                            // Give a few seconds to potential ongoing connections to finish, 
-                            // otherwise finish immediately
-                            libunftp::options::Shutdown::new().grace_period(Duration::from_secs(5))
-                        })
-                        .build();
-
-                    match server_result {
-                        Ok(server) => {
-                            let listen_addr = format!("{}:{}", bind_address, port);
-                            info!("FTP server listening on {}", listen_addr);
-                            
-                            if let Err(e) = server.listen(&listen_addr).await {
-                                error!("Error starting the FTP server on {}: {}", listen_addr, e);
-                            } else {
-                                info!("FTP server stopped gracefully");
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to build FTP server: {}", e);
-                        }
-                    }
-                    break;
+        let vfs = config.server.vfs();
+        vfs.validate()?;
+        Ok(FtpServer { vfs: Live::new(vfs), auth: Live::new(config.auth.clone()), config })
+    }
+
+    /// Builds a libunftp server, stopping gracefully once the context is cancelled
+    fn build(&self, ctx: &ServeContext) -> QuickServeResult<libunftp::Server<VfsStorage, FtpUser>> {
+        let vfs = self.vfs.clone();
+        let stats = ctx.stats.clone();
+        let grace_period = self.config.server.grace_period;
+        let shutdown = ctx.shutdown.clone();
+
+        let authenticator: Arc<dyn Authenticator> = Arc::new(ConfiguredAuthenticator { credentials: self.auth.clone() });
+
+        // Define new server with proper error handling
+        libunftp::ServerBuilder::with_user_detail_provider(Box::new(move || {
+            VfsStorage { vfs: vfs.clone(), stats: stats.clone() }
+        }), Arc::new(FtpUserProvider))
+            .authenticator(authenticator)
+            .passive_ports(self.config.passive_ports.clone())
+            .metrics()
+            .shutdown_indicator(async move {
+                info!("FTP server connected. Waiting command to disconnect...");
+                shutdown.cancelled().await;
+                debug!("Gracefully terminating the FTP server");
+                // Give a few seconds to potential ongoing connections to finish,
+                // otherwise finish immediately
+                libunftp::options::Shutdown::new().grace_period(grace_period)
+            })
+            .build()
+            .map_err(|e| QuickServeError::server_lifecycle(format!("Failed to build FTP server: {}", e)))
+    }
+
+    /// Starts a server on an address, returning the address once bound
+    ///
+    /// libunftp binds by itself, on the first poll of the listening future, so
+    /// an ephemeral port (port 0) is picked beforehand. Another process may take
+    /// it in between, in which case another one is picked.
+    async fn listen(&self, ctx: &ServeContext, addr: SocketAddr) -> QuickServeResult<(SocketAddr, Listening)> {
+        let mut attempt = 1;
+        loop {
+            let listen_addr = if addr.port() == 0 { net::bind_tcp(addr)?.local_addr()? } else { addr };
+            let mut listening: Listening = Box::pin(self.build(ctx)?.listen(listen_addr.to_string()));
+            match std::future::poll_fn(|cx| Poll::Ready(listening.as_mut().poll(cx))).await {
+                Poll::Pending => return Ok((listen_addr, listening)),
+                // Stopped before even listening
+                Poll::Ready(Ok(())) => return Ok((listen_addr, Box::pin(async { Ok(()) }))),
+                Poll::Ready(Err(e)) if addr.port() == 0 && attempt < EPHEMERAL_ATTEMPTS && in_use(&e) => {
+                    debug!("FTP port {} taken meanwhile, picking another one", listen_addr.port());
+                    attempt += 1;
+                }
+                Poll::Ready(Err(e)) => {
+                    return Err(QuickServeError::Network(format!("Error starting the FTP server on {}: {}", listen_addr, e)));
                 }
             }
+        }
+    }
+}
+
+#[async_trait]
+impl ProtocolServer for FtpServer {
+    fn protocol(&self) -> Protocol {
+        Protocol::Ftp
+    }
+
+    fn config(&self) -> &ServerConfig {
+        &self.config.server
+    }
+
+    async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+        // libunftp serves a single address, so run one server per address.
+        // They all serve the same tree and feed the same statistics.
+        let mut listen_addrs = Vec::new();
+        let mut servers = JoinSet::new();
+        for addr in self.config.server.socket_addrs() {
+            info!("Starting FTP server on {}", addr);
+            let (listen_addr, listening) = self.listen(&ctx, addr).await?;
+            info!("FTP server listening on {}", listen_addr);
+            servers.spawn(async move {
+                listening.await
+                    .map_err(|e| QuickServeError::Network(format!("Error running the FTP server on {}: {}", listen_addr, e)))
+            });
+            listen_addrs.push(listen_addr);
+        }
+        ctx.listening(listen_addrs);
+
+        // libunftp gives the sessions the grace period by itself, the transfers
+        // still going on after it only need to be reported
+        let reporter = ctx.clone();
+        let report = tokio::spawn(async move {
+            reporter.shutdown.cancelled().await;
+            reporter.drain(reporter.transfers_done()).await;
         });
+
+        while let Some(res) = servers.join_next().await {
+            // Any listener failing brings the others down with it
+            if let Err(e) = res.map_err(QuickServeError::from).and_then(|res| res) {
+                report.abort();
+                return Err(e);
+            }
+        }
+        let _ = report.await;
+
+        info!("FTP server stopped gracefully");
+        Ok(())
+    }
+
+    /// The served tree and credentials are replaced, the passive ports and
+    /// grace period applying from the next start
+    fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+        let next = reloaded_backend::<FtpServer>(next)?;
+        self.vfs.set((*next.vfs.get()).clone());
+        self.auth.set(next.config.auth.clone());
+        Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::{Mount, Server, ServerStatus};
+
+    #[tokio::test]
+    async fn test_configured_authenticator() {
+        let authenticator = ConfiguredAuthenticator { credentials: Live::new(None) };
+        let anyone = authenticator.authenticate("anyone", &"".into()).await.expect("anyone without credentials");
+        assert_eq!(FtpUser::from_principal(&anyone).unwrap().name, None, "anonymous access");
+
+        authenticator.credentials.set(Some(Credentials { username: "user".into(), password: "secret".into() }));
 
+        let principal = authenticator.authenticate("user", &"secret".into()).await.expect("valid login");
+        let user = FtpUserProvider.provide_user_detail(&principal).await.unwrap();
+        assert_eq!(user, FtpUser { name: Some("user".into()), ip: "127.0.0.1".parse().unwrap() }, "logged in from where the credentials came");
+        assert!(matches!(authenticator.authenticate("user", &"wrong".into()).await, Err(AuthenticationError::BadPassword)));
+        assert!(matches!(authenticator.authenticate("other", &"secret".into()).await, Err(AuthenticationError::BadPassword)), "same error for unknown users");
+        assert!(authenticator.authenticate("user", &auth::Credentials { password: None, ..auth::Credentials::from("") }).await.is_err(), "no password");
+    }
+
+    #[tokio::test]
+    async fn test_listen_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let ftp = |port| {
+            let server = ServerConfig { path: Arc::new(dir.path().into()), port, ..Default::default() };
+            Server::new(Box::new(FtpServer::new(FtpConfig { server, ..Default::default() }).unwrap()), Stats::default())
+        };
+
+        // The address reported is the one libunftp bound
+        let server = ftp(0);
+        server.start().unwrap();
+        let ServerStatus::Listening(addrs) = server.wait_change_from(ServerStatus::Idle).await else { panic!("FTP server should listen") };
+        tokio::net::TcpStream::connect(addrs[0]).await.expect("port should be listened on");
+
+        let taken = ftp(addrs[0].port());
+        taken.start().unwrap();
+        assert!(matches!(taken.wait_change_from(ServerStatus::Idle).await, ServerStatus::Failed(_)));
+
+        assert!(in_use(&std::io::Error::from(std::io::ErrorKind::AddrInUse).into()));
+        assert!(!in_use(&std::io::Error::from(std::io::ErrorKind::PermissionDenied).into()));
+    }
+
+    #[tokio::test]
+    async fn test_writes_over_mount_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let image = dir.path().join("Image");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::create_dir(dir.path().join("extra")).unwrap();
+        std::fs::write(&image, b"kernel").unwrap();
+        let vfs = Vfs::new(&root, vec![Mount::new("/kernel", &image).unwrap(), Mount::new("/fw/extra", dir.path().join("extra")).unwrap()]);
+        let storage = VfsStorage { vfs: Live::new(vfs), stats: Stats::default() };
+        let user = FtpUser { name: Some("user".into()), ip: "10.0.0.2".parse().unwrap() };
+
+        let refused = storage.put(&user, &b"overwritten"[..], "/kernel", 0).await.unwrap_err();
+        assert_eq!(refused.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(std::fs::read(&image).unwrap(), b"kernel", "the mounted file is left alone");
+        assert_eq!(storage.mkd(&user, "/fw").await.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(storage.mkd(&user, "/fw/extra").await.unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        assert_eq!(storage.put(&user, &b"data"[..], "/new.bin", 0).await.unwrap(), 4);
+        storage.mkd(&user, "/dir").await.unwrap();
+        assert!(root.join("dir").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_transfers_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let stats = Stats::default();
+        let storage = VfsStorage { vfs: Live::new(Vfs::new(dir.path(), Vec::new())), stats: stats.clone() };
+        let user = FtpUser { name: Some("user".into()), ip: "10.0.0.2".parse().unwrap() };
+
+        storage.put(&user, &b"data"[..], "/a.bin", 0).await.unwrap();
+        let mut output = Vec::new();
+        storage.get_into(&user, "/a.bin", 1, &mut output).await.unwrap();
+
+        let totals = &stats.clients()[&user.ip];
+        assert_eq!((totals.bytes_received, totals.bytes_sent), (4, 3), "totals kept for the FTP client");
+        assert!(stats.finished().iter().all(|t| t.client == Some(user.client())));
+
+        // Files that cannot be opened are errors, not transfers
+        assert!(storage.put(&user, &b"data"[..], "/missing/a.bin", 0).await.is_err());
+        assert_eq!(stats.finished().len(), 2);
+        assert_eq!(stats.protocol(&Protocol::Ftp).errors, 1);
+
+        let anonymous = FtpUser { name: None, ip: "::1".parse().unwrap() };
+        assert_eq!(FtpUser::from_principal(&anonymous.principal()), Some(anonymous));
+        assert_eq!(FtpUser::from_principal(&user.principal()), Some(user));
+    }
+}
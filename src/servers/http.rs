@@ -2,177 +2,436 @@ use log::{debug, info, error};
 
 use bytes::Bytes;
 use crate::servers::Protocol;
-use crate::utils::validation;
-use http_body_util::Full;
+use crate::utils::net;
+use http_body_util::{Either, Full};
 
 use hyper_util::rt::TokioIo;
 use hyper::{Request, Response, StatusCode};
+use hyper::body::{Body, Frame, SizeHint};
+use hyper::header::{AUTHORIZATION, REFERER, USER_AGENT, WWW_AUTHENTICATE};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 
-use std::net::{IpAddr, SocketAddr};
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
 
-use super::Server;
+use super::{reloaded_backend, Live, ProtocolServer, ServeContext, ServerConfig, Vfs};
+use crate::{QuickServeError, QuickServeResult};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, ReadBuf};
 use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use crate::{same_secret, Credentials, Direction, Stats, TransferHandle};
+use crate::access_log::{self, HttpAccess};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 
 
-async fn receive_request(req: Request<hyper::body::Incoming>, base_path: Arc<PathBuf>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+/// Size of the chunks files are streamed with
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Body of the responses: either a short message, or a file
+type HttpBody = Either<Full<Bytes>, FileBody>;
+
+/// Streams a file, following the transfer as the chunks are handed to the connection
+///
+/// The transfer is complete once the last chunk is handed over: hyper does
+/// not poll bodies any further once their announced length is reached.
+struct FileBody {
+    file: tokio::fs::File,
+    remaining: u64,
+    transfer: Option<TransferHandle>,
+}
+
+impl FileBody {
+    fn new(file: tokio::fs::File, len: u64, transfer: TransferHandle) -> Self {
+        let mut body = FileBody { file, remaining: len, transfer: Some(transfer) };
+        body.complete_if_done();
+        body
+    }
+
+    fn complete_if_done(&mut self) {
+        if self.remaining == 0 {
+            if let Some(transfer) = self.transfer.take() {
+                transfer.complete();
+            }
+        }
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, std::io::Error>>> {
+        let this = &mut *self;
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = vec![0u8; CHUNK_SIZE.min(this.remaining as usize)];
+        let mut buf = ReadBuf::new(&mut chunk);
+        match Pin::new(&mut this.file).poll_read(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                Poll::Ready(Some(Err(std::io::ErrorKind::UnexpectedEof.into())))
+            }
+            Poll::Ready(Ok(())) => {
+                let len = buf.filled().len();
+                chunk.truncate(len);
+                this.remaining -= len as u64;
+                if let Some(transfer) = &this.transfer {
+                    transfer.progress(len as u64);
+                }
+                this.complete_if_done();
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from(chunk)))))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// Body of a short message, e.g. for errors
+fn message(text: &'static str) -> HttpBody {
+    Either::Left(Full::new(Bytes::from(text)))
+}
+
+/// Returns the `user:password` pair sent through basic authentication, if any
+fn basic_credentials<B>(req: &Request<B>) -> Option<Vec<u8>> {
+    req.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+}
+
+/// Checks the basic authentication header of a request against the expected credentials
+fn is_authorized<B>(req: &Request<B>, auth: &Option<Credentials>) -> bool {
+    let Some(auth) = auth else { return true };
+
+    basic_credentials(req)
+        .is_some_and(|decoded| same_secret(&decoded, format!("{}:{}", auth.username, auth.password).as_bytes()))
+}
+
+/// Body of a response, writing the request to the access log once done with
+///
+/// That is once the body is dropped, be it fully sent or not, so that the
+/// bytes actually sent and the whole time taken are known.
+struct LoggedBody {
+    inner: HttpBody,
+    access: Option<HttpAccess>,
+    started: Instant,
+    bytes: u64,
+}
+
+impl Body for LoggedBody {
+    type Data = Bytes;
+    type Error = <HttpBody as Body>::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        let res = Pin::new(&mut this.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &res {
+            if let Some(data) = frame.data_ref() {
+                this.bytes += data.len() as u64;
+            }
+        }
+        res
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(access) = self.access.take() {
+            access_log::write(&access.line(self.bytes, self.started.elapsed()));
+        }
+    }
+}
+
+/// Serves a request, to be written to the access log once its response is sent
+async fn serve_request(req: Request<hyper::body::Incoming>, client: SocketAddr, auth: Arc<Option<Credentials>>, vfs: Arc<Vfs>, stats: Stats) -> Result<Response<LoggedBody>, hyper::Error> {
+    let started = Instant::now();
+    let access = access_log::is_enabled().then(|| {
+        let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        HttpAccess {
+            client: client.ip(),
+            user: basic_credentials(&req)
+                .and_then(|decoded| String::from_utf8_lossy(&decoded).split_once(':').map(|(user, _)| user.to_string())),
+            method: req.method().to_string(),
+            target: req.uri().path_and_query().map_or_else(|| req.uri().path().to_string(), |p| p.to_string()),
+            version: format!("{:?}", req.version()),
+            status: 0,
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+            time: chrono::Local::now(),
+        }
+    });
+
+    let response = receive_request(req, client, auth, vfs, stats).await?;
+    let status = response.status().as_u16();
+    Ok(response.map(|inner| LoggedBody {
+        inner,
+        access: access.map(|access| HttpAccess { status, ..access }),
+        started,
+        bytes: 0,
+    }))
+}
+
+async fn receive_request(req: Request<hyper::body::Incoming>, client: SocketAddr, auth: Arc<Option<Credentials>>, vfs: Arc<Vfs>, stats: Stats) -> Result<Response<HttpBody>, hyper::Error> {
+    stats.request(&Protocol::Http);
+
+    if !is_authorized(&req, &auth) {
+        info!(protocol = "http", client:% = client, event = "unauthorized", path = req.uri().path(); "Unauthorized request for {}", req.uri().path());
+        stats.error(&Protocol::Http);
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(WWW_AUTHENTICATE, "Basic realm=\"quick-serve\"")
+            .body(message("Unauthorized"))
+            .unwrap());
+    }
 
     // Remove the trailing slash from the path to avoid 
     // Path treating it as absolute path and ignoring the base path
     let req_path = req.uri().path().strip_prefix('/').unwrap_or(req.uri().path());
 
-    // Use the new validation function for security checks
-    let file_path = match crate::utils::validation::validate_file_path(&base_path, req_path) {
+    // Map the path to the served tree (root or mounts), with the security checks
+    let file_path = match vfs.resolve(req_path) {
         Ok(path) => path,
         Err(e) => {
-            error!("Path validation failed for '{}': {}", req_path, e);
+            error!(protocol = "http", client:% = client, event = "denied", path = req_path; "Path validation failed for '{}': {}", req_path, e);
+            stats.error(&Protocol::Http);
             return Ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(Bytes::from("Invalid path")))
+                .body(message("Invalid path"))
                 .unwrap());
         }
     };
 
-    info!("Request path: {}", file_path.display());
+    debug!(protocol = "http", client:% = client, event = "resolved", path = req_path; "Request path: {}", file_path.display());
 
     if !file_path.exists() {
-        info!("File does not exist: {}", file_path.display());
+        info!(protocol = "http", client:% = client, event = "not_found", path = req_path; "File does not exist: {}", file_path.display());
+        stats.error(&Protocol::Http);
         return Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
-            .body(Full::new(Bytes::from("File not found")))
+            .body(message("File not found"))
             .unwrap());
     }
 
     if !file_path.is_file() {
         info!("Path is not a file: {}", file_path.display());
+        stats.error(&Protocol::Http);
         return Ok(Response::builder()
             .status(StatusCode::BAD_REQUEST)
-            .body(Full::new(Bytes::from("Path is not a file")))
+            .body(message("Path is not a file"))
             .unwrap());
     }
 
-    match tokio::fs::read(&file_path).await {
-        Ok(file_content) => {
-            info!("Successfully served file: {} ({} bytes)", file_path.display(), file_content.len());
-            Ok(Response::new(Full::new(Bytes::from(file_content))))
+    let file = match tokio::fs::File::open(&file_path).await {
+        Ok(file) => file.metadata().await.map(|meta| (file, meta.len())),
+        Err(e) => Err(e),
+    };
+    match file {
+        Ok((file, len)) => {
+            info!(protocol = "http", client:% = client, event = "download", path = req_path, size = len; "Serving file: {} ({} bytes) to {}", file_path.display(), len, client);
+            let transfer = stats.begin(&Protocol::Http, Some(client), req_path, Direction::Download, Some(len));
+            Ok(Response::new(Either::Right(FileBody::new(file, len, transfer))))
         }
         Err(e) => {
-            error!("Failed to read file {}: {}", file_path.display(), e);
+            error!(protocol = "http", client:% = client, event = "failed", path = req_path; "Failed to read file {}: {}", file_path.display(), e);
+            stats.error(&Protocol::Http);
             Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Full::new(Bytes::from("Internal server error")))
+                .body(message("Internal server error"))
                 .unwrap())
         }
     }
 }
 
 
-pub trait HTTPRunner {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> where Self: Sized;
-    fn runner(&self);
+/// HTTP specific settings
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    pub server: ServerConfig,
+    /// Credentials required through basic authentication, if any
+    pub auth: Option<Credentials>,
+}
+
+impl From<ServerConfig> for HttpConfig {
+    fn from(server: ServerConfig) -> Self {
+        HttpConfig { server, auth: None }
+    }
 }
 
-impl HTTPRunner for Server {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> {
-        let mut s = Server::default();
+/// Serves files over HTTP/1.1
+pub struct HttpServer {
+    config: HttpConfig,
+    /// The served tree and the credentials, replaced on reload
+    vfs: Live<Vfs>,
+    auth: Live<Option<Credentials>>,
+}
 
+impl HttpServer {
+    pub fn new(config: HttpConfig) -> Result<Self, crate::QuickServeError> {
         // Validate inputs with proper error handling
-        validation::validate_path(&path)?;
-        validation::validate_ip_port(&bind_ip, port)?;
-
-        s.path = Arc::new(path.clone()); // Make a clone of the path and store it in the Server struct
-        s.bind_address = IpAddr::from_str(&bind_ip)
-            .map_err(|e| crate::QuickServeError::validation(format!("Invalid IP address '{}': {}", bind_ip, e)))?;
-        s.port = port;
-
-        s.protocol = Protocol::Http;
-        HTTPRunner::runner(&s);
-        Ok(s)
-    }
-
-    fn runner(&self) {
-        let mut receiver = self.sender.subscribe();
-
-        let bind_address = self.bind_address;
-        let port = self.port;
-        let path = self.path.clone();
-        
-        tokio::spawn(async move {
-            loop {
-                debug!("HTTP runner started. Waiting command to connect...");
-                
-                let m = match receiver.recv().await {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        error!("Failed to receive message in HTTP runner: {}", e);
-                        break;
-                    }
-                };
-                debug!("Message received");
-
-                if m.connect {
-                    info!("Starting HTTP server on {}:{}", bind_address, port);
-                    
-                    let tsk = tokio::spawn(async move {
-                        let socket_addr = SocketAddr::new(bind_address, port);
-                        
-                        let listener = match TcpListener::bind(socket_addr).await {
-                            Ok(listener) => {
-                                info!("HTTP server listening on {}", socket_addr);
-                                listener
-                            }
-                            Err(e) => {
-                                error!("Failed to bind HTTP server to {}: {}", socket_addr, e);
-                                return;
-                            }
-                        };
-
-                        loop {
-                            match listener.accept().await {
-                                Ok((stream, addr)) => {
-                                    debug!("New HTTP connection from {}", addr);
-                                    let io = TokioIo::new(stream);
-                                    let path_clone = path.clone();
-
-                                    tokio::spawn(async move {
-                                        if let Err(err) = http1::Builder::new()
-                                            .serve_connection(io, service_fn(move |req| receive_request(req, path_clone.clone())))
-                                            .await
-                                        {
-                                            error!("Error serving HTTP connection from {}: {:?}", addr, err);
-                                        }
-                                    });
-                                }
-                                Err(e) => {
-                                    error!("Failed to accept HTTP connection: {}", e);
-                                    // Continue accepting other connections
-                                }
-                            }
-                        }
-                    });
-
-                    // Wait for stop command
-                    match receiver.recv().await {
-                        Ok(_) => {
-                            info!("Stop command received, shutting down HTTP server");
-                            tsk.abort();
-                            debug!("HTTP server stopped");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("Failed to receive stop command: {}", e);
-                            tsk.abort();
-                            break;
+        let vfs = config.server.vfs();
+        vfs.validate()?;
+        Ok(HttpServer { vfs: Live::new(vfs), auth: Live::new(config.auth.clone()), config })
+    }
+}
+
+#[async_trait]
+impl ProtocolServer for HttpServer {
+    fn protocol(&self) -> Protocol {
+        Protocol::Http
+    }
+
+    fn config(&self) -> &ServerConfig {
+        &self.config.server
+    }
+
+    async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+        // Bind all addresses first, so that none is served if any fails
+        let mut listeners = Vec::new();
+        for socket_addr in self.config.server.socket_addrs() {
+            info!("Starting HTTP server on {}", socket_addr);
+            let listener = bind(socket_addr)
+                .map_err(|e| QuickServeError::Network(format!("Failed to bind HTTP server to {}: {}", socket_addr, e)))?;
+            info!("HTTP server listening on {}", listener.local_addr()?);
+            listeners.push(listener);
+        }
+        ctx.listening(listeners.iter().map(|l| l.local_addr()).collect::<Result<_, _>>()?);
+
+        // One accept loop per listener, all sharing the same tree and stats
+        let mut loops = JoinSet::new();
+        for listener in listeners {
+            loops.spawn(accept_loop(listener, self.auth.clone(), self.vfs.clone(), ctx.clone()));
+        }
+        let mut connections = Vec::new();
+        while let Some(res) = loops.join_next().await {
+            connections.extend(res.ok());
+        }
+
+        // Those left once the grace period is over are aborted when dropped
+        ctx.drain(async {
+            for open in &mut connections {
+                while open.join_next().await.is_some() {}
+            }
+        }).await;
+        debug!("HTTP server stopped");
+        Ok(())
+    }
+
+    fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+        let next = reloaded_backend::<HttpServer>(next)?;
+        self.vfs.set((*next.vfs.get()).clone());
+        self.auth.set(next.config.auth.clone());
+        Ok(())
+    }
+}
+
+/// Binds a listener on the given address
+fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    TcpListener::from_std(net::bind_tcp(addr)?)
+}
+
+/// Accepts connections on a listener until the server is stopped
+///
+/// Each request is served with the settings current when it comes in.
+///
+/// # Returns
+/// The connections still open, closing once done with the response being sent
+async fn accept_loop(listener: TcpListener, auth: Live<Option<Credentials>>, vfs: Live<Vfs>, ctx: ServeContext) -> JoinSet<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            _ = ctx.shutdown.cancelled() => break,
+            accepted = listener.accept() => accepted,
+            // Reap the connections closed meanwhile
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+        };
+
+        match accepted {
+            Ok((stream, addr)) => {
+                debug!("New HTTP connection from {}", addr);
+                let io = TokioIo::new(stream);
+                let auth = auth.clone();
+                let vfs = vfs.clone();
+                let stats = ctx.stats.clone();
+                let shutdown = ctx.shutdown.clone();
+
+                connections.spawn(async move {
+                    let connection = http1::Builder::new()
+                        .serve_connection(io, service_fn(move |req| serve_request(req, addr, auth.get(), vfs.get(), stats.clone())));
+                    tokio::pin!(connection);
+                    let res = tokio::select! {
+                        res = connection.as_mut() => res,
+                        _ = shutdown.cancelled() => {
+                            // Finish the response being sent, if any, then close
+                            connection.as_mut().graceful_shutdown();
+                            connection.await
                         }
+                    };
+                    if let Err(err) = res {
+                        error!("Error serving HTTP connection from {}: {:?}", addr, err);
                     }
-                }
+                });
+            }
+            Err(e) => {
+                error!("Failed to accept HTTP connection: {}", e);
+                // Continue accepting other connections
             }
-        });
+        }
     }
+    connections
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri("/file");
+        if let Some(value) = authorization {
+            builder = builder.header(AUTHORIZATION, value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn test_no_auth_configured_allows_all() {
+        assert!(is_authorized(&request(None), &None));
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let auth = Some(Credentials { username: "user".into(), password: "secret".into() });
+        let valid = format!("Basic {}", STANDARD.encode("user:secret"));
+        let wrong = format!("Basic {}", STANDARD.encode("user:other"));
+
+        assert!(is_authorized(&request(Some(&valid)), &auth));
+        assert!(!is_authorized(&request(Some(&wrong)), &auth));
+        assert!(!is_authorized(&request(Some("Basic not-base64!")), &auth));
+        assert!(!is_authorized(&request(Some("Bearer token")), &auth));
+        assert!(!is_authorized(&request(None), &auth));
+    }
+}
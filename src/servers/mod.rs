@@ -1,14 +1,21 @@
 // Re-export the types and items you want to make public from this module.
 pub use dhcp::*;
+pub use dhcp_server::DhcpPool;
 pub use ftp::*;
 pub use http::*;
+pub use registry::*;
+pub use reload::*;
 pub use server::*;
 pub use tftp::*;
+pub use vfs::*;
 
 // Import and re-export the submodule files.
 pub mod dhcp;
 pub mod dhcp_server;
 pub mod ftp;
 pub mod http;
+pub mod registry;
+pub mod reload;
 pub mod server;
 pub mod tftp;
+pub mod vfs;
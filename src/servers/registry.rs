@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use std::time::Duration;
+
+use crate::servers::{DhcpConfig, DhcpServer, FtpConfig, FtpServer, HttpConfig, HttpServer, Live, Protocol, ProtocolServer, ServerConfig, TftpConfig, TftpServer, PROTOCOL_LIST};
+use crate::{CommandMsg, Config, QuickServeError, QuickServeResult};
+
+/// Builds a protocol backend out of the common server settings
+pub type ServerFactory = Arc<dyn Fn(ServerConfig) -> QuickServeResult<Box<dyn ProtocolServer>> + Send + Sync>;
+
+/// Maps each protocol to the factory able to create its backend
+///
+/// The default registry knows about all built-in protocols. Additional (or
+/// replacement) backends can be plugged in with [`ServerRegistry::register`].
+#[derive(Clone)]
+pub struct ServerRegistry {
+    factories: HashMap<Protocol, ServerFactory>,
+    order: Vec<Protocol>,
+    /// Settings whose per-protocol sections the built-in backends are created with
+    config: Live<Config>,
+}
+
+impl Default for ServerRegistry {
+    fn default() -> Self {
+        ServerRegistry::from_config(&Config::default())
+    }
+}
+
+impl ServerRegistry {
+    /// Creates a registry of the built-in protocols, using the
+    /// protocol specific options of a configuration file
+    ///
+    /// # Arguments
+    /// * `config` - Settings whose per-protocol sections are applied to every backend created
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = ServerRegistry::empty();
+        registry.update(config);
+
+        let config = registry.config.clone();
+        registry.register(Protocol::Http, move |server| Ok(Box::new(HttpServer::new(HttpConfig {
+            server,
+            auth: config.get().http.auth.clone(),
+        })?)));
+
+        let config = registry.config.clone();
+        registry.register(Protocol::Tftp, move |server| {
+            let tftp = &config.get().tftp;
+            Ok(Box::new(TftpServer::new(TftpConfig {
+                server,
+                timeout: Duration::from_secs(tftp.timeout_secs),
+                block_size_limit: tftp.block_size_limit,
+            })?))
+        });
+
+        let config = registry.config.clone();
+        registry.register(Protocol::Ftp, move |server| {
+            let ftp = &config.get().ftp;
+            Ok(Box::new(FtpServer::new(FtpConfig {
+                server,
+                auth: ftp.auth.clone(),
+                passive_ports: ftp.passive_ports.clone(),
+            })?))
+        });
+
+        let config = registry.config.clone();
+        registry.register(Protocol::Dhcp, move |server| Ok(Box::new(DhcpServer::new(DhcpConfig {
+            server,
+            pool: config.get().dhcp.pool.clone(),
+        })?)));
+
+        debug_assert!(PROTOCOL_LIST.iter().all(|p| registry.factories.contains_key(*p)));
+        registry
+    }
+
+    /// Creates a registry without any backend
+    pub fn empty() -> Self {
+        ServerRegistry {
+            factories: HashMap::new(),
+            order: Vec::new(),
+            config: Live::default(),
+        }
+    }
+
+    /// Replaces the settings whose per-protocol sections the built-in backends
+    /// are created with, e.g. once the configuration file is reloaded
+    ///
+    /// Shared with the clones of this registry.
+    pub fn update(&self, config: &Config) {
+        self.config.set(config.clone());
+    }
+
+    /// Registers the factory for a protocol, replacing any previous one
+    ///
+    /// # Arguments
+    /// * `protocol` - The protocol served by the backend
+    /// * `factory` - Creates the backend from the common server settings
+    pub fn register<F>(&mut self, protocol: Protocol, factory: F)
+    where
+        F: Fn(ServerConfig) -> QuickServeResult<Box<dyn ProtocolServer>> + Send + Sync + 'static,
+    {
+        if !self.order.contains(&protocol) {
+            self.order.push(protocol.clone());
+        }
+        self.factories.insert(protocol, Arc::new(factory));
+    }
+
+    /// Returns the registered protocols, in registration order
+    pub fn protocols(&self) -> impl Iterator<Item = Protocol> + '_ {
+        self.order.iter().cloned()
+    }
+
+    /// Creates the backend requested by a start command
+    ///
+    /// # Arguments
+    /// * `msg` - The command requesting the server to start
+    ///
+    /// # Returns
+    /// * `Ok(Box<dyn ProtocolServer>)` - The backend, ready to be wrapped by a `Server`
+    /// * `Err(QuickServeError)` - If the protocol is unknown or the settings are invalid
+    pub fn create(&self, msg: &CommandMsg) -> QuickServeResult<Box<dyn ProtocolServer>> {
+        let factory = self.factories.get(&msg.protocol)
+            .ok_or_else(|| QuickServeError::validation(format!("No backend registered for protocol '{}'", msg.protocol.to_string())))?;
+
+        let server = ServerConfig {
+            grace_period: self.config.get().grace_period,
+            ..ServerConfig::from_command(msg)?
+        };
+        factory(server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::ServeContext;
+    use crate::ServerAction;
+    use async_trait::async_trait;
+
+    struct DummyServer {
+        config: ServerConfig,
+    }
+
+    #[async_trait]
+    impl ProtocolServer for DummyServer {
+        fn protocol(&self) -> Protocol {
+            Protocol::Custom("dummy")
+        }
+
+        fn config(&self) -> &ServerConfig {
+            &self.config
+        }
+
+        async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+            ctx.shutdown.cancelled().await;
+            Ok(())
+        }
+    }
+
+    fn command(protocol: Protocol, port: u16) -> CommandMsg {
+        CommandMsg {
+            action: ServerAction::Start,
+            port,
+            protocol,
+            bind_ip: "127.0.0.1".to_string(),
+            path: std::env::temp_dir().to_string_lossy().to_string(),
+            mounts: Vec::new(),
+            symlinks: Default::default(),
+            access: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_default_registry_has_builtin_protocols() {
+        let registry = ServerRegistry::default();
+        let protocols: Vec<Protocol> = registry.protocols().collect();
+        assert_eq!(protocols.len(), PROTOCOL_LIST.len());
+        for protocol in PROTOCOL_LIST {
+            assert!(protocols.contains(protocol), "{:?} not registered", protocol);
+        }
+    }
+
+    #[test]
+    fn test_create_unknown_protocol_fails() {
+        let registry = ServerRegistry::empty();
+        let result = registry.create(&command(Protocol::Http, 8080));
+        assert!(result.is_err());
+        assert!(result.err().unwrap().to_string().contains("No backend registered"));
+    }
+
+    #[test]
+    fn test_register_custom_protocol() {
+        let mut registry = ServerRegistry::default();
+        registry.register(Protocol::Custom("dummy"), |config| Ok(Box::new(DummyServer { config })));
+
+        assert!(registry.protocols().any(|p| p == Protocol::Custom("dummy")));
+
+        let backend = registry.create(&command(Protocol::Custom("dummy"), 9000)).expect("custom backend should be created");
+        assert_eq!(backend.protocol(), Protocol::Custom("dummy"));
+        assert_eq!(backend.config().port, 9000);
+    }
+
+    #[test]
+    fn test_register_replaces_existing_backend() {
+        let mut registry = ServerRegistry::default();
+        registry.register(Protocol::Http, |config| Ok(Box::new(DummyServer { config })));
+
+        assert_eq!(registry.protocols().filter(|p| *p == Protocol::Http).count(), 1);
+        let backend = registry.create(&command(Protocol::Http, 8080)).unwrap();
+        assert_eq!(backend.protocol(), Protocol::Custom("dummy"));
+    }
+
+    #[test]
+    fn test_create_validates_common_settings() {
+        let registry = ServerRegistry::default();
+        let mut cmd = command(Protocol::Http, 8080);
+        cmd.bind_ip = "not an ip".to_string();
+        assert!(registry.create(&cmd).is_err());
+    }
+
+    #[test]
+    fn test_dhcp_serves_a_single_ipv4_address() {
+        let registry = ServerRegistry::default();
+        let mut cmd = command(Protocol::Dhcp, 6767);
+        cmd.bind_ip = "127.0.0.1, 127.0.0.2".to_string();
+        assert!(registry.create(&cmd).is_err());
+        cmd.bind_ip = "::1".to_string();
+        assert!(registry.create(&cmd).is_err());
+
+        cmd.bind_ip = "127.0.0.1, [::1]".to_string();
+        cmd.protocol = Protocol::Http;
+        let backend = registry.create(&cmd).expect("other protocols accept several addresses");
+        assert_eq!(backend.config().bind_addresses.len(), 2);
+    }
+}
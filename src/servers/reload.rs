@@ -0,0 +1,119 @@
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::Sender;
+
+use crate::{Cli, CommandMsg, Config, ServerAction, ServerRegistry, PROTOCOL_LIST};
+
+/// How often a watched configuration file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hands new settings to the running servers, without restarting them
+///
+/// Each running server serves the requests to come with the new tree, access
+/// rules and credentials, while transfers already going on complete with the
+/// old ones. Addresses and ports are kept, and stopped servers are left alone.
+///
+/// # Arguments
+/// * `config` - The new settings
+/// * `registry` - The registry creating the servers, taking the new per-protocol sections
+/// * `sender` - The channel the servers are controlled through
+pub fn reload_servers(config: &Config, registry: &ServerRegistry, sender: &Sender<CommandMsg>) {
+    registry.update(config);
+    for protocol in PROTOCOL_LIST {
+        if let Some(cmd) = CommandMsg::from_config(config, protocol) {
+            // Servers not running ignore it
+            let _ = sender.send(CommandMsg { action: ServerAction::Reconfigure, ..cmd });
+        }
+    }
+}
+
+/// Reads the configuration again, with the command line on top, and hands it to
+/// the running servers, keeping the current settings if it is invalid
+fn reload_config(cli: &Cli, registry: &ServerRegistry, sender: &Sender<CommandMsg>) {
+    match Config::resolve(cli) {
+        Ok(config) => reload_servers(&config, registry, sender),
+        Err(e) => error!("Keeping the current settings: {}", e),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the configuration on SIGHUP and, with `--watch-config`, whenever
+/// the configuration file changes
+///
+/// # Arguments
+/// * `cli` - The command line, the configuration file being read again and overridden by it
+/// * `registry` - The registry creating the servers
+/// * `sender` - The channel the servers are controlled through
+pub fn spawn_config_reloader(cli: &Cli, registry: &ServerRegistry, sender: Sender<CommandMsg>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (cli, registry, sender) = (cli.clone(), registry.clone(), sender.clone());
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                tokio::spawn(async move {
+                    while hangup.recv().await.is_some() {
+                        info!("SIGHUP received, reloading the configuration");
+                        reload_config(&cli, &registry, &sender);
+                    }
+                });
+            }
+            Err(e) => error!("Failed to listen for SIGHUP: {}", e),
+        }
+    }
+
+    let Some(path) = Config::path(cli).filter(|_| cli.watch_config) else {
+        return;
+    };
+    let (cli, registry) = (cli.clone(), registry.clone());
+    tokio::spawn(async move {
+        watch(path, &cli, &registry, &sender).await;
+    });
+}
+
+/// Polls the modification time of the configuration file, reloading it once changed
+async fn watch(path: PathBuf, cli: &Cli, registry: &ServerRegistry, sender: &Sender<CommandMsg>) {
+    info!("Watching {} for changes", path.display());
+    let mut last = modified(&path);
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        let current = modified(&path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        // Left alone until written again
+        if current.is_none() {
+            continue;
+        }
+        info!("{} changed, reloading the configuration", path.display());
+        reload_config(cli, registry, sender);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DefaultChannel, Protocol};
+
+    #[test]
+    fn test_reload_servers() {
+        let channel: DefaultChannel<CommandMsg> = Default::default();
+        let mut rcv = channel.sender.subscribe();
+        let registry = ServerRegistry::from_config(&Config::default());
+
+        let config = Config { serve_dir: "/srv/next".into(), ..Default::default() };
+        reload_servers(&config, &registry, &channel.sender);
+
+        let msgs: Vec<CommandMsg> = std::iter::from_fn(|| rcv.try_recv().ok()).collect();
+        assert_eq!(msgs.len(), PROTOCOL_LIST.len());
+        assert!(msgs.iter().all(|msg| msg.action == ServerAction::Reconfigure && msg.path == "/srv/next"));
+        assert_eq!(msgs[0].protocol, Protocol::Http);
+    }
+}
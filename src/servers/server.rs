@@ -1,13 +1,20 @@
-use log::{debug, info, error};
-use tokio::sync::broadcast;
-use tokio::time::sleep;
+use async_trait::async_trait;
+use log::{debug, info, error, warn};
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+use std::any::Any;
+use std::future::Future;
 use std::process::exit;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 use std::{path::PathBuf, sync::Arc};
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
-use crate::{Cli, CommandMsg, DefaultChannel, FTPRunner, HTTPRunner, TFTPRunner, DHCPRunner, QuickServeError, QuickServeResult};
+use crate::utils::snippets;
+use crate::utils::validation::{self, SymlinkPolicy};
+use crate::{format_bytes, AccessRules, Cli, CommandMsg, Config, DefaultChannel, Mount, QuickServeError, QuickServeResult, ServerAction, ServerRegistry, Stats, Transfer, Vfs};
 
 
 #[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
@@ -17,9 +24,11 @@ pub enum Protocol {
     #[default]
     Http,
     Tftp,
+    /// Protocol provided by a third-party backend registered in a [`ServerRegistry`]
+    Custom(&'static str),
 }
 
-pub const PROTOCOL_LIST: [&'static Protocol; 4] = [&Protocol::Http, &Protocol::Tftp, &Protocol::Ftp, &Protocol::Dhcp];
+pub const PROTOCOL_LIST: [&Protocol; 4] = [&Protocol::Http, &Protocol::Tftp, &Protocol::Ftp, &Protocol::Dhcp];
 
 impl Protocol {
     /// Returns the protocol name as a string
@@ -29,9 +38,10 @@ impl Protocol {
             Protocol::Ftp  => "ftp",
             Protocol::Http => "http",
             Protocol::Tftp => "tftp",
+            Protocol::Custom(name) => name,
         }
     }
-    
+
     /// Returns the default port for the protocol
     pub fn get_default_port(&self) -> u16 {
         match self {
@@ -39,248 +49,799 @@ impl Protocol {
             Protocol::Ftp  => 2121,
             Protocol::Http => 8080,
             Protocol::Tftp => 6969,
+            Protocol::Custom(_) => 0,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = QuickServeError;
+
+    /// Parses the name of a built-in protocol, e.g. `tftp`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PROTOCOL_LIST.iter()
+            .find(|p| p.to_string() == s)
+            .map(|p| (*p).clone())
+            .ok_or_else(|| QuickServeError::validation(format!("Unknown protocol: {} (expected http, ftp, tftp or dhcp)", s)))
+    }
+}
+
+/// Time given by default to the transfers going on to complete once stopped
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How often the transfers going on are checked while draining
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Settings shared by every protocol backend
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Path to serve files from
+    pub path: Arc<PathBuf>,
+    /// IP addresses to bind to, each one getting its own listener
+    pub bind_addresses: Vec<IpAddr>,
+    /// Port to listen on
+    pub port: u16,
+    /// Directories and files grafted into the served tree
+    pub mounts: Vec<Mount>,
+    /// How links found in the served tree are handled
+    pub symlinks: SymlinkPolicy,
+    /// Rules deciding what may be served
+    pub access: AccessRules,
+    /// Time given to the transfers going on to complete once stopped
+    pub grace_period: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            path: Arc::new(PathBuf::default()),
+            bind_addresses: vec![IpAddr::from_str("127.0.0.1").unwrap()],
+            port: 0,
+            mounts: Vec::new(),
+            symlinks: SymlinkPolicy::default(),
+            access: AccessRules::default(),
+            grace_period: DEFAULT_GRACE_PERIOD,
         }
     }
 }
 
-/// Message used for internal server communication
-#[derive(Default, Clone, Debug)]
-pub struct Message {
-    /// Whether to connect (true) or disconnect (false)
-    pub connect: bool,
+impl ServerConfig {
+    /// Builds the common configuration out of a start command
+    ///
+    /// Validates the bind address and port. Protocol specific checks (e.g. on the
+    /// served path) are left to each backend.
+    ///
+    /// # Arguments
+    /// * `msg` - The command requesting the server to start
+    pub fn from_command(msg: &CommandMsg) -> QuickServeResult<Self> {
+        let bind_addresses = validation::parse_bind_addresses(&msg.bind_ip, msg.port)?;
+
+        Ok(ServerConfig {
+            path: Arc::new(validation::ensure_trailing_slash(&PathBuf::from(&msg.path))),
+            bind_addresses,
+            port: msg.port,
+            mounts: msg.mounts.clone(),
+            symlinks: msg.symlinks,
+            access: msg.access.clone(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+        })
+    }
+
+    /// Returns the tree to be served, i.e. the served path along with its mounts
+    pub fn vfs(&self) -> Vfs {
+        Vfs::new(self.path.as_path(), self.mounts.clone())
+            .with_symlinks(self.symlinks)
+            .with_access(&self.access)
+    }
+
+    /// Returns the socket addresses to listen on, one per bind address
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.bind_addresses.iter().map(|ip| SocketAddr::new(*ip, self.port)).collect()
+    }
+}
+
+/// Lifecycle state of a [`Server`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ServerStatus {
+    /// Created, waiting to be started
+    #[default]
+    Idle,
+    /// Accepting requests on the given addresses (at least one)
+    Listening(Vec<SocketAddr>),
+    /// Stopped on request
+    Stopped,
+    /// Exited because of the given error
+    Failed(String),
+}
+
+/// Publishes a server's lifecycle state, both to its own watchers and to
+/// the shared [`Stats`]
+#[derive(Clone)]
+struct StatusReporter {
+    protocol: Protocol,
+    tx: Arc<watch::Sender<ServerStatus>>,
+    stats: Stats,
+}
+
+impl StatusReporter {
+    fn set(&self, status: ServerStatus) {
+        self.stats.set_status(&self.protocol, status.clone());
+        self.tx.send_replace(status);
+    }
+}
+
+/// Handed by [`Server`] to its backend every time it is started
+#[derive(Clone)]
+pub struct ServeContext {
+    /// Cancelled when the server is requested to stop
+    pub shutdown: CancellationToken,
+    /// Statistics to be fed with the requests served
+    pub stats: Stats,
+    /// Time given to the transfers going on to complete once stopped
+    pub grace_period: Duration,
+    status: StatusReporter,
+}
+
+impl ServeContext {
+    /// Reports the addresses the backend is actually listening on
+    ///
+    /// That is where ephemeral ports (port 0) get to be known.
+    pub fn listening(&self, addrs: Vec<SocketAddr>) {
+        let list: Vec<String> = addrs.iter().map(|a| a.to_string()).collect();
+        info!(protocol = self.status.protocol.to_string(), event = "listening"; "{} server ready on {}", self.status.protocol.to_string().to_uppercase(), list.join(", "));
+        self.status.set(ServerStatus::Listening(addrs));
+    }
+
+    /// Returns the transfers of this server going on
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.stats.active().into_iter().filter(|t| t.protocol == self.status.protocol).collect()
+    }
+
+    /// Waits for the transfers of this server going on to complete
+    pub async fn transfers_done(&self) {
+        while !self.transfers().is_empty() {
+            tokio::time::sleep(DRAIN_INTERVAL).await;
+        }
+    }
+
+    /// Gives the transfers going on, once stopped, up to the grace period to complete
+    ///
+    /// Those still going on after the grace period are reported as cut off,
+    /// for the backend to drop them.
+    ///
+    /// # Arguments
+    /// * `done` - Completes once the transfers have
+    ///
+    /// # Returns
+    /// Whether they all completed in time
+    pub async fn drain(&self, done: impl Future<Output = ()>) -> bool {
+        let name = self.status.protocol.to_string().to_uppercase();
+        let pending = self.transfers().len();
+        if pending > 0 {
+            info!(protocol = self.status.protocol.to_string(), event = "draining"; "{} server waiting up to {:?} for {} transfer(s) to complete",
+                name, self.grace_period, pending);
+        }
+        if tokio::time::timeout(self.grace_period, done).await.is_ok() {
+            return true;
+        }
+
+        for t in self.transfers() {
+            let client = t.client_label();
+            let size = t.size.map(|size| format!(" of {}", format_bytes(size))).unwrap_or_default();
+            warn!(protocol = self.status.protocol.to_string(), client = client.as_str(), event = "cut off", path = t.path.as_str();
+                "{} transfer of {} with {} cut off after {}{}", name, t.path, client, format_bytes(t.bytes), size);
+        }
+        false
+    }
+}
+
+/// Settings of a backend that can be replaced while it runs
+///
+/// Requests take the current settings when they begin, so that the ones
+/// in flight go on with the settings they started with.
+#[derive(Debug, Default)]
+pub struct Live<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Clone for Live<T> {
+    fn clone(&self) -> Self {
+        Live(self.0.clone())
+    }
+}
+
+impl<T> Live<T> {
+    pub fn new(value: T) -> Self {
+        Live(Arc::new(RwLock::new(Arc::new(value))))
+    }
+
+    /// Returns the current settings
+    pub fn get(&self) -> Arc<T> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces the settings, for the requests to come
+    pub fn set(&self, value: T) {
+        *self.0.write().unwrap() = Arc::new(value);
+    }
+}
+
+/// A protocol backend that can be started and stopped by a [`Server`]
+///
+/// Implementors only have to provide the serving logic. Waiting for commands,
+/// spawning and cancelling is handled by [`Server`] for all of them alike.
+#[async_trait]
+pub trait ProtocolServer: Any + Send + Sync {
+    /// The protocol implemented by this backend
+    fn protocol(&self) -> Protocol;
+
+    /// The common settings this backend was created with
+    fn config(&self) -> &ServerConfig;
+
+    /// Serves until `ctx.shutdown` is cancelled or an unrecoverable error occurs
+    ///
+    /// Backends are expected to call [`ServeContext::listening`] once bound.
+    ///
+    /// # Arguments
+    /// * `ctx` - Shutdown signal and shared state for this run
+    async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()>;
+
+    /// Takes the settings of `next`, a backend of the same type created out of
+    /// new settings, for the requests to come, keeping the addresses listened on
+    ///
+    /// Backends that cannot change their settings while running keep this
+    /// default, refusing to.
+    fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+        let _ = next;
+        Err(QuickServeError::server_lifecycle(format!("{} server cannot change its settings while running",
+            self.protocol().to_string().to_uppercase())))
+    }
+}
+
+/// Returns the backend handed to [`ProtocolServer::reload`] as the type of the one reloaded
+pub fn reloaded_backend<T: ProtocolServer>(next: &dyn ProtocolServer) -> QuickServeResult<&T> {
+    (next as &dyn Any).downcast_ref::<T>()
+        .ok_or_else(|| QuickServeError::server_lifecycle(format!("Cannot reload with a different {} backend",
+            next.protocol().to_string().to_uppercase())))
 }
 
 /// Represents a server instance with its configuration
+///
+/// A server is started once: stopped, it is dropped for a new one to be
+/// created out of the settings to serve with next. Dropping it also stops
+/// its backend, without waiting for it.
 pub struct Server {
-    /// Broadcast sender for control messages
-    pub sender: broadcast::Sender<Message>,
     /// The protocol this server handles
     pub protocol: Protocol,
-    /// Path to serve files from
-    pub path: Arc<PathBuf>,
-    /// IP address to bind to
-    pub bind_address: IpAddr,
-    /// Port to listen on
-    pub port: u16
+    /// The settings the server was created with
+    pub config: ServerConfig,
+    /// Statistics fed by the backend
+    pub stats: Stats,
+    status: Arc<watch::Sender<ServerStatus>>,
+    backend: Option<Arc<dyn ProtocolServer>>,
+    /// Cancelled to stop the backend
+    shutdown: CancellationToken,
+    started: AtomicBool,
 }
 
 impl Default for Server {
     fn default() -> Self {
         Server {
-            sender: broadcast::channel(10).0,
             protocol: Protocol::default(),
-            path: Arc::new(PathBuf::default()),
-            bind_address: IpAddr::from_str("127.0.0.1").unwrap(),
-            port: 0,
+            config: ServerConfig::default(),
+            stats: Stats::default(),
+            status: Arc::new(watch::channel(ServerStatus::default()).0),
+            backend: None,
+            shutdown: CancellationToken::new(),
+            started: AtomicBool::new(false),
         }
     }
 }
 
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
 impl Server {
-    /// Starts the server by sending a connect message
+    /// Wraps a protocol backend, to be launched by [`Server::start`]
     ///
-    /// # Returns
-    /// * `Ok(())` if the message was sent successfully
-    /// * `Err(QuickServeError)` if sending the message failed
-    pub fn start(&self) -> QuickServeResult<()> {
-        info!("Starting {} server bind to {}:{}", self.protocol.to_string(), self.bind_address, self.port);
-        info!("Serving {}", self.path.to_string_lossy());
+    /// # Arguments
+    /// * `backend` - The protocol backend to be managed
+    /// * `stats` - Statistics to be fed by the backend
+    pub fn new(backend: Box<dyn ProtocolServer>, stats: Stats) -> Self {
+        let backend: Arc<dyn ProtocolServer> = Arc::from(backend);
+        Server {
+            protocol: backend.protocol(),
+            config: backend.config().clone(),
+            stats,
+            status: Arc::new(watch::channel(ServerStatus::default()).0),
+            backend: Some(backend),
+            shutdown: CancellationToken::new(),
+            started: AtomicBool::new(false),
+        }
+    }
 
-        let s = Message{connect: true};
-        self.sender.send(s)
-            .map_err(|err| QuickServeError::server_lifecycle(format!("Error sending start message: {:?}", err)))?;
+    /// Takes the settings of a backend created out of new ones, for the requests to come
+    ///
+    /// Requests being served go on with the settings they started with. The
+    /// addresses listened on are kept, changing them requires a restart.
+    ///
+    /// # Arguments
+    /// * `next` - A backend of the same protocol, created out of the new settings
+    pub fn reload(&mut self, next: Box<dyn ProtocolServer>) -> QuickServeResult<()> {
+        let backend = self.backend.as_ref()
+            .ok_or_else(|| QuickServeError::server_lifecycle("No backend to reload"))?;
+        backend.reload(next.as_ref())?;
+
+        let next = next.config();
+        if next.bind_addresses != self.config.bind_addresses || next.port != self.config.port {
+            warn!(protocol = self.protocol.to_string(); "{} server keeps listening on {} port {}, restart it to change",
+                self.protocol.to_string().to_uppercase(), self.config.bind_addresses.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", "), self.config.port);
+        }
+        self.config = ServerConfig {
+            bind_addresses: self.config.bind_addresses.clone(),
+            port: self.config.port,
+            ..next.clone()
+        };
+        info!(protocol = self.protocol.to_string(), event = "reloaded"; "{} server now serving {}",
+            self.protocol.to_string().to_uppercase(), self.config.path.to_string_lossy());
+        for mount in &self.config.mounts {
+            info!("Mounting {}", mount);
+        }
         Ok(())
     }
 
-    /// Stops the server by sending disconnect messages
+    /// Returns the current lifecycle state
+    pub fn status(&self) -> ServerStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Returns the (first) address the server is listening on, if running
+    pub fn bound_addr(&self) -> Option<SocketAddr> {
+        self.bound_addrs().first().copied()
+    }
+
+    /// Returns all addresses the server is listening on, if running
+    pub fn bound_addrs(&self) -> Vec<SocketAddr> {
+        match self.status() {
+            ServerStatus::Listening(addrs) => addrs,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Waits until the server leaves the given state
+    ///
+    /// # Returns
+    /// The state the server transitioned to
+    pub async fn wait_change_from(&self, from: ServerStatus) -> ServerStatus {
+        let mut rx = self.status.subscribe();
+        let changed = rx.wait_for(|status| *status != from).await.map(|status| status.clone());
+        changed.unwrap_or_else(|_| self.status())
+    }
+
+    /// Waits until the server is done serving, once started
     ///
-    /// Sends two disconnect messages to ensure both the inner loop and runner exit.
+    /// # Returns
+    /// The state the server ended in, stopped or failed
+    pub async fn finished(&self) -> ServerStatus {
+        let mut rx = self.status.subscribe();
+        let finished = rx.wait_for(|status| matches!(status, ServerStatus::Stopped | ServerStatus::Failed(_))).await
+            .map(|status| status.clone());
+        finished.unwrap_or_else(|_| self.status())
+    }
+
+    /// Whether the server is done serving, stopped or failed
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status(), ServerStatus::Stopped | ServerStatus::Failed(_))
+    }
+
+    /// Launches the backend, serving until stopped
     ///
     /// # Returns
-    /// * `Ok(())` if the messages were sent successfully
-    /// * `Err(QuickServeError)` if sending messages failed
-    pub fn stop(&self) -> QuickServeResult<()> {
-        // Stop the serving loop to exit the application. 
-        // Mostly required by the headless version (single sessions).
-
-        info!("Stopping {} server", self.protocol.to_string());
-        
-        // First stop and to then stop
-        let m = Message {connect: false};
-
-        // Send twice. Once to make sure the server is stopped (inner loop)
-        // and the second to ensure runner exits.
-        self.sender.send(m.clone())
-            .map_err(|err| QuickServeError::server_lifecycle(format!("Error sending first stop message: {:?}", err)))?;
-        
-        self.sender.send(m)
-            .map_err(|err| QuickServeError::server_lifecycle(format!("Error sending second stop message: {:?}", err)))?;
-        
-        info!("{} server stopped", self.protocol.to_string());
+    /// * `Ok(())` once launched, the backend then reporting where it listens
+    /// * `Err(QuickServeError)` without a backend, or if started or stopped already
+    pub fn start(&self) -> QuickServeResult<()> {
+        let name = self.protocol.to_string().to_uppercase();
+        let backend = self.backend.clone()
+            .ok_or_else(|| QuickServeError::server_lifecycle("No backend to start"))?;
+        if self.started.swap(true, Ordering::SeqCst) || self.shutdown.is_cancelled() {
+            return Err(QuickServeError::server_lifecycle(format!("{} server already started", name)));
+        }
+
+        for addr in self.config.socket_addrs() {
+            info!("Starting {} server bind to {}", self.protocol.to_string(), addr);
+        }
+        info!("Serving {}", self.config.path.to_string_lossy());
+        for mount in &self.config.mounts {
+            info!("Mounting {}", mount);
+        }
+
+        let status = StatusReporter {
+            protocol: self.protocol.clone(),
+            tx: self.status.clone(),
+            stats: self.stats.clone(),
+        };
+        let ctx = ServeContext {
+            shutdown: self.shutdown.clone(),
+            stats: self.stats.clone(),
+            grace_period: backend.config().grace_period,
+            status: status.clone(),
+        };
+        tokio::spawn(async move {
+            // Spawned apart for a panic of the backend to be reported as a failure
+            let res = tokio::spawn(async move { backend.serve(ctx).await }).await;
+            let final_status = match res {
+                Ok(Ok(())) => {
+                    info!(protocol = status.protocol.to_string(), event = "stopped"; "{} server stopped", name);
+                    ServerStatus::Stopped
+                }
+                Ok(Err(e)) => {
+                    error!("{} server error: {}", name, e);
+                    ServerStatus::Failed(e.to_string())
+                }
+                Err(e) => {
+                    error!("{} server task failed: {}", name, e);
+                    ServerStatus::Failed(e.to_string())
+                }
+            };
+            status.set(final_status);
+        });
         Ok(())
     }
+
+    /// Stops the server by cancelling its backend
+    ///
+    /// The backend gives the transfers going on up to its grace period to
+    /// complete: see [`Server::finished`] to wait for it. Stopping a server
+    /// already stopped does nothing.
+    pub fn stop(&self) {
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+        info!(protocol = self.protocol.to_string(), event = "stopping"; "Stopping {} server", self.protocol.to_string());
+        self.shutdown.cancel();
+    }
 }
 
 
+/// Carries out the commands for one protocol, one after the other
+///
+/// Stopping waits for the server to be done, for the one started next to be
+/// able to bind the same addresses however quickly the commands follow.
+struct ServerControl {
+    protocol: Protocol,
+    registry: ServerRegistry,
+    stats: Stats,
+    /// The server last started, until stopped
+    server: Option<Server>,
+}
+
+impl ServerControl {
+    async fn handle(&mut self, msg: CommandMsg) {
+        // Forget a server which exited on its own
+        if self.server.as_ref().is_some_and(Server::is_finished) {
+            self.server = None;
+        }
 
-/// Starts receiver tasks for all protocols
+        match msg.action {
+            ServerAction::Start if self.server.is_some() => {
+                debug!("{} server already running", self.protocol.to_string());
+            }
+            ServerAction::Start => self.start(&msg),
+            ServerAction::Stop => self.stop().await,
+            ServerAction::Restart => {
+                self.stop().await;
+                self.start(&msg);
+            }
+            ServerAction::Reconfigure => match &mut self.server {
+                Some(server) => {
+                    if let Err(e) = self.registry.create(&msg).and_then(|backend| server.reload(backend)) {
+                        error!("Failed to reload {} server: {}", self.protocol.to_string(), e);
+                    }
+                }
+                None => debug!("{} server not running, nothing to reconfigure", self.protocol.to_string()),
+            },
+        }
+    }
+
+    fn start(&mut self, msg: &CommandMsg) {
+        let server = match self.registry.create(msg) {
+            Ok(backend) => Server::new(backend, self.stats.clone()),
+            Err(e) => {
+                error!("Failed to create {} server: {}", self.protocol.to_string(), e);
+                return;
+            }
+        };
+        if let Err(e) = server.start() {
+            error!("Failed to start {} server: {}", self.protocol.to_string(), e);
+            return;
+        }
+        info!(protocol = self.protocol.to_string(), event = "started"; "Started {} server", self.protocol.to_string());
+        self.server = Some(server);
+    }
+
+    async fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.stop();
+            server.finished().await;
+        }
+    }
+}
+
+/// Starts receiver tasks for all registered protocols
 ///
-/// Spawns one async task per protocol that listens for start/stop commands
-/// and manages the lifecycle of each server.
+/// Spawns one async task per protocol carrying out the commands sent for
+/// it, in order, and managing the lifecycle of its server.
 ///
 /// # Arguments
 /// * `channel` - The broadcast channel for sending commands to servers
-pub fn server_starter_receiver(channel: &DefaultChannel<CommandMsg>) {
-    ////////////////////////////////////////////////////////////////////////
-    // Spawn one thread per protocol and start waiting for command
-    // to start or stop each server
-    ////////////////////////////////////////////////////////////////////////
-    for protocol in PROTOCOL_LIST {
+/// * `registry` - The backends available to be started
+/// * `stats` - Statistics shared by all servers
+pub fn server_starter_receiver(channel: &DefaultChannel<CommandMsg>, registry: &ServerRegistry, stats: &Stats) {
+    for protocol in registry.protocols() {
         let mut rcv = channel.sender.subscribe();
+        let mut control = ServerControl {
+            protocol: protocol.clone(),
+            registry: registry.clone(),
+            stats: stats.clone(),
+            server: None,
+        };
         debug!("Spawning receiver for {}", protocol.to_string());
         tokio::spawn(async move {
             loop {
-                debug!(" {} started waiting for messages", protocol.to_string());
-                let msg = rcv.recv().await.expect("Failed to receive message");
-                if msg.protocol != *protocol {
-                    debug!("\"Not my business...\" said the {}", protocol.to_string());
-                    continue;
-                }
-
-                if msg.start == true {
-                    let server = match msg.protocol {
-                        Protocol::Http => {
-                            <Server as HTTPRunner>::new(msg.path.clone().into(), msg.bind_ip.clone(), msg.port)
-                        },
-                        Protocol::Ftp => {
-                            <Server as FTPRunner>::new(msg.path.clone().into(), msg.bind_ip.clone(), msg.port)
-                        },
-                        Protocol::Tftp => {
-                            <Server as TFTPRunner>::new(msg.path.clone().into(), msg.bind_ip.clone(), msg.port)
-                        },
-                        Protocol::Dhcp => {
-                            <Server as DHCPRunner>::new(msg.path.clone().into(), msg.bind_ip.clone(), msg.port)
-                        },
-                    };
-
-                    let server = match server {
-                        Ok(s) => s,
-                        Err(e) => {
-                            error!("Failed to create {} server: {}", msg.protocol.to_string(), e);
-                            continue;
-                        }
-                    };
-
-                    // Small delay to ensure the server's internal receiver is ready
-                    // This is necessary because the server spawns async tasks that need to subscribe
-                    sleep(Duration::from_millis(10)).await;
-                    
-                    if let Err(e) = server.start() {
-                        error!("Failed to start {} server: {}", msg.protocol.to_string(), e);
-                        continue;
-                    }
-                    info!("Started {} server", msg.protocol.to_string());
-
-                    // Once started, wait for termination
-                    match rcv.recv().await {
-                        Ok(_msg) => {
-                            if let Err(e) = server.stop() {
-                                error!("Failed to stop {} server: {}", msg.protocol.to_string(), e);
-                            } else {
-                                info!("{} server stopped", msg.protocol.to_string());
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to receive stop message for {} server: {}", msg.protocol.to_string(), e);
-                            // Try to stop the server anyway
-                            if let Err(stop_err) = server.stop() {
-                                error!("Failed to stop {} server after receive error: {}", msg.protocol.to_string(), stop_err);
-                            }
-                        }
+                match rcv.recv().await {
+                    Ok(msg) if msg.protocol != protocol => continue,
+                    Ok(msg) => control.handle(msg).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("{} server missed {} command(s)", protocol.to_string().to_uppercase(), n);
                     }
+                    Err(broadcast::error::RecvError::Closed) => break,
                 }
             }
+            // Nothing can control the server anymore
+            control.stop().await;
         });
     }
 }
 
 
-/// Processes CLI arguments and sends start commands for requested servers
+/// How long to wait for the requested servers to bind before reporting them
+const READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the servers to stop, on top of their grace period, before exiting anyway
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Exit status when `--timeout` expires before the other exit conditions are met
+pub const EXIT_TIMEOUT: i32 = 124;
+
+/// Polls `done` every `interval` until it returns true or `timeout` expires
 ///
-/// Validates the bind address and path, then sends start messages for each
-/// server protocol specified in the command-line arguments. Blocks indefinitely
-/// waiting for the Ctrl+C handler to terminate the process.
+/// # Returns
+/// Whether `done` returned true in time
+fn wait_until(timeout: Option<Duration>, interval: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        if done() {
+            return true;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return false;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Waits until none of the given protocols' servers is idle anymore
 ///
-/// # Arguments
-/// * `cli_args` - Parsed command-line arguments
-/// * `channel` - The broadcast channel for sending commands to servers
-pub fn server_starter_sender(cli_args: &Cli, channel: &DefaultChannel<CommandMsg>) {
-    // Read and validate the bind address
-    let bind_ip = &cli_args.bind_ip;
-    let path = &cli_args.serve_dir;
-
-    let mut count = 0u8;
-
-    let mut cmd = CommandMsg {
-        start: true,
-        bind_ip: bind_ip.to_string(),
-        path: path.to_string(),
-        ..Default::default()
-    };
-
-    // Check for each server invoked from the command line, and send 
-    // messages accordingly to start each
-    if cli_args.http.is_some() {
-        cmd.protocol = Protocol::Http;
-        cmd.port = cli_args.http.unwrap() as u16;
-        if let Err(e) = channel.sender.send(cmd.clone()) {
-            error!("Failed to send HTTP start command: {}", e);
+/// # Returns
+/// The state of each server once settled (or once `timeout` expires)
+pub fn wait_ready(stats: &Stats, protocols: &[Protocol], timeout: Duration) -> Vec<(Protocol, ServerStatus)> {
+    wait_until(Some(timeout), Duration::from_millis(10), || {
+        protocols.iter().all(|p| stats.status(p) != ServerStatus::Idle)
+    });
+    protocols.iter().map(|p| (p.clone(), stats.status(p))).collect()
+}
+
+/// When to stop serving and exit, rather than waiting for Ctrl+C
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExitConditions {
+    /// Exit once this many files have been fully downloaded
+    pub max_transfers: Option<u64>,
+    /// Exit after serving for this long
+    pub timeout: Option<Duration>,
+    /// Exit once this file (path within the served tree) has been fully downloaded
+    pub exit_after: Option<String>,
+}
+
+impl ExitConditions {
+    pub fn from_cli(cli: &Cli) -> Self {
+        ExitConditions {
+            max_transfers: cli.max_transfers,
+            timeout: cli.timeout,
+            exit_after: cli.exit_after.clone(),
         }
-        count += 1;
     }
 
-    if cli_args.ftp.is_some() {
-        cmd.protocol = Protocol::Ftp;
-        cmd.port = cli_args.ftp.unwrap() as u16;
-        if let Err(e) = channel.sender.send(cmd.clone()) {
-            error!("Failed to send FTP start command: {}", e);
+    /// Whether the servers are to run until interrupted
+    pub fn is_empty(&self) -> bool {
+        *self == ExitConditions::default()
+    }
+
+    /// Whether any condition on the downloads is met
+    pub fn transfers_done(&self, stats: &Stats) -> bool {
+        self.max_transfers.is_some_and(|n| stats.transfers() >= n)
+            || self.exit_after.as_ref().is_some_and(|file| stats.downloads(file) > 0)
+    }
+
+    /// Waits for any condition to be met, or any server to fail
+    ///
+    /// # Returns
+    /// The exit status: 0 once a condition is met, [`EXIT_TIMEOUT`] if the
+    /// timeout expires before the download conditions are, 1 if a server failed
+    pub fn wait(&self, stats: &Stats, protocols: &[Protocol]) -> i32 {
+        let mut failed = false;
+        let done = wait_until(self.timeout, Duration::from_millis(50), || {
+            failed = protocols.iter().any(|p| matches!(stats.status(p), ServerStatus::Failed(_)));
+            failed || self.transfers_done(stats)
+        });
+
+        if failed {
+            error!("A server failed, exiting");
+            1
+        } else if done {
+            info!("{} file(s) downloaded, exiting", stats.transfers());
+            0
+        } else if self.max_transfers.is_some() || self.exit_after.is_some() {
+            error!("Timed out after {} file(s) downloaded, exiting", stats.transfers());
+            EXIT_TIMEOUT
+        } else {
+            info!("Served for {:?}, exiting", self.timeout.unwrap_or_default());
+            0
         }
-        count += 1;
     }
+}
 
-    if cli_args.tftp.is_some() {
-        cmd.protocol = Protocol::Tftp;
-        cmd.port = cli_args.tftp.unwrap() as u16;
-        if let Err(e) = channel.sender.send(cmd.clone()) {
-            error!("Failed to send TFTP start command: {}", e);
+/// Formats the bound address of each listening server as a JSON object
+///
+/// e.g. `{"http":"127.0.0.1:8080","tftp":"127.0.0.1:40123"}`. Servers
+/// listening on several addresses get a list, e.g. `{"http":["0.0.0.0:8080","[::]:8080"]}`.
+pub fn ready_report(states: &[(Protocol, ServerStatus)]) -> String {
+    let map: serde_json::Map<String, serde_json::Value> = states.iter()
+        .filter_map(|(p, s)| match s {
+            ServerStatus::Listening(addrs) => {
+                let mut addrs: Vec<serde_json::Value> = addrs.iter().map(|a| a.to_string().into()).collect();
+                let value = if addrs.len() == 1 { addrs.remove(0) } else { addrs.into() };
+                Some((p.to_string().to_string(), value))
+            }
+            _ => None,
+        })
+        .collect();
+    serde_json::Value::Object(map).to_string()
+}
+
+/// Stops servers, waiting for them to be done with the transfers going on
+///
+/// # Arguments
+/// * `sender` - The channel the servers are controlled through
+/// * `stats` - Statistics shared by all servers, used to follow their state
+/// * `protocols` - The servers to stop, those not running being left alone
+/// * `grace_period` - The longest time the servers give transfers to complete
+///
+/// # Returns
+/// Whether they all stopped in time
+pub fn stop_servers(sender: &broadcast::Sender<CommandMsg>, stats: &Stats, protocols: &[Protocol], grace_period: Duration) -> bool {
+    for protocol in protocols {
+        let stop = CommandMsg { action: ServerAction::Stop, protocol: protocol.clone(), ..Default::default() };
+        if let Err(e) = sender.send(stop) {
+            error!("Failed to send {} stop command: {}", protocol.to_string().to_uppercase(), e);
         }
-        count += 1;
     }
+    let stopped = wait_until(Some(grace_period + STOP_TIMEOUT), Duration::from_millis(10), || {
+        protocols.iter().all(|p| !matches!(stats.status(p), ServerStatus::Listening(_)))
+    });
+    if !stopped {
+        error!("Servers did not stop in time, exiting anyway");
+    }
+    stopped
+}
+
+/// Sends start commands for the enabled servers
+///
+/// Sends start messages for each server enabled in the configuration
+/// (either in the file or on the command line). Once they are bound,
+/// reports where (also to `ready_file`, if requested). Without exit
+/// conditions, blocks indefinitely waiting for the Ctrl+C handler to
+/// terminate the process. Otherwise stops the servers once any is met.
+///
+/// # Arguments
+/// * `config` - Settings resolved from the configuration file and command line
+/// * `ready_file` - Where to also write the addresses the servers are bound to
+/// * `conditions` - When to stop the servers and return
+/// * `channel` - The broadcast channel for sending commands to servers
+/// * `stats` - Statistics shared by all servers, used to follow their state
+///
+/// # Returns
+/// The status to exit the process with (see [`ExitConditions::wait`])
+pub fn server_starter_sender(config: &Config, ready_file: Option<&str>, conditions: &ExitConditions, channel: &DefaultChannel<CommandMsg>, stats: &Stats) -> i32 {
+    let requested = [Protocol::Http, Protocol::Ftp, Protocol::Tftp, Protocol::Dhcp];
+
+    let mut started = Vec::new();
+
+    // Check for each server enabled, and send messages accordingly to start each
+    for protocol in requested {
+        let Some(cmd) = CommandMsg::from_config(config, &protocol).filter(|cmd| cmd.action == ServerAction::Start) else { continue };
 
-    if cli_args.dhcp.is_some() {
-        cmd.protocol = Protocol::Dhcp;
-        cmd.port = cli_args.dhcp.unwrap() as u16;
         if let Err(e) = channel.sender.send(cmd.clone()) {
-            error!("Failed to send DHCP start command: {}", e);
+            error!("Failed to send {} start command: {}", cmd.protocol.to_string().to_uppercase(), e);
         }
-        count += 1;
+        started.push(cmd.protocol);
     }
 
-    if count == 0 {
+    // Servers may also be started later on from the web interface or the control socket
+    if started.is_empty() && config.admin.is_none() && config.control.is_none() {
         println!("No server specified. Use -h for help");
         exit(2);
     }
     else {
-        // Wait indefinitely for signals (Ctrl+C handler will terminate the process)
-        // This is more efficient than busy-waiting with sleep
-        info!("All servers started. Waiting for shutdown signal...");
-        std::thread::park();
+        let states = wait_ready(stats, &started, READY_TIMEOUT);
+        let report = ready_report(&states);
+
+        // Single line on stdout, for scripts to find out where each server ended up
+        println!("READY {}", report);
+        if let Some(ready_file) = ready_file {
+            if let Err(e) = std::fs::write(ready_file, &report) {
+                error!("Failed to write ready file {}: {}", ready_file, e);
+            }
+        }
+        snippets::log_client_commands(config, &states);
+
+        if conditions.is_empty() {
+            // Wait indefinitely for signals (Ctrl+C handler will terminate the process)
+            // This is more efficient than busy-waiting with sleep
+            info!("All servers started. Waiting for shutdown signal...");
+            loop {
+                std::thread::park();
+            }
+        }
+
+        let status = conditions.wait(stats, &started);
+        stop_servers(&channel.sender, stats, &started, config.grace_period);
+
+        for line in stats.summary() {
+            info!("{}", line);
+        }
+        status
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Direction;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_exit_conditions() {
+        let stats = Stats::default();
+        let protocols = [Protocol::Http];
+        assert!(ExitConditions::default().is_empty());
+
+        let timeout = Some(Duration::from_millis(20));
+        assert_eq!(ExitConditions { timeout, ..Default::default() }.wait(&stats, &protocols), 0);
+
+        let conditions = ExitConditions { max_transfers: Some(2), timeout, exit_after: Some("fw/b.bin".into()) };
+        stats.begin(&Protocol::Http, None, "fw/a.bin", Direction::Download, None).complete();
+        assert!(!conditions.transfers_done(&stats));
+        assert_eq!(conditions.wait(&stats, &protocols), EXIT_TIMEOUT);
+
+        stats.begin(&Protocol::Tftp, None, "/fw/b.bin", Direction::Download, None).complete();
+        assert!(conditions.transfers_done(&stats), "either condition is enough");
+        assert_eq!(conditions.wait(&stats, &protocols), 0);
+
+        let stats = Stats::default();
+        stats.set_status(&Protocol::Http, ServerStatus::Failed("boom".into()));
+        assert_eq!(ExitConditions { max_transfers: Some(1), ..Default::default() }.wait(&stats, &protocols), 1);
+    }
 
     #[test]
     fn test_protocol_to_string() {
@@ -319,7 +880,7 @@ mod tests {
         let all = [Protocol::Http, Protocol::Ftp, Protocol::Tftp, Protocol::Dhcp];
         for variant in &all {
             assert!(
-                PROTOCOL_LIST.iter().any(|p| *p == variant),
+                PROTOCOL_LIST.contains(&variant),
                 "Protocol {:?} missing from PROTOCOL_LIST", variant
             );
         }
@@ -344,91 +905,287 @@ mod tests {
         }
     }
 
-    // ── Server::start / Server::stop messaging ────────────────────────────────
+    // ── Shared runner lifecycle ───────────────────────────────────────────────
 
-    #[test]
-    fn test_start_sends_connect_true() {
-        let server = Server::default();
-        let mut rx = server.sender.subscribe();
+    struct FlagServer {
+        config: ServerConfig,
+        started: Arc<AtomicBool>,
+        stopped: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl ProtocolServer for FlagServer {
+        fn protocol(&self) -> Protocol {
+            Protocol::Custom("flag")
+        }
 
-        server.start().expect("start should succeed");
+        fn config(&self) -> &ServerConfig {
+            &self.config
+        }
 
-        let msg = rx.try_recv().expect("should have received a message after start");
-        assert!(msg.connect, "start() should send connect=true");
+        async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+            self.started.store(true, Ordering::SeqCst);
+            ctx.listening(vec![SocketAddr::new(self.config.bind_addresses[0], 1234)]);
+            ctx.shutdown.cancelled().await;
+            self.stopped.store(true, Ordering::SeqCst);
+            Ok(())
+        }
     }
 
-    #[test]
-    fn test_stop_sends_two_connect_false_messages() {
-        let server = Server::default();
-        let mut rx = server.sender.subscribe();
+    #[tokio::test]
+    async fn test_runner_serves_on_start_and_cancels_on_stop() {
+        let (server, started, stopped) = flag_server();
+        assert_eq!(server.protocol, Protocol::Custom("flag"));
+        assert_eq!(server.status(), ServerStatus::Idle);
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!started.load(Ordering::SeqCst), "backend should wait to be started");
+
+        server.start().unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(started.load(Ordering::SeqCst), "backend should serve after start");
+        assert!(!stopped.load(Ordering::SeqCst));
+        assert_eq!(server.bound_addr(), Some("127.0.0.1:1234".parse().unwrap()));
+
+        let listening = server.status();
+        server.stop();
+        assert_eq!(server.wait_change_from(listening).await, ServerStatus::Stopped);
+        assert!(stopped.load(Ordering::SeqCst), "backend should be cancelled after stop");
+        assert_eq!(server.bound_addr(), None);
+    }
 
-        server.stop().expect("stop should succeed");
+    // ── Server::start / Server::stop ──────────────────────────────────────────
 
-        let msg1 = rx.try_recv().expect("should have received first stop message");
-        let msg2 = rx.try_recv().expect("should have received second stop message");
-        assert!(!msg1.connect, "first stop message should have connect=false");
-        assert!(!msg2.connect, "second stop message should have connect=false");
-        assert!(rx.try_recv().is_err(), "stop() should send exactly 2 messages");
+    fn flag_server() -> (Server, Arc<AtomicBool>, Arc<AtomicBool>) {
+        let started = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server = Server::new(Box::new(FlagServer {
+            config: ServerConfig::default(),
+            started: started.clone(),
+            stopped: stopped.clone(),
+        }), Stats::default());
+        (server, started, stopped)
     }
 
     #[test]
-    fn test_start_then_stop_message_sequence() {
-        let server = Server::default();
-        let mut rx = server.sender.subscribe();
+    fn test_start_fails_without_backend() {
+        let result = Server::default().start();
+        assert!(result.unwrap_err().to_string().contains("No backend to start"));
+    }
 
+    #[tokio::test]
+    async fn test_start_and_stop_once() {
+        let (server, _, stopped) = flag_server();
         server.start().unwrap();
-        server.stop().unwrap();
+        let err = server.start().unwrap_err().to_string();
+        assert!(err.contains("already started"), "unexpected error: {}", err);
+
+        // Stopping again does nothing
+        server.stop();
+        server.stop();
+        assert_eq!(server.finished().await, ServerStatus::Stopped);
+        assert!(stopped.load(Ordering::SeqCst));
 
-        let msgs: Vec<_> = (0..3).filter_map(|_| rx.try_recv().ok()).collect();
-        assert_eq!(msgs.len(), 3, "expected 1 start + 2 stop messages");
-        assert!(msgs[0].connect,  "first message should be connect=true (start)");
-        assert!(!msgs[1].connect, "second message should be connect=false (stop 1)");
-        assert!(!msgs[2].connect, "third message should be connect=false (stop 2)");
+        // A stopped server is not started again, a new one is
+        assert!(server.start().is_err());
+        assert_eq!(server.status(), ServerStatus::Stopped);
     }
 
-    #[test]
-    fn test_start_fails_without_receiver() {
-        // A sender with no active subscribers returns an error on send.
-        let (tx, _rx) = broadcast::channel::<Message>(10);
-        // Drop _rx immediately so there are no subscribers
-        drop(_rx);
-
-        let server = Server {
-            sender: tx,
-            ..Server::default()
-        };
+    #[tokio::test]
+    async fn test_stop_before_start() {
+        let (server, started, _) = flag_server();
+        server.stop();
+        assert!(server.start().is_err());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!started.load(Ordering::SeqCst), "backend should not serve once stopped");
+        assert_eq!(server.status(), ServerStatus::Idle);
+    }
 
-        let result = server.start();
-        assert!(result.is_err(), "start() with no receivers should fail");
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("Error sending start message"), "unexpected error: {}", err);
+    #[tokio::test]
+    async fn test_drop_stops_backend() {
+        let (server, _, stopped) = flag_server();
+        let stats = server.stats.clone();
+        server.start().unwrap();
+        server.wait_change_from(ServerStatus::Idle).await;
+        drop(server);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while stats.status(&Protocol::Custom("flag")) != ServerStatus::Stopped {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("backend should stop once the server is dropped");
+        assert!(stopped.load(Ordering::SeqCst));
     }
 
-    #[test]
-    fn test_stop_fails_without_receiver() {
-        let (tx, _rx) = broadcast::channel::<Message>(10);
-        drop(_rx);
+    // ── Commands carried out in order ─────────────────────────────────────────
+
+    /// Binds its port for real, holding it a little while once stopped as
+    /// transfers being drained would
+    struct PortServer {
+        config: ServerConfig,
+        /// How many times it listened
+        serves: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ProtocolServer for PortServer {
+        fn protocol(&self) -> Protocol {
+            Protocol::Custom("port")
+        }
+
+        fn config(&self) -> &ServerConfig {
+            &self.config
+        }
+
+        async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+            let listener = tokio::net::TcpListener::bind(self.config.socket_addrs()[0]).await?;
+            ctx.listening(vec![listener.local_addr()?]);
+            self.serves.fetch_add(1, Ordering::SeqCst);
+            ctx.shutdown.cancelled().await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(())
+        }
+    }
+
+    /// Carries out the commands sent on the channel returned with [`PortServer`]s,
+    /// the command given starting one on a free port
+    fn port_servers(serves: &Arc<AtomicUsize>) -> (DefaultChannel<CommandMsg>, Stats, CommandMsg) {
+        let mut registry = ServerRegistry::empty();
+        let counter = serves.clone();
+        registry.register(Protocol::Custom("port"), move |config| Ok(Box::new(PortServer { config, serves: counter.clone() })));
+
+        let channel = DefaultChannel::default();
+        let stats = Stats::default();
+        server_starter_receiver(&channel, &registry, &stats);
 
-        let server = Server {
-            sender: tx,
-            ..Server::default()
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let cmd = CommandMsg {
+            action: ServerAction::Start,
+            port,
+            protocol: Protocol::Custom("port"),
+            bind_ip: "127.0.0.1".into(),
+            path: std::env::temp_dir().to_string_lossy().to_string(),
+            ..Default::default()
         };
+        (channel, stats, cmd)
+    }
 
-        let result = server.stop();
-        assert!(result.is_err(), "stop() with no receivers should fail");
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("Error sending first stop message"), "unexpected error: {}", err);
+    fn send(channel: &DefaultChannel<CommandMsg>, cmd: &CommandMsg, actions: &[ServerAction]) {
+        for action in actions {
+            channel.sender.send(CommandMsg { action: *action, ..cmd.clone() }).unwrap();
+        }
     }
 
-    #[test]
-    fn test_multiple_subscribers_all_receive_start() {
-        let server = Server::default();
-        let mut rx1 = server.sender.subscribe();
-        let mut rx2 = server.sender.subscribe();
+    /// Waits for the server to have listened `count` times and to be in the given state,
+    /// then checks it stays there
+    async fn settle(stats: &Stats, serves: &AtomicUsize, count: usize, status: fn(&ServerStatus) -> bool) {
+        let protocol = Protocol::Custom("port");
+        let settled = || serves.load(Ordering::SeqCst) == count && status(&stats.status(&protocol));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !settled() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap_or_else(|_| panic!("served {} time(s), now {:?}", serves.load(Ordering::SeqCst), stats.status(&protocol)));
 
-        server.start().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(settled(), "served {} time(s), now {:?}", serves.load(Ordering::SeqCst), stats.status(&protocol));
+    }
+
+    fn listening(status: &ServerStatus) -> bool {
+        matches!(status, ServerStatus::Listening(_))
+    }
+
+    #[tokio::test]
+    async fn test_rapid_toggles() {
+        let serves = Arc::new(AtomicUsize::new(0));
+        let (channel, stats, cmd) = port_servers(&serves);
+
+        // Each start binds the port again, once the server before let it go
+        for _ in 0..10 {
+            send(&channel, &cmd, &[ServerAction::Start, ServerAction::Stop]);
+        }
+        send(&channel, &cmd, &[ServerAction::Start]);
+        settle(&stats, &serves, 11, listening).await;
+        tokio::net::TcpStream::connect(("127.0.0.1", cmd.port)).await.expect("port should be listened on");
+
+        send(&channel, &cmd, &[ServerAction::Stop, ServerAction::Start, ServerAction::Stop]);
+        settle(&stats, &serves, 12, |status| *status == ServerStatus::Stopped).await;
+        std::net::TcpListener::bind(("127.0.0.1", cmd.port)).expect("port should be free once stopped");
+    }
+
+    #[tokio::test]
+    async fn test_restart_and_redundant_commands() {
+        let serves = Arc::new(AtomicUsize::new(0));
+        let (channel, stats, cmd) = port_servers(&serves);
+
+        // Nothing to stop or reconfigure yet, then a single server however many starts
+        send(&channel, &cmd, &[ServerAction::Stop, ServerAction::Reconfigure, ServerAction::Start, ServerAction::Start]);
+        settle(&stats, &serves, 1, listening).await;
+
+        send(&channel, &cmd, &[ServerAction::Restart, ServerAction::Restart]);
+        settle(&stats, &serves, 3, listening).await;
+
+        // Restarting a stopped server starts it
+        send(&channel, &cmd, &[ServerAction::Stop, ServerAction::Restart]);
+        settle(&stats, &serves, 4, listening).await;
+    }
+
+    /// Serves whatever its live path currently is
+    struct LiveServer {
+        config: ServerConfig,
+        path: Live<PathBuf>,
+    }
+
+    #[async_trait]
+    impl ProtocolServer for LiveServer {
+        fn protocol(&self) -> Protocol {
+            Protocol::Custom("live")
+        }
+
+        fn config(&self) -> &ServerConfig {
+            &self.config
+        }
+
+        async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+            ctx.shutdown.cancelled().await;
+            Ok(())
+        }
+
+        fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+            let next = reloaded_backend::<LiveServer>(next)?;
+            self.path.set(next.path.get().as_ref().clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let live = |path: &str, port| {
+            let config = ServerConfig { path: Arc::new(path.into()), port, ..Default::default() };
+            Box::new(LiveServer { path: Live::new(path.into()), config })
+        };
+        let first = live("/srv/a", 8080);
+        let path = first.path.clone();
+        let mut server = Server::new(first, Stats::default());
+
+        // The backend takes the new path, the server keeps its port
+        server.reload(live("/srv/b", 9090)).unwrap();
+        assert_eq!(*path.get(), PathBuf::from("/srv/b"));
+        assert_eq!(*server.config.path, PathBuf::from("/srv/b"));
+        assert_eq!(server.config.port, 8080);
 
-        assert!(rx1.try_recv().expect("rx1 should receive").connect);
-        assert!(rx2.try_recv().expect("rx2 should receive").connect);
+        // Backends of another kind, or without reload, are refused
+        assert!(server.reload(Box::new(FlagServer {
+            config: ServerConfig::default(),
+            started: Default::default(),
+            stopped: Default::default(),
+        })).is_err());
+        assert_eq!(*path.get(), PathBuf::from("/srv/b"));
+        let mut flag = Server::new(Box::new(FlagServer {
+            config: ServerConfig::default(),
+            started: Default::default(),
+            stopped: Default::default(),
+        }), Stats::default());
+        assert!(flag.reload(live("/srv/b", 0)).is_err());
     }
 }
@@ -1,107 +1,295 @@
-use log::{info, debug, error};
+use log::{info, debug};
 
-use super::{Protocol, Server};
+use super::{reloaded_backend, Live, Protocol, ProtocolServer, ServeContext, ServerConfig, Vfs};
 
 // Create the TFTP server.
-use async_tftp::server::TftpServerBuilder;
-use std::{net::IpAddr, ops::Deref, path::PathBuf, str::FromStr};
-use crate::utils::validation;
-use std::sync::Arc;
+use async_tftp::packet;
+use async_tftp::server::handlers::{DirHandler, DirHandlerMode};
+use async_tftp::server::{Handler, TftpServerBuilder};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use futures_io::AsyncRead;
+use crate::utils::net;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use crate::{Direction, QuickServeError, QuickServeResult, Stats, TransferHandle};
+use crate::access_log;
+use async_trait::async_trait;
 
+/// Times a block is resent without being acknowledged before giving up
+const MAX_SEND_RETRIES: u32 = 100;
 
-pub trait TFTPRunner {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> where Self: Sized;
-    fn runner(&self);
+/// Shortest timeout clients may negotiate (the `timeout` option being in seconds)
+const MIN_CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// TFTP specific settings
+#[derive(Clone, Debug)]
+pub struct TftpConfig {
+    pub server: ServerConfig,
+    /// Time to wait for an acknowledgement before resending a block
+    pub timeout: Duration,
+    /// Largest block size negotiated with clients, if limited
+    pub block_size_limit: Option<u16>,
+}
+
+impl Default for TftpConfig {
+    fn default() -> Self {
+        TftpConfig {
+            server: ServerConfig::default(),
+            timeout: Duration::from_secs(3),
+            block_size_limit: None,
+        }
+    }
+}
+
+impl From<ServerConfig> for TftpConfig {
+    fn from(server: ServerConfig) -> Self {
+        TftpConfig { server, ..Default::default() }
+    }
+}
+
+/// Reader following the transfer of a file, complete once dropped if the
+/// last block was acknowledged
+///
+/// async-tftp reads the last block once the previous ones are acknowledged,
+/// then resends it until it is acknowledged in turn, or until all its
+/// retries timed out, and drops the reader either way. So the transfer is
+/// complete if the reader is dropped before the retries could run out.
+struct TransferReader {
+    inner: <DirHandler as Handler>::Reader,
+    transfer: Option<TransferHandle>,
+    /// When the end of the file was read, the last block being sent right after
+    eof_at: Option<Instant>,
+    /// Shortest time the last block is resent for before giving up
+    give_up_after: Duration,
+}
+
+impl TransferReader {
+    fn completed(&self) -> bool {
+        self.eof_at.is_some_and(|eof_at| eof_at.elapsed() < self.give_up_after)
+    }
+}
+
+impl AsyncRead for TransferReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            if n == 0 && !buf.is_empty() {
+                self.eof_at.get_or_insert_with(Instant::now);
+            } else if let Some(transfer) = &self.transfer {
+                transfer.progress(n as u64);
+            }
+        }
+        res
+    }
+}
+
+impl Drop for TransferReader {
+    fn drop(&mut self) {
+        let Some(transfer) = self.transfer.take() else { return };
+        let completed = self.completed();
+        if let Some(snapshot) = transfer.snapshot() {
+            access_log::xferlog(&snapshot, None, completed);
+        }
+        // Otherwise dropping the handle records a failure
+        if completed {
+            transfer.complete();
+        }
+    }
 }
 
-impl TFTPRunner for Server {
-    fn new(path: PathBuf, bind_ip: String, port: u16) -> Result<Self, crate::QuickServeError> {
-        let mut s = Server::default();
-        
+/// Read only handler serving the [`Vfs`] and feeding the statistics
+struct VfsHandler {
+    /// Replaced on reload, each request taking the current one
+    vfs: Live<Vfs>,
+    stats: Stats,
+    /// Cancelled once stopped, new requests being refused from then on
+    shutdown: CancellationToken,
+    /// See [`TransferReader::give_up_after`]
+    give_up_after: Duration,
+}
+
+impl VfsHandler {
+    /// Opens a file of the served tree, through a handler of the directory it ends up in
+    async fn open(&self, client: &SocketAddr, path: &Path) -> Result<(TransferReader, Option<u64>), packet::Error> {
+        let requested = path.to_str().ok_or(packet::Error::FileNotFound)?;
+        let file_path = self.vfs.get().resolve(requested).map_err(|e| {
+            debug!("TFTP path validation failed for '{}': {}", requested, e);
+            packet::Error::PermissionDenied
+        })?;
+
+        let (Some(dir), Some(name)) = (file_path.parent(), file_path.file_name()) else {
+            return Err(packet::Error::FileNotFound);
+        };
+        let mut dir = DirHandler::new(dir, DirHandlerMode::ReadOnly)
+            .map_err(|_| packet::Error::FileNotFound)?;
+        let (inner, size) = dir.read_req_open(client, Path::new(name)).await?;
+
+        let transfer = self.stats.begin(&Protocol::Tftp, Some(*client), requested, Direction::Download, size);
+        Ok((TransferReader { inner, transfer: Some(transfer), eof_at: None, give_up_after: self.give_up_after }, size))
+    }
+}
+
+impl Handler for VfsHandler {
+    type Reader = TransferReader;
+    type Writer = <DirHandler as Handler>::Writer;
+
+    async fn read_req_open(&mut self, client: &SocketAddr, path: &Path) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        self.stats.request(&Protocol::Tftp);
+        // The transfers going on are given time to complete, but no new ones
+        let res = match self.shutdown.is_cancelled() {
+            true => Err(packet::Error::Msg("Server shutting down".into())),
+            false => self.open(client, path).await,
+        };
+        match &res {
+            Ok(_) => info!(protocol = "tftp", client:% = client, event = "download", path:% = path.display(); "TFTP sending {} to {}", path.display(), client),
+            Err(e) => {
+                info!(protocol = "tftp", client:% = client, event = "failed", path:% = path.display(); "TFTP request for {} from {} failed: {:?}", path.display(), client, e);
+                self.stats.error(&Protocol::Tftp);
+            }
+        }
+        res
+    }
+
+    async fn write_req_open(&mut self, _client: &SocketAddr, _path: &Path, _size: Option<u64>) -> Result<Self::Writer, packet::Error> {
+        Err(packet::Error::IllegalOperation)
+    }
+}
+
+/// Serves files over TFTP (read only)
+pub struct TftpServer {
+    config: TftpConfig,
+    /// The served tree, replaced on reload
+    vfs: Live<Vfs>,
+}
+
+impl TftpServer {
+    pub fn new(config: TftpConfig) -> Result<Self, crate::QuickServeError> {
         // Validate inputs with proper error handling
-        validation::validate_path(&path)?;
-        validation::validate_ip_port(&bind_ip, port)?;
-        
-        let path = validation::ensure_trailing_slash(&path);
-        s.path = Arc::new(path);
-        s.bind_address = IpAddr::from_str(&bind_ip)
-            .map_err(|e| crate::QuickServeError::validation(format!("Invalid IP address '{}': {}", bind_ip, e)))?;
-        s.port = port;
-
-        s.protocol = Protocol::Tftp;
-        TFTPRunner::runner(&s);
-        Ok(s)
-    }
-    fn runner(&self) {
-        let mut receiver = self.sender.subscribe();
-        
-        let bind_address = self.bind_address.clone();
-        let port = self.port;
-        let path = self.path.clone();
-
-        tokio::spawn(async move {
-            loop {
-                // Get notified about the server's spawned task
-                let m = match receiver.recv().await {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        error!("Failed to receive message in TFTP runner: {}", e);
-                        break;
-                    }
-                };
-
-                if m.connect {
-                    info!("Starting TFTP server on {}:{}", bind_address, port);
-                    let tsk = tokio::spawn(async move {
-                        let addr = format!("{}:{}", bind_address, port);
-                        
-                        // Build TFTP server with proper error handling
-                        let tftpd_result = TftpServerBuilder::with_dir_ro(path.deref())
-                            .map_err(|e| format!("Failed to create TFTP server: {}", e))
-                            .and_then(|builder| {
-                                addr.parse()
-                                    .map_err(|e| format!("Invalid address '{}': {}", addr, e))
-                                    .map(|parsed_addr| builder.bind(parsed_addr))
-                            });
-
-                        match tftpd_result {
-                            Ok(builder) => {
-                                match builder.build().await {
-                                    Ok(tftpd) => {
-                                        info!("TFTP server listening on {}", addr);
-                                        if let Err(e) = tftpd.serve().await {
-                                            error!("TFTP server error: {}", e);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to build TFTP server: {}", e);
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to create TFTP server: {}", e);
-                            }
-                        }
-                    });
-
-                    // Wait for stop command
-                    match receiver.recv().await {
-                        Ok(_) => {
-                            info!("Stop command received, shutting down TFTP server");
-                            tsk.abort();
-                            debug!("TFTP server stopped");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("Failed to receive stop command: {}", e);
-                            tsk.abort();
-                            break;
-                        }
-                    }
-                }
+        let vfs = config.server.vfs();
+        vfs.validate()?;
+        Ok(TftpServer { config, vfs: Live::new(vfs) })
+    }
+
+    /// Builds a TFTP server bound to the given address
+    async fn build(&self, addr: SocketAddr, ctx: &ServeContext) -> QuickServeResult<async_tftp::server::TftpServer<VfsHandler>> {
+        // Each retry waits for the timeout, possibly shortened by the client
+        let give_up_after = self.config.timeout.min(MIN_CLIENT_TIMEOUT) * (MAX_SEND_RETRIES + 1);
+        let handler = VfsHandler { vfs: self.vfs.clone(), stats: ctx.stats.clone(), shutdown: ctx.shutdown.clone(), give_up_after };
+
+        let socket = net::bind_udp(addr)
+            .map_err(|e| QuickServeError::Network(format!("Failed to bind TFTP server to {}: {}", addr, e)))?;
+
+        let mut builder = TftpServerBuilder::with_handler(handler)
+            .std_socket(socket)
+            .map_err(|e| QuickServeError::Network(format!("Failed to build TFTP server: {}", e)))?
+            .timeout(self.config.timeout)
+            .max_send_retries(MAX_SEND_RETRIES);
+        if let Some(limit) = self.config.block_size_limit {
+            builder = builder.block_size_limit(limit);
+        }
+
+        builder
+            .build().await
+            .map_err(|e| QuickServeError::Network(format!("Failed to build TFTP server: {}", e)))
+    }
+}
+
+#[async_trait]
+impl ProtocolServer for TftpServer {
+    fn protocol(&self) -> Protocol {
+        Protocol::Tftp
+    }
+
+    fn config(&self) -> &ServerConfig {
+        &self.config.server
+    }
+
+    async fn serve(&self, ctx: ServeContext) -> QuickServeResult<()> {
+        // Bind all addresses first, so that none is served if any fails
+        let mut servers = Vec::new();
+        for addr in self.config.server.socket_addrs() {
+            info!("Starting TFTP server on {}", addr);
+            servers.push(self.build(addr, &ctx).await?);
+        }
+
+        let mut addrs = Vec::new();
+        for tftpd in &servers {
+            let addr = tftpd.listen_addr()
+                .map_err(|e| QuickServeError::Network(format!("Failed to get TFTP listen address: {}", e)))?;
+            info!("TFTP server listening on {}", addr);
+            addrs.push(addr);
+        }
+        ctx.listening(addrs);
+
+        let mut tasks = JoinSet::new();
+        for tftpd in servers {
+            tasks.spawn(async move {
+                tftpd.serve().await
+                    .map_err(|e| QuickServeError::Network(format!("TFTP server error: {}", e)))
+            });
+        }
+
+        tokio::select! {
+            _ = ctx.shutdown.cancelled() => {
+                // Transfers run within the servers, which are aborted when dropped
+                ctx.drain(ctx.transfers_done()).await;
+                debug!("TFTP server stopped");
+                Ok(())
             }
-        });
+            Some(res) = tasks.join_next() => res?,
+        }
+    }
+
+    /// Only the served tree is replaced, the timeout and block size applying from the next start
+    fn reload(&self, next: &dyn ProtocolServer) -> QuickServeResult<()> {
+        let next = reloaded_backend::<TftpServer>(next)?;
+        self.vfs.set((*next.vfs.get()).clone());
+        Ok(())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransferState;
 
+    async fn read_to_end(reader: &mut TransferReader) {
+        let mut buf = [0u8; 512];
+        while std::future::poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf)).await.unwrap() > 0 {}
+    }
+
+    #[tokio::test]
+    async fn test_transfer_completed_once_last_block_acknowledged() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.bin"), b"data").unwrap();
+        let stats = Stats::default();
+        let handler = |give_up_after| VfsHandler {
+            vfs: Live::new(Vfs::new(dir.path(), Vec::new())),
+            stats: stats.clone(),
+            shutdown: CancellationToken::new(),
+            give_up_after,
+        };
+        let client: SocketAddr = "127.0.0.1:1069".parse().unwrap();
+
+        // Dropped right after the end of the file: the last block was acknowledged
+        let (mut reader, _) = handler(Duration::from_secs(60)).open(&client, Path::new("a.bin")).await.unwrap();
+        read_to_end(&mut reader).await;
+        drop(reader);
+        assert_eq!(stats.finished().last().unwrap().state, TransferState::Completed);
+
+        // Dropped once the retries of the last block ran out
+        let (mut reader, _) = handler(Duration::ZERO).open(&client, Path::new("a.bin")).await.unwrap();
+        read_to_end(&mut reader).await;
+        drop(reader);
+        assert_eq!(stats.finished().last().unwrap().state, TransferState::Failed);
+
+        // Dropped before the end
+        let (reader, _) = handler(Duration::from_secs(60)).open(&client, Path::new("a.bin")).await.unwrap();
+        drop(reader);
+        assert_eq!(stats.finished().last().unwrap().state, TransferState::Failed);
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::access::AccessMatcher;
+use crate::utils::validation;
+use crate::{QuickServeError, QuickServeResult};
+
+pub use crate::utils::access::AccessRules;
+pub use crate::utils::validation::SymlinkPolicy;
+
+
+/// A directory or single file grafted into the served tree
+///
+/// Written as `/AT=SOURCE`, e.g. `/fw=/opt/firmware` serves the content of
+/// `/opt/firmware` under `fw/`, and `/kernel=/build/out/Image` serves that
+/// single file as `kernel`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Mount {
+    /// Where it shows up in the served tree, without leading or trailing slashes
+    pub at: String,
+    /// The directory or file served there
+    pub source: PathBuf,
+}
+
+impl Mount {
+    /// Creates a mount, checking the mount point
+    ///
+    /// # Arguments
+    /// * `at` - Where it shows up in the served tree, e.g. `/fw`
+    /// * `source` - The directory or file served there
+    ///
+    /// # Returns
+    /// * `Ok(Mount)` - The mount, with a normalized mount point
+    /// * `Err(QuickServeError)` - If the mount point is the root or not a valid path
+    pub fn new(at: &str, source: impl Into<PathBuf>) -> QuickServeResult<Self> {
+        let at = at.trim().trim_matches('/');
+        if at.is_empty() {
+            return Err(QuickServeError::validation("Cannot mount over the served root"));
+        }
+        // The mount point is subject to the same rules as any requested path
+        validation::validate_file_path(&PathBuf::new(), at, SymlinkPolicy::Follow)?;
+
+        Ok(Mount { at: at.to_string(), source: source.into() })
+    }
+
+    /// Returns the part of a requested path falling inside this mount, if any
+    ///
+    /// e.g. `a.bin` for `fw/a.bin` when mounted at `fw`, or an empty
+    /// string for the mount point itself.
+    fn strip<'a>(&self, requested: &'a str) -> Option<&'a str> {
+        let rest = requested.strip_prefix(self.at.as_str())?;
+        if rest.is_empty() {
+            Some(rest)
+        } else {
+            rest.strip_prefix('/')
+        }
+    }
+}
+
+impl FromStr for Mount {
+    type Err = QuickServeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (at, source) = s.split_once('=')
+            .ok_or_else(|| QuickServeError::validation(format!("Invalid mount '{}', expected /AT=SOURCE", s)))?;
+        if source.trim().is_empty() {
+            return Err(QuickServeError::validation(format!("Invalid mount '{}', the source cannot be empty", s)));
+        }
+        Mount::new(at, source.trim())
+    }
+}
+
+impl TryFrom<String> for Mount {
+    type Error = QuickServeError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Mount> for String {
+    fn from(mount: Mount) -> Self {
+        mount.to_string()
+    }
+}
+
+impl fmt::Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/{}={}", self.at, self.source.display())
+    }
+}
+
+/// The tree served by the file serving backends
+///
+/// A root directory, with any number of [`Mount`]s grafted into it. Every
+/// requested path is mapped to the file system through [`Vfs::resolve`], so
+/// that HTTP, FTP and TFTP all see the same tree.
+#[derive(Clone, Debug, Default)]
+pub struct Vfs {
+    root: PathBuf,
+    /// Longest mount points first, so that nested mounts take precedence
+    mounts: Vec<Mount>,
+    symlinks: SymlinkPolicy,
+    access: AccessMatcher,
+}
+
+impl Vfs {
+    /// Creates the tree out of a root directory and the mounts grafted into it
+    pub fn new(root: impl Into<PathBuf>, mut mounts: Vec<Mount>) -> Self {
+        mounts.sort_by_key(|m| std::cmp::Reverse(m.at.len()));
+        Vfs { root: root.into(), mounts, symlinks: SymlinkPolicy::default(), access: AccessMatcher::default() }
+    }
+
+    /// Sets how links found below the root and mounts are handled
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets the rules deciding what may be served
+    pub fn with_access(mut self, rules: &AccessRules) -> Self {
+        self.access = rules.matcher();
+        self
+    }
+
+    /// Checks that the root directory and the source of every mount exist,
+    /// and are not denied by the access rules
+    pub fn validate(&self) -> QuickServeResult<()> {
+        validation::validate_path(&self.root, &self.access)?;
+        for mount in &self.mounts {
+            if !mount.source.exists() {
+                return Err(QuickServeError::validation(format!("Mount source does not exist: {}", mount.source.display())));
+            }
+            self.access.check_dir(&mount.source.canonicalize()?)?;
+        }
+        Ok(())
+    }
+
+    /// Maps a requested path to the file system
+    ///
+    /// The path is normalized first (see [`validation::normalize`]), and then
+    /// matched against the mount points (the longest one winning), falling
+    /// back to the root directory otherwise. Either way, it is checked with
+    /// [`validation::validate_file_path`] against the directory it ends up in,
+    /// following the tree's [`SymlinkPolicy`]. Paths denied by the
+    /// [`AccessRules`] are refused, both as requested and once their links
+    /// are resolved.
+    ///
+    /// # Arguments
+    /// * `requested` - The requested path, relative to the served tree. A
+    ///   single leading slash is accepted.
+    ///
+    /// # Returns
+    /// * `Ok(PathBuf)` - Where the path is found in the file system (not necessarily existing)
+    /// * `Err(QuickServeError)` - If the path is invalid or a security risk
+    pub fn resolve(&self, requested: &str) -> QuickServeResult<PathBuf> {
+        let requested = in_tree(requested)?;
+        let requested = requested.as_str();
+        self.access.check_file(requested)?;
+
+        let (base, rest) = match self.mounts.iter().find_map(|m| m.strip(requested).map(|rest| (m, rest))) {
+            Some((mount, "")) => return Ok(mount.source.clone()),
+            Some((mount, _)) if mount.source.is_file() => {
+                return Err(QuickServeError::validation(format!("Not a directory: /{}", mount.at)));
+            }
+            Some((mount, rest)) => (&mount.source, rest),
+            None => (&self.root, requested),
+        };
+        let path = validation::validate_file_path(base, rest, self.symlinks)?;
+        self.check_real(base, &path)?;
+        Ok(path)
+    }
+
+    /// Checks where a path actually leads to against the access rules, for
+    /// links not to get around them: the absolute rules on its real location,
+    /// and the relative ones on that location within the real base directory
+    fn check_real(&self, base: &Path, path: &Path) -> QuickServeResult<()> {
+        let real = validation::real_path(path)
+            .map_err(|e| QuickServeError::validation(format!("Cannot resolve path: {}", e)))?;
+        self.access.check_dir(&real)?;
+        if let Ok(inside) = real.strip_prefix(validation::real_path(base)?) {
+            self.access.check_file(&inside.to_string_lossy())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the mount points showing up directly in a directory
+    ///
+    /// e.g. `["fw", "images"]` for the root, with `/fw` and `/images/extra`
+    /// mounted. Used to list mount points next to the directory's own entries.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory, relative to the served tree
+    pub fn mount_points(&self, dir: &str) -> Vec<String> {
+        let Ok(dir) = in_tree(dir) else { return Vec::new() };
+
+        let mut names: Vec<String> = Vec::new();
+        for mount in &self.mounts {
+            let rest = if dir.is_empty() {
+                Some(mount.at.as_str())
+            } else {
+                mount.at.strip_prefix(dir.as_str()).and_then(|r| r.strip_prefix('/'))
+            };
+            if let Some(name) = rest.and_then(|r| r.split('/').next()) {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        names
+    }
+
+    /// Whether a path is a mount point itself, e.g. `fw` when `/fw` is mounted
+    pub fn is_mount_point(&self, path: &str) -> bool {
+        in_tree(path).is_ok_and(|path| self.mounts.iter().any(|m| m.at == path))
+    }
+
+    /// Whether a path only exists as the parent of mount points
+    ///
+    /// e.g. `images` when `/images/extra` is mounted but the root has no `images` directory.
+    pub fn is_virtual_dir(&self, path: &str) -> bool {
+        !self.mount_points(path).is_empty() && self.resolve(path).map(|p| !p.is_dir()).unwrap_or(false)
+    }
+}
+
+/// Normalizes a requested path, e.g. `fw/a.bin` for `/./fw/a.bin`, for the
+/// rules and mount points to be checked against what is actually served
+fn in_tree(requested: &str) -> QuickServeResult<String> {
+    let requested = requested.strip_prefix('/').unwrap_or(requested);
+    let normalized = validation::normalize(requested.strip_suffix('/').unwrap_or(requested))?;
+    Ok(normalized.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::access::AccessRules;
+
+    fn vfs() -> Vfs {
+        Vfs::new("/srv/root", vec![
+            "/fw=/opt/firmware".parse().unwrap(),
+            "/kernel=/build/out/Image".parse().unwrap(),
+            "/fw/extra=/opt/extra".parse().unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_parse_mount() {
+        let mount: Mount = "/fw/=/opt/firmware".parse().unwrap();
+        assert_eq!(mount, Mount { at: "fw".into(), source: "/opt/firmware".into() });
+        assert_eq!(mount.to_string(), "/fw=/opt/firmware");
+
+        assert!("/fw".parse::<Mount>().is_err(), "missing source");
+        assert!("/fw=".parse::<Mount>().is_err(), "empty source");
+        assert!("/=/opt".parse::<Mount>().is_err(), "the root cannot be mounted over");
+        assert!("/../up=/opt".parse::<Mount>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_mounts_and_root() {
+        let vfs = vfs();
+        assert_eq!(vfs.resolve("fw/a.bin").unwrap(), PathBuf::from("/opt/firmware/a.bin"));
+        assert_eq!(vfs.resolve("/fw/a.bin").unwrap(), PathBuf::from("/opt/firmware/a.bin"));
+        assert_eq!(vfs.resolve("fw").unwrap(), PathBuf::from("/opt/firmware"));
+        assert_eq!(vfs.resolve("fw/extra/b.bin").unwrap(), PathBuf::from("/opt/extra/b.bin"), "longest mount wins");
+        assert_eq!(vfs.resolve("kernel").unwrap(), PathBuf::from("/build/out/Image"));
+        assert_eq!(vfs.resolve("fwx/a.bin").unwrap(), PathBuf::from("/srv/root/fwx/a.bin"), "only whole components match");
+        assert_eq!(vfs.resolve("other.txt").unwrap(), PathBuf::from("/srv/root/other.txt"));
+    }
+
+    #[test]
+    fn test_resolve_rejects_traversal() {
+        let vfs = vfs();
+        assert!(vfs.resolve("fw/../../etc/passwd").is_err());
+        assert!(vfs.resolve("../etc/passwd").is_err());
+        assert!(vfs.resolve("//etc/passwd").is_err());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let vfs = Vfs::new("/srv/root", vec![Mount::new("kernel", file.path()).unwrap()]);
+        assert!(vfs.resolve("kernel/anything").is_err(), "a file mount has no children");
+    }
+
+    #[test]
+    fn test_resolve_normalized() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.bin"), b"").unwrap();
+        let root = dir.path().to_path_buf();
+        let vfs = Vfs::new(&root, vec!["/fw=/opt/firmware".parse().unwrap()]).with_access(&AccessRules::default());
+
+        // Rules are checked against what is served, not against `.` components
+        assert_eq!(vfs.resolve("./a.bin").unwrap(), root.join("a.bin"));
+        assert_eq!(vfs.resolve("/./fw/./a.bin").unwrap(), PathBuf::from("/opt/firmware/a.bin"));
+        assert!(vfs.resolve("./.env").unwrap_err().to_string().contains("Access denied"));
+        assert!(vfs.is_mount_point("./fw/") && !vfs.mount_points("./").is_empty());
+
+        // `..` is refused as traversal, whether it stays in the tree or not
+        let err = vfs.resolve("sub/../a.bin").unwrap_err().to_string();
+        assert!(err.contains("traversal") && !err.contains("Access denied"), "unexpected error: {}", err);
+        assert!(vfs.resolve("fw/../.env").unwrap_err().to_string().contains("traversal"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_checks_link_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join(".git")).unwrap();
+        std::fs::write(root.join(".git/config"), b"").unwrap();
+        std::os::unix::fs::symlink(".git/config", root.join("cfg")).unwrap();
+        std::os::unix::fs::symlink(".git", root.join("repo")).unwrap();
+        std::os::unix::fs::symlink("/etc", root.join("system")).unwrap();
+
+        let vfs = Vfs::new(&root, vec![]).with_access(&AccessRules::default());
+        assert!(vfs.resolve("cfg").unwrap_err().to_string().contains("Access denied"), "link into .git");
+        assert!(vfs.resolve("repo/config").is_err(), "link to .git");
+        assert!(vfs.with_symlinks(SymlinkPolicy::Follow).resolve("system/passwd").is_err(), "link into /etc");
+    }
+
+    #[test]
+    fn test_resolve_absolute_rules_below_root() {
+        let vfs = Vfs::new("/", vec![]).with_access(&AccessRules::default());
+        assert!(vfs.validate().is_ok(), "the root itself is not denied");
+        assert!(vfs.resolve("etc/passwd").is_err());
+        assert!(vfs.resolve("proc/self/environ").is_err());
+        assert!(vfs.resolve("tmp").is_ok());
+    }
+
+    #[test]
+    fn test_mount_points() {
+        let vfs = Vfs::new("/srv/root", vec![
+            "/fw=/opt/firmware".parse().unwrap(),
+            "/images/extra=/opt/extra".parse().unwrap(),
+        ]);
+        assert_eq!(vfs.mount_points(""), vec!["fw", "images"]);
+        assert_eq!(vfs.mount_points("/images/"), vec!["extra"]);
+        assert!(vfs.mount_points("fw").is_empty());
+    }
+}
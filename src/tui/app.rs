@@ -0,0 +1,660 @@
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{error, warn, Level};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::broadcast::Sender;
+
+use crate::utils::logger::LogEntry;
+use crate::utils::validation;
+use crate::{format_bytes, stop_servers, CommandMsg, Config, Protocol, ServerAction, ServerStatus, Stats, PROTOCOL_LIST};
+
+/// How often the screen is redrawn, when no key is pressed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Transfers listed at most, the others being only counted
+const MAX_TRANSFERS_SHOWN: usize = 5;
+
+/// Lines scrolled at once through the logs
+const LOG_PAGE: usize = 10;
+
+/// Levels toggled with the keys 1 to 5
+const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+/// Columns of the servers table the cursor moves through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Start,
+    Port,
+    BindIp,
+    Path,
+}
+
+const COLUMNS: [Column; 4] = [Column::Start, Column::Port, Column::BindIp, Column::Path];
+
+/// Full-screen terminal interface, for when there is no display (e.g. over SSH)
+///
+/// The first row of the servers table holds the global bind IP and
+/// directory, and the following ones each protocol's server. As in the
+/// GUI, empty per protocol fields fall back to the global ones.
+pub struct App {
+    protocols: Vec<CommandMsg>,
+    bind_ip: String,
+    path: String,
+
+    // Cursor in the servers table, and the text of the cell being edited
+    row: usize,
+    column: usize,
+    editing: Option<String>,
+
+    // Log view filters, and how far up the logs are scrolled (0 follows new entries)
+    hidden_levels: HashSet<Level>,
+    hidden_protocols: HashSet<Option<Protocol>>,
+    log_search: String,
+    searching: bool,
+    scroll: usize,
+
+    quit: bool,
+    /// The longest time the servers give transfers to complete, once quitting
+    grace_period: Duration,
+    sender: Sender<CommandMsg>,
+    logs: Arc<Mutex<Vec<LogEntry>>>,
+    stats: Stats,
+}
+
+impl App {
+    /// Takes the initial settings from the configuration. The servers it
+    /// enables are started once running.
+    pub fn new(config: &Config, sender: Sender<CommandMsg>, logs: Arc<Mutex<Vec<LogEntry>>>, stats: Stats) -> Self {
+        let protocols = PROTOCOL_LIST.iter().map(|protocol| {
+            let mut msg = CommandMsg::new(protocol);
+            if let Some((enabled, port)) = config.server(protocol) {
+                msg.action = if enabled { ServerAction::Start } else { ServerAction::Stop };
+                msg.port = port;
+            }
+            let (bind_ip, path) = config.overrides(protocol);
+            msg.bind_ip = bind_ip.cloned().unwrap_or_default();
+            msg.path = path.cloned().unwrap_or_default();
+            msg.mounts = config.mounts.clone();
+            msg.symlinks = config.symlinks;
+            msg.access = config.access.clone();
+            msg
+        }).collect();
+
+        App {
+            protocols,
+            bind_ip: config.bind_ip.clone(),
+            path: config.serve_dir.clone(),
+            row: 1,
+            column: 0,
+            editing: None,
+            hidden_levels: HashSet::new(),
+            hidden_protocols: HashSet::new(),
+            log_search: String::new(),
+            searching: false,
+            scroll: 0,
+            quit: false,
+            grace_period: config.grace_period,
+            sender,
+            logs,
+            stats,
+        }
+    }
+
+    /// Takes over the terminal until the user quits, then stops the servers,
+    /// waiting for the transfers going on
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+
+        let running: Vec<Protocol> = self.protocols.iter().filter(|p| p.action == ServerAction::Start).map(|p| p.protocol.clone()).collect();
+        stop_servers(&self.sender, &self.stats, &running, self.grace_period);
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        for i in 0..self.protocols.len() {
+            if self.protocols[i].action == ServerAction::Start {
+                self.send(i);
+            }
+        }
+
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(REFRESH_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The command starting or stopping a server, empty fields falling
+    /// back to the global bind IP and directory
+    fn command(&self, p: &CommandMsg) -> CommandMsg {
+        let mut msg = p.clone();
+        if msg.bind_ip.is_empty() {
+            msg.bind_ip = self.bind_ip.clone();
+        }
+        if msg.path.is_empty() {
+            msg.path = self.path.clone();
+        }
+        msg
+    }
+
+    /// Sends the command of a protocol's server, as it is set. Returns whether it was sent
+    fn send(&self, index: usize) -> bool {
+        let msg = self.command(&self.protocols[index]);
+        if msg.action == ServerAction::Start {
+            if let Err(e) = validation::parse_bind_addresses(&msg.bind_ip, msg.port) {
+                error!("{}", e);
+                return false;
+            }
+        }
+        match self.sender.send(msg) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to send {} command: {}", self.protocols[index].protocol.to_string().to_uppercase(), e);
+                false
+            }
+        }
+    }
+
+    /// Starts or stops the server of the selected row
+    fn toggle(&mut self) {
+        let Some(index) = self.row.checked_sub(1) else { return };
+        let previous = self.protocols[index].action;
+        self.protocols[index].action = if previous == ServerAction::Start { ServerAction::Stop } else { ServerAction::Start };
+        if !self.send(index) {
+            self.protocols[index].action = previous;
+        }
+    }
+
+    /// Current text of the selected cell, if it can be edited
+    fn cell_text(&self) -> Option<String> {
+        let column = COLUMNS[self.column];
+        let Some(index) = self.row.checked_sub(1) else {
+            return match column {
+                Column::BindIp => Some(self.bind_ip.clone()),
+                Column::Path => Some(self.path.clone()),
+                _ => None,
+            };
+        };
+        let p = &self.protocols[index];
+        match column {
+            Column::Start => None,
+            // Some protocols do not allow changing ports (their default is 0)
+            Column::Port if p.protocol.get_default_port() == 0 => None,
+            Column::Port => Some(p.port.to_string()),
+            Column::BindIp => Some(p.bind_ip.clone()),
+            Column::Path => Some(p.path.clone()),
+        }
+    }
+
+    /// Hands a running server the directory it serves now
+    fn reload(&self, index: usize) {
+        let p = &self.protocols[index];
+        if p.action == ServerAction::Start {
+            let _ = self.sender.send(CommandMsg { action: ServerAction::Reconfigure, ..self.command(p) });
+        }
+    }
+
+    /// Sets the selected cell to the text typed. Directories apply to running servers
+    /// right away, other changes the next time a server is started
+    fn apply_edit(&mut self, text: String) {
+        let text = text.trim().to_string();
+        let column = COLUMNS[self.column];
+        let Some(index) = self.row.checked_sub(1) else {
+            match column {
+                Column::BindIp => self.bind_ip = text,
+                Column::Path => {
+                    self.path = text;
+                    for i in 0..self.protocols.len() {
+                        if self.protocols[i].path.is_empty() {
+                            self.reload(i);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            return;
+        };
+        let p = &mut self.protocols[index];
+        match column {
+            Column::Start => {}
+            Column::Port => match text.parse() {
+                Ok(port) => p.port = port,
+                Err(_) => warn!("Invalid port: {}", text),
+            },
+            Column::BindIp => p.bind_ip = text,
+            Column::Path => {
+                p.path = text;
+                self.reload(index);
+            }
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        if let Some(text) = &mut self.editing {
+            match key.code {
+                KeyCode::Enter => {
+                    let text = std::mem::take(text);
+                    self.editing = None;
+                    self.apply_edit(text);
+                }
+                KeyCode::Esc => self.editing = None,
+                KeyCode::Backspace => { text.pop(); }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            }
+            return;
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.searching = false,
+                KeyCode::Backspace => { self.log_search.pop(); }
+                KeyCode::Char(c) => self.log_search.push(c),
+                _ => {}
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Up => self.row = self.row.saturating_sub(1),
+            KeyCode::Down => self.row = (self.row + 1).min(self.protocols.len()),
+            KeyCode::Left => self.column = self.column.saturating_sub(1),
+            KeyCode::Right => self.column = (self.column + 1).min(COLUMNS.len() - 1),
+            KeyCode::Char(' ') => self.toggle(),
+            KeyCode::Enter if COLUMNS[self.column] == Column::Start => self.toggle(),
+            KeyCode::Enter => self.editing = self.cell_text(),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Char(c @ '1'..='5') => {
+                toggle_hidden(&mut self.hidden_levels, LEVELS[c as usize - '1' as usize]);
+            }
+            KeyCode::Char(c @ '6'..='9') => {
+                toggle_hidden(&mut self.hidden_protocols, Some(PROTOCOL_LIST[c as usize - '6' as usize].clone()));
+            }
+            KeyCode::Char('0') => toggle_hidden(&mut self.hidden_protocols, None),
+            KeyCode::Char('c') => self.logs.lock().unwrap().clear(),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(LOG_PAGE),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(LOG_PAGE),
+            KeyCode::Home => self.scroll = usize::MAX,
+            KeyCode::End => self.scroll = 0,
+            _ => {}
+        }
+    }
+
+    /// Whether a log entry passes the level, protocol and search filters
+    fn shows(&self, entry: &LogEntry) -> bool {
+        !self.hidden_levels.contains(&entry.level)
+            && !self.hidden_protocols.contains(&entry.protocol)
+            && (self.log_search.is_empty() || entry.message.to_lowercase().contains(&self.log_search.to_lowercase()))
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let transfers = self.stats.active().len().clamp(1, MAX_TRANSFERS_SHOWN);
+        let [servers, transfers, logs, help] = Layout::vertical([
+            Constraint::Length(self.protocols.len() as u16 + 4),
+            Constraint::Length(transfers as u16 + 2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        self.draw_servers(frame, servers);
+        self.draw_transfers(frame, transfers);
+        self.draw_logs(frame, logs);
+
+        let text = if self.editing.is_some() {
+            "Enter apply · Esc cancel"
+        } else if self.searching {
+            "Type to search the logs · Enter done"
+        } else {
+            "↑↓←→ select · Space start/stop · Enter edit · / search · 1-5 levels · 6-0 protocols · PgUp/PgDn/End scroll · c clear · q quit"
+        };
+        frame.render_widget(Paragraph::new(text).style(Style::default().fg(Color::DarkGray)), help);
+    }
+
+    /// The global settings, then each protocol's, with the state of its server
+    fn draw_servers(&self, frame: &mut Frame, area: Rect) {
+        let hint = Style::default().fg(Color::DarkGray);
+        let cell = |row: usize, column: Column, text: String, style: Style| {
+            if (row, column) != (self.row, COLUMNS[self.column]) {
+                return Cell::from(text).style(style);
+            }
+            match &self.editing {
+                Some(edited) => Cell::from(format!("{}▏", edited)).style(Style::default().fg(Color::Yellow)),
+                None => Cell::from(text).style(style.add_modifier(Modifier::REVERSED)),
+            }
+        };
+        let or_global = |text: &String, global: &String| {
+            if text.is_empty() { (global.clone(), hint) } else { (text.clone(), Style::default()) }
+        };
+
+        let mut rows = vec![Row::new(vec![
+            cell(0, Column::Start, "    All".into(), Style::default()),
+            cell(0, Column::Port, String::new(), Style::default()),
+            cell(0, Column::BindIp, self.bind_ip.clone(), Style::default()),
+            cell(0, Column::Path, self.path.clone(), Style::default()),
+            Cell::from(""),
+        ])];
+
+        let counters = self.stats.snapshot();
+        for (i, p) in self.protocols.iter().enumerate() {
+            let row = i + 1;
+            let name = format!("[{}] {}", if p.action == ServerAction::Start { "x" } else { " " }, p.protocol.to_string().to_uppercase());
+            let name_style = Style::default().fg(protocol_color(Some(&p.protocol)).unwrap_or(Color::Reset));
+            let port = if p.protocol.get_default_port() == 0 { "-".to_string() } else { p.port.to_string() };
+            let (bind_ip, bind_ip_style) = or_global(&p.bind_ip, &self.bind_ip);
+            let (path, path_style) = or_global(&p.path, &self.path);
+
+            // Where the server actually ended up (e.g. with port 0), and what it served so far
+            let counters = counters.get(&p.protocol).cloned().unwrap_or_default();
+            let served = format!("{} requests, {} sent", counters.requests, format_bytes(counters.bytes_sent));
+            let status = match self.stats.status(&p.protocol) {
+                ServerStatus::Listening(addrs) => Line::from(vec![
+                    Span::styled(addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "), Style::default().fg(Color::Green)),
+                    Span::styled(format!("  {}", served), hint),
+                ]),
+                ServerStatus::Failed(e) => Line::styled(format!("failed: {}", e), Style::default().fg(Color::Red)),
+                ServerStatus::Stopped => Line::styled("stopped", hint),
+                ServerStatus::Idle => Line::default(),
+            };
+
+            rows.push(Row::new(vec![
+                cell(row, Column::Start, name, name_style),
+                cell(row, Column::Port, port, Style::default()),
+                cell(row, Column::BindIp, bind_ip, bind_ip_style),
+                cell(row, Column::Path, path, path_style),
+                Cell::from(status),
+            ]));
+        }
+
+        let header = Row::new(["Server", "Port", "Bind IP", "Directory", "Status"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(20),
+            Constraint::Fill(1),
+            Constraint::Fill(2),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(" Quick-serve "));
+        frame.render_widget(table, area);
+    }
+
+    /// Transfers going on, along with the totals so far
+    fn draw_transfers(&self, frame: &mut Frame, area: Rect) {
+        let active = self.stats.active();
+        let totals = self.stats.snapshot();
+        let title = format!(" Transfers: {} active, {} downloaded, {} sent, {} received ",
+            active.len(),
+            totals.values().map(|s| s.transfers).sum::<u64>(),
+            format_bytes(totals.values().map(|s| s.bytes_sent).sum()),
+            format_bytes(totals.values().map(|s| s.bytes_received).sum()));
+
+        let mut lines: Vec<Line> = active.iter().take(MAX_TRANSFERS_SHOWN).map(|t| {
+            Line::from(vec![
+                Span::styled(format!("{:<5}", t.protocol.to_string().to_uppercase()),
+                    Style::default().fg(protocol_color(Some(&t.protocol)).unwrap_or(Color::Reset))),
+                Span::raw(format!("{} {} ", t.client_label(), t.path)),
+                Span::raw(progress_bar(t.progress(), 20)),
+                Span::raw(format!(" {} at {}/s", format_bytes(t.bytes), format_bytes(t.rate() as u64))),
+            ])
+        }).collect();
+        if active.len() > MAX_TRANSFERS_SHOWN {
+            let last = lines.len() - 1;
+            lines[last] = Line::styled(format!("… and {} more", active.len() - MAX_TRANSFERS_SHOWN + 1), Style::default().fg(Color::DarkGray));
+        }
+        if lines.is_empty() {
+            lines.push(Line::styled("No transfer going on", Style::default().fg(Color::DarkGray)));
+        }
+
+        let block = Block::default().borders(Borders::ALL).title(title);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// Logs, filtered by level, protocol and text, following new entries unless scrolled up
+    fn draw_logs(&mut self, frame: &mut Frame, area: Rect) {
+        let toggle = |key: char, text: String, shown: bool, color: Option<Color>| {
+            let style = match (shown, color) {
+                (false, _) => Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT),
+                (true, Some(color)) => Style::default().fg(color),
+                (true, None) => Style::default(),
+            };
+            vec![Span::styled(format!("{}", key), Style::default().add_modifier(Modifier::BOLD)), Span::styled(format!(" {} ", text), style)]
+        };
+
+        let mut title = vec![Span::raw(" Logs ")];
+        for (i, level) in LEVELS.iter().enumerate() {
+            let key = char::from(b'1' + i as u8);
+            title.extend(toggle(key, level.as_str().into(), !self.hidden_levels.contains(level), None));
+        }
+        for (i, &protocol) in PROTOCOL_LIST.iter().enumerate() {
+            let key = char::from(b'6' + i as u8);
+            let shown = !self.hidden_protocols.contains(&Some(protocol.clone()));
+            title.extend(toggle(key, protocol.to_string().to_uppercase(), shown, protocol_color(Some(protocol))));
+        }
+        title.extend(toggle('0', "Other".into(), !self.hidden_protocols.contains(&None), None));
+        if self.searching || !self.log_search.is_empty() {
+            let cursor = if self.searching { "▏" } else { "" };
+            title.push(Span::styled(format!("/{}{} ", self.log_search, cursor), Style::default().fg(Color::Yellow)));
+        }
+
+        let logs = self.logs.clone();
+        let logs = logs.lock().unwrap();
+        let shown: Vec<&LogEntry> = logs.iter().filter(|e| self.shows(e)).collect();
+
+        let height = area.height.saturating_sub(2) as usize;
+        self.scroll = self.scroll.min(shown.len().saturating_sub(height));
+        let end = shown.len() - self.scroll;
+        let lines: Vec<Line> = shown[end.saturating_sub(height)..end].iter().map(|entry| {
+            let style = Style::default().fg(protocol_color(entry.protocol.as_ref()).unwrap_or(Color::Reset));
+            Line::styled(entry.line(), style)
+        }).collect();
+
+        let mut block = Block::default().borders(Borders::ALL).title(Line::from(title));
+        if self.scroll > 0 {
+            block = block.title_bottom(format!(" {} newer lines, End to follow ", self.scroll));
+        }
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// The color logs of a protocol are shown in, if any
+fn protocol_color(protocol: Option<&Protocol>) -> Option<Color> {
+    match protocol? {
+        Protocol::Http => Some(Color::Rgb(0x4a, 0x90, 0xd9)),
+        Protocol::Ftp  => Some(Color::Rgb(0xd9, 0x8c, 0x2b)),
+        Protocol::Tftp => Some(Color::Rgb(0x3c, 0xa5, 0x5c)),
+        Protocol::Dhcp => Some(Color::Rgb(0xa5, 0x5c, 0xc8)),
+        Protocol::Custom(_) => None,
+    }
+}
+
+/// Shows the entries of a kind if hidden, hides them otherwise
+fn toggle_hidden<T: Eq + std::hash::Hash>(hidden: &mut HashSet<T>, value: T) {
+    if !hidden.remove(&value) {
+        hidden.insert(value);
+    }
+}
+
+/// A bar of the given width, followed by the percentage. Files of unknown size get an empty one
+fn progress_bar(progress: Option<f32>, width: usize) -> String {
+    let Some(progress) = progress else {
+        return format!("[{}]", "·".repeat(width));
+    };
+    let filled = ((progress.clamp(0.0, 1.0) * width as f32) as usize).min(width);
+    format!("[{}{}] {:>3.0}%", "#".repeat(filled), "-".repeat(width - filled), progress * 100.0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use crate::{DefaultChannel, Direction};
+
+    fn app(config: &Config) -> (App, DefaultChannel<CommandMsg>) {
+        let channel: DefaultChannel<CommandMsg> = Default::default();
+        let app = App::new(config, channel.sender.clone(), Default::default(), Stats::default());
+        (app, channel)
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::from(*key));
+        }
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn test_toggle_server() {
+        let config = Config { bind_ip: "127.0.0.1".into(), serve_dir: "/srv".into(), ..Default::default() };
+        let (mut app, mut channel) = app(&config);
+
+        // The global directory is used as the HTTP one is empty
+        press(&mut app, &[KeyCode::Char(' ')]);
+        let msg = channel.receiver.try_recv().unwrap();
+        assert_eq!(msg.action, ServerAction::Start);
+        assert_eq!(msg.protocol, Protocol::Http);
+        assert_eq!((msg.bind_ip.as_str(), msg.path.as_str()), ("127.0.0.1", "/srv"));
+
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(channel.receiver.try_recv().unwrap().action, ServerAction::Stop);
+
+        // Nothing to start on the global row, nor with an invalid bind IP
+        press(&mut app, &[KeyCode::Up, KeyCode::Char(' ')]);
+        assert!(channel.receiver.try_recv().is_err());
+        app.protocols[0].bind_ip = "not an ip".into();
+        press(&mut app, &[KeyCode::Down, KeyCode::Char(' ')]);
+        assert!(channel.receiver.try_recv().is_err());
+        assert_eq!(app.protocols[0].action, ServerAction::Stop);
+    }
+
+    #[test]
+    fn test_edit_fields() {
+        let (mut app, _channel) = app(&Config::default());
+
+        // TFTP port
+        press(&mut app, &[KeyCode::Down, KeyCode::Right, KeyCode::Enter]);
+        assert_eq!(app.editing.as_deref(), Some("6969"));
+        press(&mut app, &[KeyCode::Backspace]);
+        type_text(&mut app, "8");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.protocols[1].port, 6968);
+
+        // Not a port, left as it was
+        press(&mut app, &[KeyCode::Enter]);
+        type_text(&mut app, "x");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(app.protocols[1].port, 6968);
+
+        // Global directory, then a cancelled edit
+        press(&mut app, &[KeyCode::Up, KeyCode::Up, KeyCode::Right, KeyCode::Right, KeyCode::Enter]);
+        type_text(&mut app, "/fw ");
+        press(&mut app, &[KeyCode::Enter]);
+        assert!(app.path.ends_with("/fw"));
+        press(&mut app, &[KeyCode::Enter]);
+        type_text(&mut app, "/other");
+        press(&mut app, &[KeyCode::Esc]);
+        assert!(app.path.ends_with("/fw"));
+        assert!(!app.quit);
+
+        press(&mut app, &[KeyCode::Char('q')]);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_reload_directory() {
+        let config = Config { serve_dir: "/srv".into(), ..Default::default() };
+        let (mut app, mut channel) = app(&config);
+        press(&mut app, &[KeyCode::Char(' ')]);
+        assert_eq!(channel.receiver.try_recv().unwrap().action, ServerAction::Start);
+
+        // The running HTTP server takes the new global directory, stopped ones are left alone
+        press(&mut app, &[KeyCode::Up, KeyCode::Right, KeyCode::Right, KeyCode::Right, KeyCode::Enter]);
+        type_text(&mut app, "/fw");
+        press(&mut app, &[KeyCode::Enter]);
+        let msg = channel.receiver.try_recv().unwrap();
+        assert_eq!(msg.action, ServerAction::Reconfigure);
+        assert_eq!((msg.protocol, msg.path.as_str()), (Protocol::Http, "/srv/fw"));
+        assert!(channel.receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_log_filters() {
+        let (mut app, _channel) = app(&Config::default());
+        let entry = |id, level, protocol: Option<Protocol>, message: &str| LogEntry { id, level, protocol, message: message.into() };
+        app.logs.lock().unwrap().extend([
+            entry(1, Level::Info, Some(Protocol::Http), "GET /fw/a.bin"),
+            entry(2, Level::Debug, Some(Protocol::Tftp), "RRQ b.bin"),
+            entry(3, Level::Warn, None, "Config reloaded"),
+        ]);
+        let shown = |app: &App| app.logs.lock().unwrap().iter().filter(|e| app.shows(e)).map(|e| e.id).collect::<Vec<_>>();
+
+        press(&mut app, &[KeyCode::Char('4')]);
+        assert_eq!(shown(&app), [1, 3]);
+        press(&mut app, &[KeyCode::Char('4'), KeyCode::Char('0')]);
+        assert_eq!(shown(&app), [1, 2]);
+
+        // Typing a search does not trigger the keys it is made of
+        press(&mut app, &[KeyCode::Char('/')]);
+        type_text(&mut app, "B.BIN");
+        press(&mut app, &[KeyCode::Enter]);
+        assert_eq!(shown(&app), [2]);
+        assert!(!app.quit);
+
+        press(&mut app, &[KeyCode::Char('c')]);
+        assert!(app.logs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_draw() {
+        let (mut app, _channel) = app(&Config::default());
+        let transfer = app.stats.begin(&Protocol::Http, None, "fw/a.bin", Direction::Download, Some(100));
+        transfer.progress(50);
+        app.logs.lock().unwrap().push(LogEntry { id: 1, level: Level::Info, protocol: None, message: "Hello".into() });
+
+        let mut terminal = Terminal::new(TestBackend::new(140, 30)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+        for text in ["[ ] HTTP", "[ ] DHCP", "1 active", "fw/a.bin", "50%", "ℹ Hello"] {
+            assert!(screen.contains(text), "{} not drawn", text);
+        }
+        transfer.complete();
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(Some(0.5), 4), "[##--]  50%");
+        assert_eq!(progress_bar(Some(1.0), 4), "[####] 100%");
+        assert_eq!(progress_bar(None, 2), "[··]");
+    }
+}
@@ -11,7 +11,6 @@
 /// ``` ignore
 /// toggle_ui(ui, &mut my_bool);
 /// ```
-
 #[allow(dead_code)]
 fn toggle_ui(ui: &mut egui::Ui, on: &mut bool) -> egui::Response {
     let desired_size = ui.spacing().interact_size.y * egui::vec2(2.0, 1.0);
@@ -1,35 +1,192 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use eframe::egui;
-use egui::{DragValue, TextEdit};
+use egui::{Color32, DragValue, RichText, TextEdit};
 use egui::{Label, TextStyle};
 use crate::ui::toggle_switch::toggle;
-use crate::{DefaultChannel, PROTOCOL_LIST};
+use crate::utils::logger::LogEntry;
+use crate::utils::{net, snippets, validation};
+use crate::{format_bytes, Config, DefaultChannel, Protocol, ServerAction, ServerStatus, Stats, PROTOCOL_LIST};
+use log::{error, info, Level};
 
 use crate::messages::CommandMsg;
 
 pub struct UI {
     protocols: Vec<CommandMsg>,
+    /// The settings each running server was started with, to tell the edits needing a restart
+    applied: HashMap<Protocol, CommandMsg>,
     bind_ip: String,
     path: String,
+    /// File the client commands fetch, the first one served if empty
+    example_file: String,
+    config: Config,
+    config_path: Option<PathBuf>,
+
+    // Log view filters
+    hidden_levels: HashSet<Level>,
+    hidden_protocols: HashSet<Option<Protocol>>,
+    log_search: String,
 
     pub channel: DefaultChannel<CommandMsg>,
-    pub logs: Arc<Mutex<Vec<String>>>,
+    pub logs: Arc<Mutex<Vec<LogEntry>>>,
+    pub stats: Stats,
 }
 
 impl UI {
     pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
         let mut s = UI {
             protocols: Vec::new(),
+            applied: HashMap::new(),
             bind_ip: "127.0.0.1".into(),
             path: "/tmp/".into(),
+            example_file: String::new(),
+            config: Config::default(),
+            config_path: None,
+            hidden_levels: HashSet::new(),
+            hidden_protocols: HashSet::new(),
+            log_search: String::new(),
             channel: Default::default(),
             logs: Default::default(),
+            stats: Default::default(),
         };
         for protocol in PROTOCOL_LIST {
             s.protocols.push(CommandMsg::new(protocol));
         }
         s
     }
+
+    /// Takes the initial settings from the configuration, and remembers
+    /// where to save them back to
+    pub fn load_config(&mut self, config: Config, path: Option<PathBuf>) {
+        self.bind_ip = config.bind_ip.clone();
+        self.path = config.serve_dir.clone();
+        self.example_file = config.example_file.clone().unwrap_or_default();
+        for p in self.protocols.iter_mut() {
+            if let Some((_, port)) = config.server(&p.protocol) {
+                p.port = port;
+            }
+            let (bind_ip, path) = config.overrides(&p.protocol);
+            p.bind_ip = bind_ip.cloned().unwrap_or_default();
+            p.path = path.cloned().unwrap_or_default();
+            p.mounts = config.mounts.clone();
+            p.symlinks = config.symlinks;
+            p.access = config.access.clone();
+        }
+        self.config = config;
+        self.config_path = path;
+    }
+
+    /// Writes the current settings to the configuration file
+    fn save_config(&mut self) {
+        let Some(path) = &self.config_path else { return };
+
+        self.config.bind_ip = self.bind_ip.clone();
+        self.config.serve_dir = self.path.clone();
+        self.config.example_file = Some(self.example_file.trim().to_string()).filter(|file| !file.is_empty());
+        for p in &self.protocols {
+            self.config.set_server(&p.protocol, p.action == ServerAction::Start, p.port);
+            let non_empty = |s: &String| Some(s.clone()).filter(|s| !s.is_empty());
+            self.config.set_overrides(&p.protocol, non_empty(&p.bind_ip), non_empty(&p.path));
+        }
+
+        match self.config.save(path) {
+            Ok(()) => info!("Settings saved to {}", path.display()),
+            Err(e) => error!("Failed to save settings to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Whether a log entry passes the level, protocol and search filters
+    fn shows(&self, entry: &LogEntry) -> bool {
+        !self.hidden_levels.contains(&entry.level)
+            && !self.hidden_protocols.contains(&entry.protocol)
+            && (self.log_search.is_empty() || entry.message.to_lowercase().contains(&self.log_search.to_lowercase()))
+    }
+
+    /// Writes the log lines shown to a file picked by the user
+    fn save_logs(lines: &str) {
+        let Some(path) = rfd::FileDialog::new().set_file_name("quick-serve.log").save_file() else { return };
+        match std::fs::write(&path, lines) {
+            Ok(()) => info!("Logs saved to {}", path.display()),
+            Err(e) => error!("Failed to save logs to {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// The color logs of a protocol are shown in, if any
+/// The command for a protocol's server, empty fields falling back to the
+/// global bind IP and directory
+fn command(p: &CommandMsg, bind_ip: &str, path: &str) -> CommandMsg {
+    let mut msg = p.clone();
+    if msg.bind_ip.is_empty() {
+        msg.bind_ip = bind_ip.to_string();
+    }
+    if msg.path.is_empty() {
+        msg.path = path.to_string();
+    }
+    msg
+}
+
+/// Whether a server started with `applied` must be restarted to use `next`,
+/// the directory being taken by a running server
+fn needs_restart(applied: &CommandMsg, next: &CommandMsg) -> bool {
+    applied.port != next.port || applied.bind_ip != next.bind_ip
+}
+
+/// A menu picking the bind IP among the addresses of the local interfaces
+///
+/// # Arguments
+/// * `global` - The global bind IP, offered to fall back to for a protocol's own
+fn interface_menu(ui: &mut egui::Ui, bind_ip: &mut String, global: Option<&str>) {
+    ui.menu_button("▾", |ui| {
+        let mut pick = |ui: &mut egui::Ui, text: String, ip: &str| {
+            if ui.button(text).clicked() {
+                *bind_ip = ip.to_string();
+                ui.close();
+            }
+        };
+        if let Some(global) = global {
+            pick(ui, format!("Global ({})", global), "");
+        }
+        pick(ui, format!("All interfaces ({})", net::ALL_INTERFACES), net::ALL_INTERFACES);
+        pick(ui, "Loopback (127.0.0.1)".into(), "127.0.0.1");
+        ui.separator();
+        match net::interfaces() {
+            Ok(list) => {
+                for iface in list.iter().filter(|iface| !iface.ip.is_loopback()) {
+                    pick(ui, iface.to_string(), &iface.ip.to_string());
+                }
+            }
+            Err(e) => { ui.label(format!("Failed to list the interfaces: {}", e)); }
+        }
+    }).response.on_hover_text("Pick a local interface");
+}
+
+/// The command handing a running server its new settings
+fn reload(p: &CommandMsg, bind_ip: &str, path: &str) -> CommandMsg {
+    CommandMsg { action: ServerAction::Reconfigure, ..command(p, bind_ip, path) }
+}
+
+fn protocol_color(protocol: Option<&Protocol>) -> Option<Color32> {
+    match protocol? {
+        Protocol::Http => Some(Color32::from_rgb(0x4a, 0x90, 0xd9)),
+        Protocol::Ftp  => Some(Color32::from_rgb(0xd9, 0x8c, 0x2b)),
+        Protocol::Tftp => Some(Color32::from_rgb(0x3c, 0xa5, 0x5c)),
+        Protocol::Dhcp => Some(Color32::from_rgb(0xa5, 0x5c, 0xc8)),
+        Protocol::Custom(_) => None,
+    }
+}
+
+/// A toggle showing or hiding the entries of a kind
+fn filter_toggle<T: Eq + std::hash::Hash>(ui: &mut egui::Ui, hidden: &mut HashSet<T>, value: T, text: RichText) {
+    let shown = !hidden.contains(&value);
+    if ui.selectable_label(shown, text).clicked() {
+        if shown {
+            hidden.insert(value);
+        } else {
+            hidden.remove(&value);
+        }
+    }
 }
 
 impl eframe::App for UI {
@@ -39,6 +196,10 @@ impl eframe::App for UI {
         egui::CentralPanel::default().show_inside(ui, |ui| {
             egui::MenuBar::new().ui(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    if ui.add_enabled(self.config_path.is_some(), egui::Button::new("Save settings")).clicked() {
+                        self.save_config();
+                        ui.close();
+                    }
                     if ui.button("Exit").clicked() {
                         std::process::exit(0);
                     }
@@ -84,6 +245,9 @@ impl eframe::App for UI {
                     if ui.button("📂").clicked() {
                         if let Some(path) = rfd::FileDialog::new().pick_folder() {
                             self.path = path.display().to_string();
+                            for p in self.protocols.iter().filter(|p| p.action == ServerAction::Start && p.path.is_empty()) {
+                                let _ = self.channel.sender.send(reload(p, &self.bind_ip, &self.path));
+                            }
                         }
                     }
                     // ui.monospace(self.path.clone());
@@ -98,11 +262,12 @@ impl eframe::App for UI {
                 ui.group(|ui| {
                     ui.horizontal(|ui| {
                         let _name_label = ui.label("Bind IP: ");
+                        // Comma separated, e.g. "0.0.0.0, ::" for both IPv4 and IPv6
                         ui.add(
                             TextEdit::singleline(&mut self.bind_ip)
-                            .char_limit(15)
-                            .desired_width(100.0)
+                            .desired_width(220.0)
                         );
+                        interface_menu(ui, &mut self.bind_ip, None);
                     });
                 });
 
@@ -110,45 +275,220 @@ impl eframe::App for UI {
                 // Iterate over each known protocol, and draw its elements
                 for p in self.protocols.iter_mut() {
                     ui.group(|ui| {
-                        ui.add(Label::new(format!("{}", p.protocol.to_string())));
-                        
+                        ui.vertical(|ui| {
+                            ui.horizontal(|ui| {
+                                ui.add(Label::new(p.protocol.to_string()));
 
-                        // Some protocols do not allow changing ports (and may be set to 0)
-                        // so we only show the port field if it is not 0
-                        if p.port != 0 {
-                            ui.add(DragValue::new(&mut p.port).range(1..=50000));
-                        }
+                                // Some protocols do not allow changing ports (their default is 0)
+                                // so we only show the port field for the others. Port 0 lets
+                                // the OS pick a free one.
+                                if p.protocol.get_default_port() != 0 {
+                                    ui.add(DragValue::new(&mut p.port).range(0..=65535));
+                                }
 
-                        if ui.add(toggle(&mut p.start)).clicked() {
+                                let mut on = p.action == ServerAction::Start;
+                                if ui.add(toggle(&mut on)).clicked() {
+                                    p.action = if on { ServerAction::Start } else { ServerAction::Stop };
+                                    let cmd = command(p, &self.bind_ip, &self.path);
+                                    self.channel.sender
+                                        .send(cmd.clone())
+                                        .expect("Failed to send message");
+                                    if on {
+                                        self.applied.insert(p.protocol.clone(), cmd);
+                                    } else {
+                                        self.applied.remove(&p.protocol);
+                                    }
+                                }
+                            });
 
-                            let mut msg = p.clone();
-                            msg.bind_ip = self.bind_ip.clone();
-                            msg.path = self.path.clone();
+                            // Per protocol bind IP and directory, overriding the global ones
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    TextEdit::singleline(&mut p.bind_ip)
+                                    .hint_text(self.bind_ip.as_str())
+                                    .desired_width(100.0)
+                                );
+                                interface_menu(ui, &mut p.bind_ip, Some(&self.bind_ip));
+                            });
+                            ui.horizontal(|ui| {
+                                let mut changed = ui.add(
+                                    TextEdit::singleline(&mut p.path)
+                                    .hint_text(self.path.as_str())
+                                    .desired_width(100.0)
+                                ).lost_focus();
+                                if ui.small_button("📂").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                        p.path = path.display().to_string();
+                                        changed = true;
+                                    }
+                                }
+                                // A running server serves the new directory from its next request
+                                if changed && p.action == ServerAction::Start {
+                                    let _ = self.channel.sender.send(reload(p, &self.bind_ip, &self.path));
+                                }
+                            });
 
-                            self.channel.sender
-                                .send(msg)
-                                .expect("Failed to send message");
-                        }
+                            // A running server only listens elsewhere once restarted
+                            let next = command(p, &self.bind_ip, &self.path);
+                            let applied = self.applied.get(&p.protocol).filter(|_| p.action == ServerAction::Start);
+                            if applied.is_some_and(|applied| needs_restart(applied, &next))
+                                && ui.small_button("Apply and restart")
+                                    .on_hover_text(format!("Listen on {} port {}", next.bind_ip, next.port))
+                                    .clicked()
+                            {
+                                match validation::parse_bind_addresses(&next.bind_ip, next.port) {
+                                    Ok(_) => {
+                                        let _ = self.channel.sender.send(CommandMsg { action: ServerAction::Restart, ..next.clone() });
+                                        self.applied.insert(p.protocol.clone(), next);
+                                    }
+                                    Err(e) => error!("{}", e),
+                                }
+                            }
+
+                            // Show where the server actually ended up (e.g. with port 0)
+                            match self.stats.status(&p.protocol) {
+                                ServerStatus::Listening(addrs) => {
+                                    for addr in addrs {
+                                        ui.small(addr.to_string());
+                                    }
+                                }
+                                ServerStatus::Failed(_) if p.action == ServerAction::Start => { ui.small("failed"); }
+                                // Also while restarting
+                                ServerStatus::Idle | ServerStatus::Stopped if p.action == ServerAction::Start => { ui.small("starting…"); }
+                                _ => {}
+                            }
+                        });
                     });
                 }
             });
 
             // #######################################################################
+            // Transfers going on, along with the totals so far
+            ui.add_space(5.0);
+            ui.group(|ui| {
+                ui.vertical(|ui| {
+                    let active = self.stats.active();
+                    let totals = self.stats.snapshot();
+                    ui.label(format!("Transfers: {} active, {} downloaded, {} sent, {} received",
+                        active.len(),
+                        totals.values().map(|s| s.transfers).sum::<u64>(),
+                        format_bytes(totals.values().map(|s| s.bytes_sent).sum()),
+                        format_bytes(totals.values().map(|s| s.bytes_received).sum())));
+
+                    for t in &active {
+                        ui.horizontal(|ui| {
+                            ui.small(format!("{} {} {}", t.protocol.to_string().to_uppercase(), t.client_label(), t.path));
+
+                            // Files of unknown size get an animated bar
+                            let text = format!("{} at {}/s", format_bytes(t.bytes), format_bytes(t.rate() as u64));
+                            let bar = egui::ProgressBar::new(t.progress().unwrap_or_default())
+                                .text(text)
+                                .animate(t.progress().is_none());
+                            ui.add(bar);
+                        });
+                    }
+                });
+            });
+
+            // #######################################################################
+            // Commands fetching a file from the servers running, to be run on the targets
+            ui.add_space(5.0);
+            egui::CollapsingHeader::new("Client commands").show(ui, |ui| {
+                let first = |dir: &str| snippets::first_file(dir).unwrap_or_else(|| "FILE".into());
+                ui.horizontal(|ui| {
+                    ui.label("File: ");
+                    ui.add(
+                        TextEdit::singleline(&mut self.example_file)
+                        .hint_text(first(&self.path))
+                        .desired_width(220.0)
+                    );
+                    if ui.small_button("📂").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().set_directory(&self.path).pick_file() {
+                            // Within the served tree, if picked there
+                            self.example_file = path.strip_prefix(&self.path).unwrap_or(&path).display().to_string();
+                        }
+                    }
+                });
+
+                let interfaces = net::interfaces().unwrap_or_default();
+                for p in &self.protocols {
+                    let ServerStatus::Listening(addrs) = self.stats.status(&p.protocol) else { continue };
+                    let file = match self.example_file.trim() {
+                        "" => first(if p.path.is_empty() { &self.path } else { &p.path }),
+                        file => file.to_string(),
+                    };
+                    let user = snippets::user(&self.config, &p.protocol);
+                    for snippet in snippets::server_snippets(&p.protocol, &addrs, &file, user.as_deref(), &interfaces) {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("📋").on_hover_text("Copy to the clipboard").clicked() {
+                                ui.ctx().copy_text(snippet.command.clone());
+                            }
+                            ui.small(format!("{} {}", p.protocol.to_string().to_uppercase(), snippet.client));
+                            ui.monospace(&snippet.command);
+                        });
+                    }
+                }
+            });
+
+            // #######################################################################
+            // Logs, filtered by level, protocol and text
             ui.add_space(5.0);
             ui.separator();
 
+            let (mut copy, mut save, mut clear) = (false, false, false);
+            ui.horizontal(|ui| {
+                for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+                    filter_toggle(ui, &mut self.hidden_levels, level, RichText::new(level.as_str()));
+                }
+                ui.separator();
+                for protocol in PROTOCOL_LIST {
+                    let text = RichText::new(protocol.to_string().to_uppercase())
+                        .color(protocol_color(Some(protocol)).unwrap_or_default());
+                    filter_toggle(ui, &mut self.hidden_protocols, Some(protocol.clone()), text);
+                }
+                filter_toggle(ui, &mut self.hidden_protocols, None, RichText::new("Other"));
+                ui.separator();
+                ui.add(TextEdit::singleline(&mut self.log_search).hint_text("🔍 Search").desired_width(120.0));
+                copy = ui.button("📋 Copy").clicked();
+                save = ui.button("💾 Save").clicked();
+                clear = ui.button("🗑 Clear").clicked();
+            });
+
+            let logs = self.logs.clone();
+            let mut logs = logs.lock().unwrap();
+            if clear {
+                logs.clear();
+            }
+            let shown: Vec<&LogEntry> = logs.iter().filter(|e| self.shows(e)).collect();
+            if copy || save {
+                let lines: Vec<String> = shown.iter().map(|e| e.line()).collect();
+                let lines = lines.join("\n");
+                if copy {
+                    ui.ctx().copy_text(lines);
+                } else {
+                    // Saving logs itself, so the lock is released first
+                    drop(shown);
+                    drop(logs);
+                    UI::save_logs(&lines);
+                    return;
+                }
+            }
+
             let text_style = TextStyle::Monospace;
-            let logs = self.logs.lock().unwrap();
             let row_height = ui.text_style_height(&text_style);
-            let num_rows = logs.len();
+            let num_rows = shown.len();
 
             egui::ScrollArea::both()
                 .auto_shrink(false)
                 .stick_to_bottom(true)
                 .scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::VisibleWhenNeeded)
                 .show_rows(ui, row_height, num_rows, |ui, row_range| {
-                    for row in row_range {
-                        ui.label( egui::RichText::new(&logs[row]).text_style(text_style.clone()) );
+                    for entry in &shown[row_range] {
+                        let mut text = RichText::new(entry.line()).text_style(text_style.clone());
+                        if let Some(color) = protocol_color(entry.protocol.as_ref()) {
+                            text = text.color(color);
+                        }
+                        ui.label(text);
                     }
                 });
         }); // CentralPanel
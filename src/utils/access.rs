@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::common::QuickServeError;
+
+/// Rules denied unless explicitly allowed: system directories, credential
+/// stores and, within the served tree, hidden files (which covers `.git`)
+pub const DEFAULT_DENY: [&str; 8] = ["/etc", "/sys", "/proc", "/dev", "/boot", "/**/.ssh", "/**/.gnupg", "**/.*"];
+
+/// Glob rules deciding what may be served
+///
+/// Rules starting with `/` apply to the directories being served (the root
+/// and mount sources). The others apply to paths within the served tree,
+/// e.g. `**/.*` for hidden files. A rule matching a directory applies to
+/// everything below it. Allow rules take precedence over deny rules, the
+/// [`DEFAULT_DENY`] ones included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessRules {
+    /// Served even when matching a deny rule, e.g. `.well-known`
+    pub allow: Vec<Glob>,
+    /// Never served nor listed, on top of the default rules
+    pub deny: Vec<Glob>,
+    /// Leaves the [`DEFAULT_DENY`] rules out, only applying `deny`
+    pub no_default_deny: bool,
+    /// Disables all rules, default ones included
+    pub unrestricted: bool,
+}
+
+impl AccessRules {
+    /// Compiles the rules, to be checked against paths
+    pub fn matcher(&self) -> AccessMatcher {
+        if self.unrestricted {
+            return AccessMatcher::default();
+        }
+        // `*` not to match across `/`, as in the shell, `**` being there for that
+        let compile = |globs: &[Glob]| globs.iter().map(|glob| {
+            GlobBuilder::new(glob.glob()).literal_separator(true).build()
+                .map_or_else(|_| glob.compile_matcher(), |glob| glob.compile_matcher())
+        }).collect();
+        let defaults = match self.no_default_deny {
+            true => Vec::new(),
+            false => DEFAULT_DENY.iter().map(|rule| Glob::new(rule).expect("Invalid default rule")).collect(),
+        };
+        AccessMatcher {
+            allow: compile(&self.allow),
+            deny: compile(&[defaults, self.deny.clone()].concat()),
+        }
+    }
+}
+
+/// Compiled [`AccessRules`]. The default one allows everything
+#[derive(Clone, Debug, Default)]
+pub struct AccessMatcher {
+    allow: Vec<GlobMatcher>,
+    deny: Vec<GlobMatcher>,
+}
+
+impl AccessMatcher {
+    /// Checks a directory to be served against the absolute rules
+    ///
+    /// # Arguments
+    /// * `dir` - The directory, with all links resolved
+    pub fn check_dir(&self, dir: &Path) -> Result<(), QuickServeError> {
+        if self.is_denied(dir, true) {
+            return Err(QuickServeError::validation(format!("Cannot serve from denied directory: {}", dir.display())));
+        }
+        Ok(())
+    }
+
+    /// Checks a path within the served tree against the relative rules
+    ///
+    /// # Arguments
+    /// * `path` - The path, relative to the served tree
+    pub fn check_file(&self, path: &str) -> Result<(), QuickServeError> {
+        if self.is_denied(Path::new(path), false) {
+            return Err(QuickServeError::validation(format!("Access denied: {}", path)));
+        }
+        Ok(())
+    }
+
+    /// Whether the path, or any of its parents, is denied and not allowed
+    fn is_denied(&self, path: &Path, absolute: bool) -> bool {
+        let applies = |m: &&GlobMatcher| m.glob().glob().starts_with('/') == absolute;
+        let matches = |rules: &[GlobMatcher]| path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| rules.iter().filter(applies).any(|m| m.is_match(p)));
+
+        matches(&self.deny) && !matches(&self.allow)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allow: &[&str], deny: &[&str]) -> AccessMatcher {
+        AccessRules {
+            allow: allow.iter().map(|r| Glob::new(r).unwrap()).collect(),
+            deny: deny.iter().map(|r| Glob::new(r).unwrap()).collect(),
+            no_default_deny: true,
+            unrestricted: false,
+        }.matcher()
+    }
+
+    #[test]
+    fn test_default_rules_on_dirs() {
+        let matcher = AccessRules::default().matcher();
+        for dir in ["/etc", "/etc/ssl", "/proc/1", "/home/user/.ssh", "/root/.ssh/keys", "/home/user/.gnupg"] {
+            assert!(matcher.check_dir(Path::new(dir)).is_err(), "{} should be denied", dir);
+        }
+        for dir in ["/root", "/root/project", "/home/user/images", "/tmp/.tmpXYZ", "/srv/etc"] {
+            assert!(matcher.check_dir(Path::new(dir)).is_ok(), "{} should be allowed", dir);
+        }
+    }
+
+    #[test]
+    fn test_default_rules_on_files() {
+        let matcher = AccessRules::default().matcher();
+        for file in [".git", ".git/config", "fw/.hidden", "a/.env/b", ".ssh/id_rsa"] {
+            assert!(matcher.check_file(file).is_err(), "{} should be denied", file);
+        }
+        for file in ["data.bin", "fw/a.bin", "etc/passwd", "a.b/c", ""] {
+            assert!(matcher.check_file(file).is_ok(), "{} should be allowed", file);
+        }
+    }
+
+    #[test]
+    fn test_allow_overrides_deny() {
+        let matcher = rules(&[".well-known"], &["**/.*", "*.key"]);
+        assert!(matcher.check_file(".well-known/acme").is_ok());
+        assert!(matcher.check_file(".git/config").is_err());
+        assert!(matcher.check_file("server.key").is_err());
+    }
+
+    #[test]
+    fn test_star_does_not_cross_separators() {
+        let matcher = rules(&[], &["*.key", "/srv/*/private"]);
+        assert!(matcher.check_file("server.key").is_err());
+        assert!(matcher.check_file("certs/server.key").is_ok(), "only at the root of the tree");
+        assert!(matcher.check_dir(Path::new("/srv/a/private")).is_err());
+        assert!(matcher.check_dir(Path::new("/srv/a/b/private")).is_ok());
+        assert!(rules(&[], &["**/*.key"]).check_file("certs/server.key").is_err());
+    }
+
+    #[test]
+    fn test_unrestricted_allows_everything() {
+        let matcher = AccessRules { unrestricted: true, ..Default::default() }.matcher();
+        assert!(matcher.check_dir(Path::new("/etc")).is_ok());
+        assert!(matcher.check_file(".git/config").is_ok());
+    }
+
+    #[test]
+    fn test_rules_from_toml() {
+        let rules: AccessRules = toml::from_str("allow = [\".well-known\"]").unwrap();
+        assert_eq!(rules.allow.len(), 1);
+        assert!(toml::from_str::<AccessRules>("deny = [\"a[\"]").is_err(), "invalid globs are rejected");
+
+        let matcher = toml::from_str::<AccessRules>("deny = [\"*.key\"]").unwrap().matcher();
+        assert!(matcher.check_file("server.key").is_err());
+        assert!(matcher.check_file(".git/config").is_err(), "default deny rules are kept");
+        assert!(matcher.check_dir(Path::new("/etc")).is_err());
+
+        let matcher = toml::from_str::<AccessRules>("deny = [\"*.key\"]\nno_default_deny = true").unwrap().matcher();
+        assert!(matcher.check_file("server.key").is_err());
+        assert!(matcher.check_file(".git/config").is_ok(), "only when explicitly left out");
+    }
+}
@@ -1,14 +1,268 @@
-use log::{Log, Metadata, Record, LevelFilter};
+use log::{Level, Log, Metadata, Record, LevelFilter};
+use log::kv::{self, VisitSource};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
+use crate::common::QuickServeError;
+use crate::servers::Protocol;
+
+/// Size past which the log file is rotated, by default
+pub const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated log files kept, besides the current one
+pub const LOG_FILES_KEPT: usize = 5;
+
+/// How log records are written, to stdout and to the log file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[LEVEL] target: message`, for humans
+    #[default]
+    Text,
+    /// One JSON object per line, with the structured fields of each record
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = QuickServeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(QuickServeError::validation(format!("Invalid log format: {} (expected text or json)", s))),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })
+    }
+}
+
+/// Log file rotated once it grows past a size
+///
+/// Older files get a numbered suffix, `.1` being the most recent one.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    /// Opens a log file, appending to it if it exists
+    ///
+    /// # Arguments
+    /// * `path` - The log file
+    /// * `max_size` - The size past which it is rotated
+    /// * `keep` - The number of rotated files kept
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path: path.to_path_buf(), file, size, max_size, keep })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            for n in (1..self.keep).rev() {
+                match std::fs::rename(self.rotated(n), self.rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Appends a line, rotating the file first if it would grow too large
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Which records are logged, per protocol, e.g. `tftp,dhcp:debug`
+///
+/// Each comma separated entry names a protocol, optionally followed by the
+/// level it is logged at (the default one otherwise). Once any protocol is
+/// given, the records of the other protocols are left out. Records not tied
+/// to a protocol are always logged, at the default level.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    rules: Vec<(Protocol, Option<LevelFilter>)>,
+}
+
+impl LogFilter {
+    /// Whether a record passes the filter
+    ///
+    /// # Arguments
+    /// * `protocol` - The protocol the record comes from, if any
+    /// * `level` - The level of the record
+    /// * `default` - The level of the protocols given without one
+    pub fn allows(&self, protocol: Option<&Protocol>, level: Level, default: LevelFilter) -> bool {
+        let max = match protocol {
+            Some(protocol) if !self.rules.is_empty() => match self.rules.iter().find(|(p, _)| p == protocol) {
+                Some((_, max)) => max.unwrap_or(default),
+                None => return false,
+            },
+            _ => default,
+        };
+        level <= max
+    }
+
+    /// The most verbose level any record may be logged at
+    pub fn max_level(&self, default: LevelFilter) -> LevelFilter {
+        self.rules.iter().map(|(_, max)| max.unwrap_or(default)).fold(default, Ord::max)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = QuickServeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (protocol, level) = match entry.split_once(':') {
+                Some((protocol, level)) => {
+                    let level = level.parse::<LevelFilter>()
+                        .map_err(|_| QuickServeError::validation(format!("Invalid log level: {} (expected error, warn, info, debug or trace)", level)))?;
+                    (protocol, Some(level))
+                }
+                None => (entry, None),
+            };
+            rules.push((protocol.parse()?, level));
+        }
+        Ok(LogFilter { rules })
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self.rules.iter()
+            .map(|(protocol, level)| match level {
+                Some(level) => format!("{}:{}", protocol.to_string(), level.as_str().to_lowercase()),
+                None => protocol.to_string().to_string(),
+            })
+            .collect();
+        f.write_str(&entries.join(","))
+    }
+}
+
+/// The protocol a record comes from
+///
+/// Taken from its `protocol` field if any, or else from the module that
+/// logged it, the libraries backing each protocol included.
+pub fn record_protocol(record: &Record) -> Option<Protocol> {
+    if let Some(protocol) = record.key_values().get(kv::Key::from("protocol")) {
+        if let Ok(protocol) = protocol.to_string().parse() {
+            return Some(protocol);
+        }
+    }
+    let target = record.target();
+    let module = target.strip_prefix("quick_serve::servers::").unwrap_or(target);
+    let crate_name = module.split("::").next().unwrap_or_default();
+    match crate_name {
+        "http" => Some(Protocol::Http),
+        "ftp" | "libunftp" | "unftp_core" => Some(Protocol::Ftp),
+        "tftp" | "async_tftp" => Some(Protocol::Tftp),
+        "dhcp" | "dhcp_server" | "dhcp4r" => Some(Protocol::Dhcp),
+        _ => None,
+    }
+}
+
+/// A record kept for the UI, tagged with the protocol it comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Sequence number, increasing with each record
+    pub id: u64,
+    pub level: Level,
+    pub protocol: Option<Protocol>,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// The line shown, with an icon for the level
+    pub fn line(&self) -> String {
+        let icon = match self.level {
+            Level::Error => "❌",
+            Level::Warn  => "⚠",
+            Level::Info  => "ℹ",
+            Level::Debug => "?",
+            Level::Trace => "~",
+        };
+        format!("{} {}", icon, self.message)
+    }
+}
+
+/// Collects the structured fields of a record, e.g. `protocol` or `client`
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = match (value.to_u64(), value.to_i64(), value.to_bool()) {
+            (Some(n), _, _) => n.into(),
+            (_, Some(n), _) => n.into(),
+            (_, _, Some(b)) => b.into(),
+            _ => value.to_string().into(),
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Formats a record as a JSON object, with its structured fields
+pub fn json_line(record: &Record) -> String {
+    let mut fields = JsonFields(serde_json::Map::new());
+    fields.0.insert("timestamp".into(), chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false).into());
+    fields.0.insert("level".into(), record.level().as_str().into());
+    fields.0.insert("target".into(), record.target().into());
+    fields.0.insert("message".into(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut fields);
+    if !fields.0.contains_key("protocol") {
+        if let Some(protocol) = record_protocol(record) {
+            fields.0.insert("protocol".into(), protocol.to_string().into());
+        }
+    }
+    serde_json::Value::Object(fields.0).to_string()
+}
+
 pub trait MyLoggerFn {
     fn new (log_level: LevelFilter) -> Self;
 }
 
 pub struct MyLogger {
     pub log_level: LevelFilter,
-    pub logs: Arc<Mutex<Vec<String>>>,
+    pub logs: Arc<Mutex<Vec<LogEntry>>>,
     max_logs: usize,
+    next_id: AtomicU64,
+    format: LogFormat,
+    filter: LogFilter,
+    stdout: bool,
+    file: Option<Mutex<RotatingFile>>,
 }
 
 impl MyLoggerFn for MyLogger {
@@ -17,34 +271,78 @@ impl MyLoggerFn for MyLogger {
             logs: Arc::new(Mutex::new(Vec::new())),
             log_level,
             max_logs: 10000, // Maximum number of logs to keep in memory
+            next_id: AtomicU64::new(1),
+            format: LogFormat::default(),
+            filter: LogFilter::default(),
+            stdout: true,
+            file: None,
         }
     }
 }
 
+impl MyLogger {
+    /// Sets how records are written to stdout and to the log file
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Only logs the records passing the filter, everywhere
+    pub fn with_filter(mut self, filter: LogFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Whether records are printed to stdout, which the terminal interface takes over
+    pub fn with_stdout(mut self, stdout: bool) -> Self {
+        self.stdout = stdout;
+        self
+    }
+
+    /// Also writes the records to a file
+    pub fn with_file(mut self, file: RotatingFile) -> Self {
+        self.file = Some(Mutex::new(file));
+        self
+    }
+}
+
 impl Log for MyLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
         // Determine whether to enable logging for the given metadata
         // For example, you can enable all levels or only certain levels
-        metadata.level() <= self.log_level
+        metadata.level() <= self.filter.max_level(self.log_level)
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            println!("[{}] {}: {}", record.level(), record.target(), record.args());
+        let protocol = record_protocol(record);
+        if self.enabled(record.metadata()) && self.filter.allows(protocol.as_ref(), record.level(), self.log_level) {
+            let line = match self.format {
+                LogFormat::Text => format!("[{}] {}: {}", record.level(), record.target(), record.args()),
+                LogFormat::Json => json_line(record),
+            };
+            if self.stdout {
+                println!("{}", line);
+            }
+
+            if let Some(file) = &self.file {
+                if let Ok(mut file) = file.lock() {
+                    if let Err(e) = file.write_line(&line) {
+                        eprintln!("Failed to write to the log file: {}", e);
+                    }
+                }
+            }
 
             // Only push logs from internal components to the UI
             if record.target().starts_with("quick_serve") {
-                let ui_icon = match record.level() {
-                    log::Level::Error => "❌",
-                    log::Level::Warn  => "⚠",
-                    log::Level::Info  => "ℹ",
-                    log::Level::Debug => "?",
-                    log::Level::Trace => "~",
+                let entry = LogEntry {
+                    id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                    level: record.level(),
+                    protocol,
+                    message: record.args().to_string(),
                 };
-                let ui_line = format!("{} {}", ui_icon, record.args());
 
                 if let Ok(mut logs) = self.logs.lock() {
-                    logs.push(ui_line);
+                    logs.push(entry);
 
                     if logs.len() > self.max_logs {
                         let excess = logs.len() - self.max_logs;
@@ -55,5 +353,97 @@ impl Log for MyLogger {
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_line_has_structured_fields() {
+        let client: std::net::SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        let kvs: [(&str, kv::Value); 4] = [
+            ("protocol", kv::Value::from("tftp")),
+            ("client", kv::Value::from_display(&client)),
+            ("event", kv::Value::from("download")),
+            ("bytes", kv::Value::from(1000u64)),
+        ];
+        let line = json_line(&Record::builder()
+            .level(log::Level::Info)
+            .target("quick_serve::servers::tftp")
+            .args(format_args!("Sending a.bin"))
+            .key_values(&kvs)
+            .build());
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["message"], "Sending a.bin");
+        assert_eq!(json["protocol"], "tftp");
+        assert_eq!(json["client"], "10.0.0.2:40000");
+        assert_eq!(json["event"], "download");
+        assert_eq!(json["bytes"], 1000);
+        assert!(chrono::DateTime::parse_from_rfc3339(json["timestamp"].as_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quick-serve.log");
+        let mut file = RotatingFile::open(&path, 20, 2).unwrap();
+
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |p: PathBuf| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), "fourth line\n");
+        assert_eq!(read(dir.path().join("quick-serve.log.1")), "third line\n");
+        assert_eq!(read(dir.path().join("quick-serve.log.2")), "second line\n");
+        assert!(!dir.path().join("quick-serve.log.3").exists(), "only 2 rotated files are kept");
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let filter: LogFilter = "tftp, dhcp:debug".parse().unwrap();
+        let info = LevelFilter::Info;
+        assert!(filter.allows(Some(&Protocol::Tftp), Level::Info, info));
+        assert!(!filter.allows(Some(&Protocol::Tftp), Level::Debug, info));
+        assert!(filter.allows(Some(&Protocol::Dhcp), Level::Debug, info));
+        assert!(!filter.allows(Some(&Protocol::Http), Level::Error, info), "other protocols are left out");
+        assert!(filter.allows(None, Level::Info, info), "general records are kept");
+        assert!(!filter.allows(None, Level::Debug, info));
+        assert_eq!(filter.max_level(info), LevelFilter::Debug);
+        assert_eq!(filter.to_string(), "tftp,dhcp:debug");
+
+        assert!(LogFilter::default().allows(Some(&Protocol::Http), Level::Info, info));
+        assert!("smtp".parse::<LogFilter>().is_err());
+        assert!("tftp:loud".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn test_record_protocol() {
+        let record = |target: &'static str, kvs: &[(&str, kv::Value)]| {
+            record_protocol(&Record::builder().target(target).key_values(&kvs).build())
+        };
+        assert_eq!(record("quick_serve::servers::tftp", &[]), Some(Protocol::Tftp));
+        assert_eq!(record("quick_serve::servers::dhcp_server::dhcp_server", &[]), Some(Protocol::Dhcp));
+        assert_eq!(record("libunftp::server::controlchan", &[]), Some(Protocol::Ftp));
+        assert_eq!(record("quick_serve::servers::server", &[("protocol", kv::Value::from("http"))]), Some(Protocol::Http));
+        assert_eq!(record("quick_serve::servers::server", &[]), None);
+    }
+
+    #[test]
+    fn test_log_format_parsing() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!(LogFormat::Text.to_string(), "text");
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
 }
@@ -1,3 +1,6 @@
 // Import and re-export the submodule files.
+pub mod access;
 pub mod validation;
 pub mod logger;
+pub mod net;
+pub mod snippets;
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+
+use if_addrs::IfAddr;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{QuickServeError, QuickServeResult};
+
+/// Bind IP listening on all interfaces, both IPv4 and IPv6
+pub const ALL_INTERFACES: &str = "0.0.0.0, ::";
+
+/// Creates a socket for the given address family
+///
+/// IPv6 sockets are made IPv6 only, so that `::` and `0.0.0.0` can be
+/// bound side by side on the same port (e.g. for dual-stack serving).
+fn socket(addr: &SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*addr), ty, Some(protocol))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    Ok(socket)
+}
+
+/// Binds a non-blocking TCP listener, ready to be handed to tokio
+///
+/// # Arguments
+/// * `addr` - The address to listen on. Port 0 lets the OS pick one
+pub fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = socket(&addr, Type::STREAM, Protocol::TCP)?;
+    // Same as the standard library does, to allow restarting right away
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Binds a non-blocking UDP socket
+///
+/// # Arguments
+/// * `addr` - The address to bind. Port 0 lets the OS pick one
+pub fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = socket(&addr, Type::DGRAM, Protocol::UDP)?;
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Binds a blocking UDP socket receiving broadcasts, as DHCP needs
+///
+/// Broadcasts only reach sockets bound to the unspecified address, so the
+/// socket is bound to `0.0.0.0` and, unless `ip` is unspecified itself, tied
+/// to the interface owning `ip`, for the other interfaces not to be answered
+/// on. Only Linux and macOS can tie a socket to an interface, the others
+/// receiving from all interfaces.
+///
+/// # Arguments
+/// * `ip` - The address of the interface to receive from
+/// * `port` - The port to bind
+pub fn bind_udp_broadcast(ip: Ipv4Addr, port: u16) -> io::Result<UdpSocket> {
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    let socket = socket(&addr, Type::DGRAM, Protocol::UDP)?;
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    if !ip.is_unspecified() {
+        let index = if_addrs::get_if_addrs()?.into_iter()
+            .find(|iface| iface.ip() == IpAddr::V4(ip))
+            .and_then(|iface| iface.index)
+            .and_then(std::num::NonZeroU32::new)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("no network interface has the address {}", ip)))?;
+        socket.bind_device_by_index_v4(Some(index))?;
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+    let _ = ip;
+    socket.set_broadcast(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+/// An address of a local network interface
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceAddr {
+    /// The interface name, e.g. `eth0`
+    pub name: String,
+    pub ip: IpAddr,
+    /// Length of the network prefix, e.g. 24 for a /24 subnet
+    pub prefix_len: u8,
+}
+
+impl fmt::Display for InterfaceAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}/{})", self.name, self.ip, self.prefix_len)
+    }
+}
+
+/// Lists the addresses of the local network interfaces, loopback included
+///
+/// IPv6 link-local addresses are left out, as they cannot be bound without
+/// their scope.
+pub fn interfaces() -> io::Result<Vec<InterfaceAddr>> {
+    let list = if_addrs::get_if_addrs()?.into_iter()
+        .filter(|iface| !matches!(&iface.addr, IfAddr::V6(addr) if addr.is_link_local()))
+        .map(|iface| InterfaceAddr {
+            ip: iface.ip(),
+            prefix_len: match &iface.addr {
+                IfAddr::V4(addr) => addr.prefixlen,
+                IfAddr::V6(addr) => addr.prefixlen,
+            },
+            name: iface.name,
+        })
+        .collect();
+    Ok(list)
+}
+
+/// Picks the addresses of the interface named out of a list
+fn find_interface(list: Vec<InterfaceAddr>, name: &str) -> QuickServeResult<Vec<InterfaceAddr>> {
+    let (found, others): (Vec<_>, Vec<_>) = list.into_iter().partition(|iface| iface.name == name);
+    if found.is_empty() {
+        let mut names: Vec<String> = others.into_iter().map(|iface| iface.name).collect();
+        names.dedup();
+        return Err(QuickServeError::validation(format!("No interface {} with an address, found: {}", name, names.join(", "))));
+    }
+    Ok(found)
+}
+
+/// Returns the addresses of a local network interface, e.g. for `--bind-iface`
+///
+/// # Arguments
+/// * `name` - The interface name, e.g. `eth0`
+///
+/// # Returns
+/// * `Ok(Vec<InterfaceAddr>)` - Its addresses, IPv4 and IPv6
+/// * `Err(QuickServeError)` - If there is no such interface, or it has no address
+pub fn interface_addrs(name: &str) -> QuickServeResult<Vec<InterfaceAddr>> {
+    find_interface(interfaces()?, name)
+}
+
+/// Formats addresses as a comma separated bind IP
+pub fn bind_ip(addrs: &[InterfaceAddr]) -> String {
+    addrs.iter().map(|iface| iface.ip.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_and_ipv6_share_port() {
+        let v4 = bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = v4.local_addr().unwrap().port();
+
+        // Skip on hosts without IPv6
+        let Ok(v6) = bind_tcp(SocketAddr::new("::1".parse().unwrap(), port)) else { return };
+        assert_eq!(v6.local_addr().unwrap().port(), port);
+    }
+
+    #[test]
+    fn test_udp_ephemeral_port() {
+        let socket = bind_udp("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_ne!(socket.local_addr().unwrap().port(), 0);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    #[test]
+    fn test_udp_broadcast_on_interface() {
+        let socket = bind_udp_broadcast(Ipv4Addr::LOCALHOST, 0).unwrap();
+        assert!(socket.local_addr().unwrap().ip().is_unspecified(), "bound to all addresses, for broadcasts");
+        assert!(socket.broadcast().unwrap());
+
+        let err = bind_udp_broadcast("192.0.2.1".parse().unwrap(), 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable, "no interface with that address");
+    }
+
+    #[test]
+    fn test_find_interface() {
+        let iface = |name: &str, ip: &str| InterfaceAddr { name: name.into(), ip: ip.parse().unwrap(), prefix_len: 24 };
+        let list = vec![iface("lo", "127.0.0.1"), iface("eth0", "192.168.1.2"), iface("eth0", "2001:db8::2"), iface("wlan0", "10.0.0.5")];
+
+        let eth0 = find_interface(list.clone(), "eth0").unwrap();
+        assert_eq!(bind_ip(&eth0), "192.168.1.2, 2001:db8::2");
+        assert!(crate::utils::validation::parse_bind_addresses(&bind_ip(&eth0), 8080).is_ok());
+
+        let err = find_interface(list, "eth1").unwrap_err().to_string();
+        assert!(err.contains("lo, eth0, wlan0"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_interfaces_include_loopback() {
+        let list = interfaces().unwrap();
+        assert!(list.iter().any(|iface| iface.ip.is_loopback()));
+        assert!(bind_ip(&interface_addrs(&list[0].name).unwrap()).contains(&list[0].ip.to_string()));
+    }
+}
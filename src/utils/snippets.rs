@@ -0,0 +1,241 @@
+//! Ready-to-copy commands fetching a file from the servers, with the usual
+//! clients found on the targets (`wget`, `curl`, BusyBox `tftp`, U-Boot).
+
+use std::net::{IpAddr, SocketAddr};
+
+use log::info;
+
+use crate::servers::{Protocol, ServerStatus};
+use crate::utils::net::{self, InterfaceAddr};
+use crate::Config;
+
+/// U-Boot's usual load address, to be changed to suit the board
+const UBOOT_LOAD_ADDR: &str = "0x80000000";
+
+/// A command fetching a file, along with the client it is for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snippet {
+    /// The client, e.g. `curl` or `U-Boot`
+    pub client: &'static str,
+    pub command: String,
+}
+
+/// The addresses clients may reach a server listening on `addr` at
+///
+/// A server listening on all interfaces (`0.0.0.0` or `::`) is reachable at
+/// the addresses of the interfaces of that family, loopback only if there
+/// is nothing else. IPv6 link-local addresses are left out, as clients could
+/// not use them without a scope.
+pub fn reachable(addr: SocketAddr, interfaces: &[InterfaceAddr]) -> Vec<SocketAddr> {
+    if !addr.ip().is_unspecified() {
+        return vec![addr];
+    }
+    let same_family: Vec<IpAddr> = interfaces.iter()
+        .map(|iface| iface.ip)
+        .filter(|ip| ip.is_ipv4() == addr.is_ipv4())
+        .filter(|ip| !matches!(ip, IpAddr::V6(ip) if ip.is_unicast_link_local()))
+        .collect();
+    let external: Vec<IpAddr> = same_family.iter().copied().filter(|ip| !ip.is_loopback()).collect();
+    let ips = if external.is_empty() { same_family } else { external };
+    ips.into_iter().map(|ip| SocketAddr::new(ip, addr.port())).collect()
+}
+
+/// Quotes a word for a POSIX shell, if needed
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+:@=,%".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Percent-encodes a path for a URL, leaving its `/` separators alone
+fn url_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Commands fetching a file from a server
+///
+/// # Arguments
+/// * `protocol` - The protocol the server serves
+/// * `addr` - An address the server is reachable at (see [`reachable`])
+/// * `file` - The file, within the served tree
+/// * `user` - The user to log in as, for servers requiring it
+///
+/// # Returns
+/// The commands, none for protocols not serving files
+pub fn snippets(protocol: &Protocol, addr: SocketAddr, file: &str, user: Option<&str>) -> Vec<Snippet> {
+    let file = file.trim_start_matches('/');
+    let name = file.rsplit('/').next().unwrap_or(file);
+    let url = |scheme: &str| format!("{}://{}/{}", scheme, addr, url_path(file));
+    let snippet = |client, command: String| Snippet { client, command };
+    // Not to take the brackets of an IPv6 address for a pattern
+    let curl = if addr.is_ipv6() { "curl -g" } else { "curl" };
+
+    match protocol {
+        Protocol::Http | Protocol::Ftp => {
+            let url = quote(&url(if *protocol == Protocol::Http { "http" } else { "ftp" }));
+            let (wget_user, curl_user) = match user {
+                Some(user) => (format!("--user={} --ask-password ", quote(user)), format!("-u {} ", quote(user))),
+                None => Default::default(),
+            };
+            vec![
+                snippet("wget", format!("wget {}{}", wget_user, url)),
+                snippet("curl", format!("{} {}-o {} {}", curl, curl_user, quote(name), url)),
+            ]
+        }
+        Protocol::Tftp => {
+            let mut list = vec![
+                snippet("tftp", format!("tftp -g -r {} -l {} {} {}", quote(file), quote(name), addr.ip(), addr.port())),
+                snippet("curl", format!("{} -o {} {}", curl, quote(name), quote(&url("tftp")))),
+            ];
+            // Only over IPv4, on port 69 unless built with CONFIG_TFTP_PORT
+            if addr.is_ipv4() {
+                let port = if addr.port() == 69 { String::new() } else { format!("setenv tftpdstp {}; ", addr.port()) };
+                list.push(snippet("U-Boot", format!("setenv serverip {}; {}tftpboot {} {}", addr.ip(), port, UBOOT_LOAD_ADDR, file)));
+            }
+            list
+        }
+        Protocol::Dhcp | Protocol::Custom(_) => Vec::new(),
+    }
+}
+
+/// The first file at the root of a directory, hidden ones left out, for the
+/// commands to fetch when none is selected
+pub fn first_file(dir: &str) -> Option<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    names.into_iter().next()
+}
+
+/// Commands fetching a file from a server, at each address it is reachable at
+///
+/// # Arguments
+/// * `protocol` - The protocol the server serves
+/// * `listening` - The addresses the server listens on
+/// * `file` - The file, within the served tree
+/// * `user` - The user to log in as, for servers requiring it
+/// * `interfaces` - The local interfaces, to tell where a server listening on all of them is reachable
+pub fn server_snippets(protocol: &Protocol, listening: &[SocketAddr], file: &str, user: Option<&str>, interfaces: &[InterfaceAddr]) -> Vec<Snippet> {
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for addr in listening.iter().flat_map(|addr| reachable(*addr, interfaces)) {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs.into_iter().flat_map(|addr| snippets(protocol, addr, file, user)).collect()
+}
+
+/// The user the servers of a protocol require to log in as, if any
+pub fn user(config: &Config, protocol: &Protocol) -> Option<String> {
+    let auth = match protocol {
+        Protocol::Http => config.http.auth.as_ref(),
+        Protocol::Ftp => config.ftp.auth.as_ref(),
+        _ => None,
+    };
+    auth.map(|credentials| credentials.username.clone())
+}
+
+/// Logs the commands fetching a file from each server listening, for the
+/// `example_file` set or else the first file each serves
+///
+/// # Arguments
+/// * `config` - The settings the servers were started with
+/// * `states` - The state of each server started
+pub fn log_client_commands(config: &Config, states: &[(Protocol, ServerStatus)]) {
+    let interfaces = net::interfaces().unwrap_or_default();
+    for (protocol, status) in states {
+        let ServerStatus::Listening(addrs) = status else { continue };
+        let file = config.example_file.clone()
+            .or_else(|| first_file(config.serve_dir_for(protocol)))
+            .unwrap_or_else(|| "FILE".into());
+        for snippet in server_snippets(protocol, addrs, &file, user(config, protocol).as_deref(), &interfaces) {
+            info!(protocol = protocol.to_string(), event = "client command"; "{} with {}: {}",
+                protocol.to_string().to_uppercase(), snippet.client, snippet.command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iface(ip: &str) -> InterfaceAddr {
+        InterfaceAddr { name: "eth0".into(), ip: ip.parse().unwrap(), prefix_len: 24 }
+    }
+
+    #[test]
+    fn test_reachable() {
+        let interfaces = [iface("127.0.0.1"), iface("192.168.1.2"), iface("::1"), iface("fe80::1"), iface("2001:db8::2")];
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+
+        assert_eq!(reachable(addr("10.0.0.1:69"), &interfaces), vec![addr("10.0.0.1:69")]);
+        assert_eq!(reachable(addr("0.0.0.0:69"), &interfaces), vec![addr("192.168.1.2:69")]);
+        assert_eq!(reachable(addr("[::]:8080"), &interfaces), vec![addr("[2001:db8::2]:8080")]);
+        assert_eq!(reachable(addr("0.0.0.0:69"), &interfaces[..1]), vec![addr("127.0.0.1:69")], "loopback if nothing else");
+        assert_eq!(reachable(addr("[::]:8080"), &interfaces[2..4]), vec![addr("[::1]:8080")], "no link-local");
+    }
+
+    #[test]
+    fn test_first_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        assert_eq!(first_file(path), None);
+
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        std::fs::write(dir.path().join(".hidden"), b"").unwrap();
+        std::fs::write(dir.path().join("z.bin"), b"").unwrap();
+        std::fs::write(dir.path().join("Image"), b"").unwrap();
+        assert_eq!(first_file(path).as_deref(), Some("Image"));
+        assert_eq!(first_file("/nonexistent"), None);
+    }
+
+    #[test]
+    fn test_server_snippets() {
+        let interfaces = [iface("127.0.0.1"), iface("192.168.1.2")];
+        let listening = ["0.0.0.0:8080".parse().unwrap(), "192.168.1.2:8080".parse().unwrap()];
+        let list = server_snippets(&Protocol::Http, &listening, "a.bin", None, &interfaces);
+        assert_eq!(list.len(), 2, "the same address once: {:?}", list);
+        assert_eq!(list[0].command, "wget http://192.168.1.2:8080/a.bin");
+
+        let config = Config { http: crate::HttpSection {
+            auth: Some(crate::Credentials { username: "admin".into(), password: "secret".into() }),
+            ..Default::default()
+        }, ..Default::default() };
+        assert_eq!(user(&config, &Protocol::Http).as_deref(), Some("admin"));
+        assert_eq!(user(&config, &Protocol::Ftp), None);
+    }
+
+    #[test]
+    fn test_snippets() {
+        let addr: SocketAddr = "192.168.1.2:69".parse().unwrap();
+        let tftp = snippets(&Protocol::Tftp, addr, "/boot/Image", None);
+        assert_eq!(tftp.iter().find(|s| s.client == "U-Boot").unwrap().command,
+            "setenv serverip 192.168.1.2; tftpboot 0x80000000 boot/Image");
+        assert_eq!(tftp[0].command, "tftp -g -r boot/Image -l Image 192.168.1.2 69");
+
+        let tftp = snippets(&Protocol::Tftp, "192.168.1.2:6969".parse().unwrap(), "Image", None);
+        assert!(tftp.iter().any(|s| s.command.starts_with("setenv serverip 192.168.1.2; setenv tftpdstp 6969; ")));
+        assert!(snippets(&Protocol::Tftp, "[::1]:6969".parse().unwrap(), "Image", None).iter().all(|s| s.client != "U-Boot"));
+
+        let http = snippets(&Protocol::Http, "[::1]:8080".parse().unwrap(), "my file.bin", Some("admin"));
+        assert_eq!(http[0].command, "wget --user=admin --ask-password 'http://[::1]:8080/my%20file.bin'");
+        assert_eq!(http[1].command, "curl -g -u admin -o 'my file.bin' 'http://[::1]:8080/my%20file.bin'");
+
+        let ftp = snippets(&Protocol::Ftp, "127.0.0.1:2121".parse().unwrap(), "a.bin", None);
+        assert_eq!(ftp[1].command, "curl -o a.bin ftp://127.0.0.1:2121/a.bin");
+        assert!(snippets(&Protocol::Dhcp, addr, "a.bin", None).is_empty());
+    }
+}
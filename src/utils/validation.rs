@@ -1,11 +1,43 @@
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
 use crate::common::QuickServeError;
+use crate::utils::access::AccessMatcher;
+
+/// Parses an IP address, accepting IPv6 literals with or without brackets
+///
+/// # Arguments
+/// * `ip` - The IP address, e.g. `127.0.0.1`, `::1` or `[::1]`
+///
+/// # Returns
+/// * `Ok(IpAddr)` - The parsed address
+/// * `Err(QuickServeError)` - If the address is empty or invalid
+pub fn parse_ip(ip: &str) -> Result<IpAddr, QuickServeError> {
+    let ip = ip.trim();
+    if ip.is_empty() {
+        return Err(QuickServeError::validation("IP address cannot be empty"));
+    }
+
+    let bracketed = ip.strip_prefix('[').and_then(|ip| ip.strip_suffix(']'));
+    let addr = match bracketed {
+        Some(inner) => inner.parse::<Ipv6Addr>().map(IpAddr::V6),
+        None => ip.parse::<IpAddr>(),
+    };
+    addr.map_err(|e| QuickServeError::validation(format!("Invalid IP:PORT format: {} ({})", e, ip)))
+}
 
 /// Validates an IP address and port combination
 ///
 /// Checks for empty IPs, invalid formats, and privileged ports.
-/// Allows binding to 0.0.0.0 for listening on all interfaces.
+/// Allows binding to 0.0.0.0 (or ::) for listening on all interfaces, and
+/// port 0 for letting the OS pick a free (ephemeral) port. IPv6 addresses
+/// may be given with or without brackets.
 ///
 /// # Arguments
 /// * `ip` - The IP address to validate
@@ -20,29 +52,74 @@ pub fn validate_ip_port(ip: &str, port: u16) -> Result<(), QuickServeError> {
         return Err(QuickServeError::validation("IP address cannot be empty"));
     }
 
-    // Allow 0.0.0.0 for binding to all interfaces (this is standard practice)
-    // Other validation will be done by parsing
-
-    // Check port range
-    if port == 0 {
-        return Err(QuickServeError::validation("Port cannot be 0"));
-    }
-
-    if port < 1024 && port != 80 && port != 443 {
+    // Check port range. Port 0 is left for the OS to pick.
+    if port != 0 && port < 1024 && port != 80 && port != 443 {
         return Err(QuickServeError::validation("Ports below 1024 require root privileges"));
     }
 
-    let addr = format!("{}:{}", ip, port);
+    parse_ip(ip).map(|_| ())
+}
 
-    match addr.parse::<SocketAddr>() {
-        Ok(socket_addr) => {
-            if socket_addr.is_ipv4() || socket_addr.is_ipv6() {
-                Ok(())
-            } else {
-                Err(QuickServeError::validation("Invalid IP format"))
-            }
+/// Parses a comma separated list of bind addresses
+///
+/// Each address gets its own listener, e.g. `0.0.0.0, ::` to serve on
+/// both IPv4 and IPv6. Duplicates are dropped.
+///
+/// # Arguments
+/// * `list` - The addresses, separated by commas
+/// * `port` - The port the addresses are going to be bound with
+///
+/// # Returns
+/// * `Ok(Vec<IpAddr>)` - The addresses, in the given order
+/// * `Err(QuickServeError)` - If the list is empty or any address is invalid
+pub fn parse_bind_addresses(list: &str, port: u16) -> Result<Vec<IpAddr>, QuickServeError> {
+    let mut addresses = Vec::new();
+    for ip in list.split(',') {
+        validate_ip_port(ip, port)?;
+        let addr = parse_ip(ip)?;
+        if !addresses.contains(&addr) {
+            addresses.push(addr);
         }
-        Err(e) => Err(QuickServeError::validation(format!("Invalid IP:PORT format: {}", e))),
+    }
+    Ok(addresses)
+}
+
+/// Parses a duration, e.g. `90`, `90s`, `500ms`, `5m` or `1h`
+///
+/// A number without unit is taken as seconds.
+///
+/// # Arguments
+/// * `duration` - The duration, as a whole number followed by an optional unit
+///
+/// # Returns
+/// * `Ok(Duration)` - The parsed duration
+/// * `Err(QuickServeError)` - If the number or the unit is invalid
+pub fn parse_duration(duration: &str) -> Result<Duration, QuickServeError> {
+    let duration = duration.trim();
+    let split = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (value, unit) = duration.split_at(split);
+    let value: u64 = value.parse()
+        .map_err(|_| QuickServeError::validation(format!("Invalid duration: {}", duration)))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 3600)),
+        _ => Err(QuickServeError::validation(format!("Invalid duration unit in {} (expected ms, s, m or h)", duration))),
+    }
+}
+
+/// Formats a duration the way [`parse_duration`] reads it, in the largest
+/// unit it is a whole number of, e.g. `5s`, `500ms` or `2m`
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    match millis {
+        0 => "0s".to_string(),
+        _ if millis.is_multiple_of(3_600_000) => format!("{}h", millis / 3_600_000),
+        _ if millis.is_multiple_of(60_000) => format!("{}m", millis / 60_000),
+        _ if millis.is_multiple_of(1000) => format!("{}s", millis / 1000),
+        _ => format!("{}ms", millis),
     }
 }
 
@@ -57,10 +134,10 @@ pub fn ensure_trailing_slash(path: &PathBuf) -> PathBuf {
     if !path.ends_with("/") { 
         let mut p = path.clone().into_os_string();
         p.push("/"); 
-        return p.into();
+        p.into()
     }
     else {
-        return path.into();
+        path.into()
     }
 }
 
@@ -68,15 +145,17 @@ pub fn ensure_trailing_slash(path: &PathBuf) -> PathBuf {
 /// Validates a directory path for serving
 ///
 /// Checks if the path exists, is a directory, is readable, and is not
-/// a sensitive system directory (like /etc, /sys, /proc).
+/// denied by the access rules (by default, system directories like /etc,
+/// /sys, /proc).
 ///
 /// # Arguments
 /// * `path` - The path to validate
+/// * `access` - The rules the directory is checked against
 ///
 /// # Returns
 /// * `Ok(())` if the path is valid and safe to serve
 /// * `Err(QuickServeError)` if validation fails
-pub fn validate_path(path: &PathBuf) -> Result<(), QuickServeError> {
+pub fn validate_path(path: &PathBuf, access: &AccessMatcher) -> Result<(), QuickServeError> {
     // Check if path exists
     if !path.exists() {
         return Err(QuickServeError::validation(format!("Path does not exist: {}", path.display())));
@@ -98,52 +177,146 @@ pub fn validate_path(path: &PathBuf) -> Result<(), QuickServeError> {
         }
     }
 
-    // Security check: prevent serving from sensitive directories
-    let path_str = path.to_string_lossy().to_lowercase();
-    let sensitive_paths = ["/etc", "/sys", "/proc", "/dev", "/root", "/boot"];
-    
-    for sensitive in &sensitive_paths {
-        if path_str.starts_with(sensitive) {
-            return Err(QuickServeError::validation(format!("Cannot serve from sensitive directory: {}", path.display())));
+    // Security check: prevent serving from denied (e.g. system) directories,
+    // wherever the path actually leads to
+    access.check_dir(&path.canonicalize()?)
+}
+
+/// How symbolic links found below the served directory are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Follow links wherever they point to
+    Follow,
+    /// Follow links, as long as they end up inside the served directory
+    #[default]
+    FollowWithinRoot,
+    /// Refuse any path going through a link
+    Deny,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = QuickServeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "follow-within-root" => Ok(SymlinkPolicy::FollowWithinRoot),
+            "deny" => Ok(SymlinkPolicy::Deny),
+            other => Err(QuickServeError::validation(format!(
+                "Invalid symlink policy '{}', expected follow, follow-within-root or deny", other))),
         }
     }
+}
 
-    Ok(())
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SymlinkPolicy::Follow => "follow",
+            SymlinkPolicy::FollowWithinRoot => "follow-within-root",
+            SymlinkPolicy::Deny => "deny",
+        })
+    }
 }
 
-/// Validates a file path for security (prevents path traversal attacks)
-///
-/// Checks for path traversal attempts (..), null bytes, absolute paths,
-/// and ensures the resolved path stays within the base directory.
-///
-/// # Arguments
-/// * `base_path` - The base directory that files must be within
-/// * `requested_path` - The requested file path (relative)
+/// Splits a requested path into its (normal) components
 ///
-/// # Returns
-/// * `Ok(PathBuf)` - The validated full path
-/// * `Err(QuickServeError)` - If the path is invalid or a security risk
-pub fn validate_file_path(base_path: &PathBuf, requested_path: &str) -> Result<PathBuf, QuickServeError> {
-    // Check for path traversal attempts
-    if requested_path.contains("..") || requested_path.contains("//") {
-        return Err(QuickServeError::validation("Path traversal attempt detected"));
+/// `.` components are dropped. Parent (`..`), root and prefix components are
+/// rejected, as are empty ones (e.g. `foo//bar`) and null bytes. Names merely
+/// containing dots (e.g. `foo..bar`) are fine.
+pub fn normalize(requested_path: &str) -> Result<PathBuf, QuickServeError> {
+    if requested_path.contains('\0') {
+        return Err(QuickServeError::validation("Null bytes in path are not allowed"));
     }
-
-    // Check for absolute paths
     if requested_path.starts_with('/') {
         return Err(QuickServeError::validation("Absolute paths are not allowed"));
     }
+    if requested_path.contains("//") {
+        return Err(QuickServeError::validation("Empty path components are not allowed"));
+    }
 
-    // Check for null bytes
-    if requested_path.contains('\0') {
-        return Err(QuickServeError::validation("Null bytes in path are not allowed"));
+    let mut normalized = PathBuf::new();
+    for component in Path::new(requested_path).components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(QuickServeError::validation("Path traversal attempt detected")),
+            Component::RootDir | Component::Prefix(_) => return Err(QuickServeError::validation("Absolute paths are not allowed")),
+        }
     }
+    Ok(normalized)
+}
 
-    let full_path = base_path.join(requested_path);
+/// Resolves all links of a path, which does not need to exist entirely
+///
+/// The longest existing part is canonicalized, and the rest appended as is.
+/// A dangling link cannot be resolved, and is reported as an error.
+pub fn real_path(path: &Path) -> io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        match existing.symlink_metadata() {
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else { break };
+                missing.push(name);
+                existing = parent;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut real = match existing.as_os_str().is_empty() {
+        true => PathBuf::new(),
+        false => existing.canonicalize()?,
+    };
+    real.extend(missing.iter().rev());
+    Ok(real)
+}
 
-    // Ensure the resolved path is still within the base directory
-    if !full_path.starts_with(base_path) {
-        return Err(QuickServeError::validation("Path outside base directory"));
+/// Validates a file path for security (prevents path traversal attacks)
+///
+/// The requested path is split into components, rejecting traversal (`..`),
+/// absolute paths and null bytes. Links found on the way are then handled
+/// according to `policy`. With [`SymlinkPolicy::FollowWithinRoot`], the path
+/// is only accepted if its real location (all links resolved) is still
+/// within the real location of the base directory.
+///
+/// # Arguments
+/// * `base_path` - The base directory that files must be within
+/// * `requested_path` - The requested file path (relative)
+/// * `policy` - How links below the base directory are handled
+///
+/// # Returns
+/// * `Ok(PathBuf)` - The validated full path (not necessarily existing)
+/// * `Err(QuickServeError)` - If the path is invalid or a security risk
+pub fn validate_file_path(base_path: &PathBuf, requested_path: &str, policy: SymlinkPolicy) -> Result<PathBuf, QuickServeError> {
+    let full_path = base_path.join(normalize(requested_path)?);
+
+    match policy {
+        SymlinkPolicy::Follow => {}
+        SymlinkPolicy::FollowWithinRoot => {
+            let real_base = real_path(base_path)?;
+            let real = real_path(&full_path)
+                .map_err(|e| QuickServeError::validation(format!("Cannot resolve path: {}", e)))?;
+            if !real.starts_with(&real_base) {
+                return Err(QuickServeError::validation("Path outside base directory"));
+            }
+        }
+        SymlinkPolicy::Deny => {
+            let mut current = base_path.clone();
+            for component in full_path.strip_prefix(base_path).unwrap_or(Path::new("")).components() {
+                current.push(component);
+                match current.symlink_metadata() {
+                    Ok(meta) if meta.file_type().is_symlink() => {
+                        return Err(QuickServeError::validation("Symbolic links are not allowed"));
+                    }
+                    Ok(_) => {}
+                    // Nothing further down can be a link
+                    Err(_) => break,
+                }
+            }
+        }
     }
 
     Ok(full_path)
@@ -160,6 +333,26 @@ mod tests {
         assert!(result.is_ok(), "Expected Ok, got {:?}", result);
     }
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        for invalid in ["", "s", "-1", "1.5s", "10d", "1 h"] {
+            assert!(parse_duration(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        for (duration, text) in [(0, "0s"), (500, "500ms"), (1500, "1500ms"), (5000, "5s"), (120_000, "2m"), (7_200_000, "2h")] {
+            assert_eq!(format_duration(Duration::from_millis(duration)), text);
+            assert_eq!(parse_duration(text).unwrap(), Duration::from_millis(duration));
+        }
+    }
+
     #[test]
     fn test_ensure_trailing_slash() {
         let result = ensure_trailing_slash(&PathBuf::from("/tmp"));
@@ -188,7 +381,7 @@ mod tests {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp directory");
         let path = temp_dir.path().to_path_buf();
 
-        let result = validate_path(&path);
+        let result = validate_path(&path, &AccessMatcher::default());
         assert!(result.is_ok(), "Expected Ok, got {:?}", result);
     }
 
@@ -197,15 +390,14 @@ mod tests {
         let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
         let path = temp_file.path().to_path_buf();
 
-        let result = validate_path(&path);
+        let result = validate_path(&path, &AccessMatcher::default());
         assert!(result.is_err(), "Expected Err, got {:?}", result);
     }
 
     #[test]
-    fn test_port_zero_is_invalid() {
+    fn test_port_zero_is_ephemeral() {
         let result = validate_ip_port("127.0.0.1", 0);
-        assert!(result.is_err());
-        assert!(result.err().unwrap().to_string().contains("Port cannot be 0"));
+        assert!(result.is_ok(), "Port 0 should be allowed for ephemeral binding, got {:?}", result);
     }
 
     #[test]
@@ -234,17 +426,23 @@ mod tests {
 
     #[test]
     fn test_ipv6_addresses() {
-        // IPv6 addresses need to be in brackets when used with ports
-        let result = validate_ip_port("::1", 8080);
-        // Note: IPv6 validation might fail without brackets, which is expected behavior
-        // for Socket address parsing
-        if result.is_err() {
-            // This is acceptable - IPv6 addresses without brackets may not parse
-            return;
+        for ip in ["::1", "[::1]", "::", "[fe80::1]", " [::1] "] {
+            let result = validate_ip_port(ip, 8080);
+            assert!(result.is_ok(), "{} should be accepted, got {:?}", ip, result);
         }
-        
-        let result = validate_ip_port("127.0.0.1", 8080);
-        assert!(result.is_ok(), "IPv4 should always work");
+        assert_eq!(parse_ip("[::1]").unwrap(), "::1".parse::<IpAddr>().unwrap());
+        assert!(validate_ip_port("[::1", 8080).is_err());
+        assert!(validate_ip_port("[127.0.0.1]", 8080).is_err(), "brackets are for IPv6 only");
+    }
+
+    #[test]
+    fn test_parse_bind_addresses() {
+        let addresses = parse_bind_addresses("0.0.0.0, [::], 0.0.0.0", 8080).unwrap();
+        assert_eq!(addresses, vec!["0.0.0.0".parse::<IpAddr>().unwrap(), "::".parse().unwrap()]);
+
+        assert!(parse_bind_addresses("127.0.0.1,", 8080).is_err(), "empty entries are rejected");
+        assert!(parse_bind_addresses("127.0.0.1, nope", 8080).is_err());
+        assert!(parse_bind_addresses("127.0.0.1", 22).is_err());
     }
 
     #[test]
@@ -259,40 +457,137 @@ mod tests {
         let base = PathBuf::from("/tmp");
         
         // Test various path traversal attempts
-        assert!(validate_file_path(&base, "../etc/passwd").is_err());
-        assert!(validate_file_path(&base, "foo/../../../etc/passwd").is_err());
-        assert!(validate_file_path(&base, "foo//bar").is_err());
+        assert!(validate_file_path(&base, "../etc/passwd", SymlinkPolicy::default()).is_err());
+        assert!(validate_file_path(&base, "foo/../../../etc/passwd", SymlinkPolicy::default()).is_err());
+        assert!(validate_file_path(&base, "foo//bar", SymlinkPolicy::default()).is_err());
     }
 
     #[test]
     fn test_validate_file_path_null_bytes() {
         let base = PathBuf::from("/tmp");
-        assert!(validate_file_path(&base, "foo\0bar").is_err());
+        assert!(validate_file_path(&base, "foo\0bar", SymlinkPolicy::default()).is_err());
     }
 
     #[test]
     fn test_validate_file_path_absolute_paths() {
         let base = PathBuf::from("/tmp");
-        assert!(validate_file_path(&base, "/etc/passwd").is_err());
+        assert!(validate_file_path(&base, "/etc/passwd", SymlinkPolicy::default()).is_err());
     }
 
     #[test]
     fn test_validate_file_path_valid_paths() {
         let base = PathBuf::from("/tmp");
         
-        let result = validate_file_path(&base, "foo/bar.txt");
+        let result = validate_file_path(&base, "foo/bar.txt", SymlinkPolicy::default());
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), PathBuf::from("/tmp/foo/bar.txt"));
         
-        let result = validate_file_path(&base, "test.txt");
+        let result = validate_file_path(&base, "test.txt", SymlinkPolicy::default());
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), PathBuf::from("/tmp/test.txt"));
     }
 
+    #[test]
+    fn test_validate_file_path_dotted_names() {
+        let base = PathBuf::from("/tmp");
+        for name in ["foo..bar", "..foo", "foo..", "./foo"] {
+            let result = validate_file_path(&base, name, SymlinkPolicy::default());
+            assert!(result.is_ok(), "{} should be accepted, got {:?}", name, result);
+        }
+        assert_eq!(validate_file_path(&base, "./a/./b", SymlinkPolicy::default()).unwrap(), PathBuf::from("/tmp/a/b"));
+    }
+
+    #[test]
+    fn test_parse_symlink_policy() {
+        for policy in [SymlinkPolicy::Follow, SymlinkPolicy::FollowWithinRoot, SymlinkPolicy::Deny] {
+            assert_eq!(policy.to_string().parse::<SymlinkPolicy>().unwrap(), policy);
+        }
+        assert!("sometimes".parse::<SymlinkPolicy>().is_err());
+    }
+
+    /// Served root holding links to a file and a directory outside of it,
+    /// a link to a file inside of it and a dangling link out of it
+    #[cfg(unix)]
+    fn symlink_tree() -> (tempfile::TempDir, PathBuf) {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let outside = dir.path().join("outside");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("sub/inner.txt"), "inner").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+        symlink(outside.join("secret.txt"), root.join("file-out")).unwrap();
+        symlink(&outside, root.join("dir-out")).unwrap();
+        symlink("sub/inner.txt", root.join("file-in")).unwrap();
+        symlink("sub", root.join("dir-in")).unwrap();
+        symlink("../outside/missing.txt", root.join("dangling-out")).unwrap();
+        symlink("dir-out", root.join("sub-chain")).unwrap();
+        (dir, root)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escapes_follow_within_root() {
+        let (_dir, root) = symlink_tree();
+        let check = |p: &str| validate_file_path(&root, p, SymlinkPolicy::FollowWithinRoot);
+
+        assert!(check("file-out").is_err(), "link to a file outside");
+        assert!(check("dir-out/secret.txt").is_err(), "link to a directory outside");
+        assert!(check("sub-chain/secret.txt").is_err(), "chain of links ending outside");
+        assert!(check("dangling-out").is_err(), "dangling link (could be created outside)");
+        assert!(check("dir-out/new.txt").is_err(), "new file below a link outside");
+
+        assert_eq!(check("file-in").unwrap(), root.join("file-in"));
+        assert_eq!(check("dir-in/inner.txt").unwrap(), root.join("dir-in/inner.txt"));
+        assert_eq!(check("sub/new.txt").unwrap(), root.join("sub/new.txt"), "paths to be created are fine");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escapes_deny() {
+        let (_dir, root) = symlink_tree();
+        let check = |p: &str| validate_file_path(&root, p, SymlinkPolicy::Deny);
+
+        assert!(check("file-out").is_err());
+        assert!(check("dir-out/secret.txt").is_err());
+        assert!(check("dangling-out").is_err());
+        assert!(check("file-in").is_err(), "even links within the root are refused");
+        assert!(check("dir-in/inner.txt").is_err());
+        assert!(check("sub/inner.txt").is_ok());
+        assert!(check("sub/new.txt").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escapes_follow() {
+        let (_dir, root) = symlink_tree();
+        let check = |p: &str| validate_file_path(&root, p, SymlinkPolicy::Follow);
+
+        assert!(check("file-out").is_ok());
+        assert!(check("dir-out/secret.txt").is_ok());
+        assert!(check("../outside/secret.txt").is_err(), "traversal is still refused");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_root_behind_a_link() {
+        let (dir, root) = symlink_tree();
+        let linked_root = dir.path().join("linked-root");
+        std::os::unix::fs::symlink(&root, &linked_root).unwrap();
+
+        // The served directory itself may be a link, only what is below it is checked
+        assert!(validate_file_path(&linked_root, "sub/inner.txt", SymlinkPolicy::FollowWithinRoot).is_ok());
+        assert!(validate_file_path(&linked_root, "sub/inner.txt", SymlinkPolicy::Deny).is_ok());
+        assert!(validate_file_path(&linked_root, "file-out", SymlinkPolicy::FollowWithinRoot).is_err());
+    }
+
     #[test]
     fn test_nonexistent_path() {
         let path = PathBuf::from("/this/path/should/not/exist/at/all");
-        let result = validate_path(&path);
+        let result = validate_path(&path, &AccessMatcher::default());
         assert!(result.is_err());
         assert!(result.err().unwrap().to_string().contains("does not exist"));
     }
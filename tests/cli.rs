@@ -101,9 +101,579 @@ fn test_custom_bind_ip_used() {
         "Expected bind IP in output:\n{}", stdout);
 }
 
+#[test]
+fn test_per_protocol_bind_ip_and_dir() {
+    let shared = tempfile::tempdir().unwrap();
+    let tftp_dir = tempfile::tempdir().unwrap();
+    let shared_arg = format!("--serve-dir={}", shared.path().display());
+    let tftp_dir_arg = format!("--tftp-dir={}", tftp_dir.path().display());
+
+    let stdout = capture_startup_output(&[
+        "--headless", &shared_arg, "--bind-ip=127.0.0.1",
+        "--http=0", "--tftp=0", &tftp_dir_arg, "--tftp-bind=127.0.0.2",
+    ]);
+
+    let line = stdout.lines().find(|l| l.starts_with("READY "))
+        .unwrap_or_else(|| panic!("Expected a READY line in output:\n{}", stdout));
+    let ready: serde_json::Value = serde_json::from_str(&line["READY ".len()..]).unwrap();
+    assert!(ready["http"].as_str().unwrap().starts_with("127.0.0.1:"), "{}", ready);
+    assert!(ready["tftp"].as_str().unwrap().starts_with("127.0.0.2:"), "{}", ready);
+
+    for dir in [shared.path(), tftp_dir.path()] {
+        assert!(stdout.contains(&format!("Serving {}", dir.display())),
+            "Expected {} to be served:\n{}", dir.display(), stdout);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_bind_interface() {
+    let stdout = capture_startup_output(&["--headless", "--http=0", "--bind-iface=lo"]);
+    let line = stdout.lines().find(|l| l.starts_with("READY "))
+        .unwrap_or_else(|| panic!("Expected a READY line in output:\n{}", stdout));
+    assert!(line.contains("\"127.0.0.1:"), "Expected the loopback address:\n{}", line);
+
+    let mut cmd = Command::cargo_bin("quick-serve").unwrap();
+    cmd.args(["--headless", "--http=0", "--bind-iface=nonexistent0"]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicate::str::contains("No interface nonexistent0"));
+}
+
 #[test]
 fn test_verbose_flag_enables_debug_logs() {
     let stdout = capture_startup_output(&["--headless", "--http=17807", "-v"]);
     assert!(stdout.contains("DEBUG") || stdout.contains("debug") || stdout.contains("Spawn") || stdout.contains("spawn"),
         "Expected debug-level output with -v flag:\n{}", stdout);
 }
+
+#[test]
+fn test_ephemeral_ports_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let ready_file = dir.path().join("ready.json");
+    let ready_arg = format!("--ready-file={}", ready_file.display());
+    let stdout = capture_startup_output(&["--headless", "--http=0", "--tftp=0", &ready_arg]);
+
+    let line = stdout.lines().find(|l| l.starts_with("READY "))
+        .unwrap_or_else(|| panic!("Expected a READY line in output:\n{}", stdout));
+    let from_stdout: serde_json::Value = serde_json::from_str(&line["READY ".len()..]).unwrap();
+    let from_file: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&ready_file).unwrap()).unwrap();
+    assert_eq!(from_stdout, from_file);
+
+    for proto in ["http", "tftp"] {
+        let addr: std::net::SocketAddr = from_file[proto].as_str()
+            .unwrap_or_else(|| panic!("{} missing from ready file: {}", proto, from_file))
+            .parse().unwrap();
+        assert_ne!(addr.port(), 0, "{} should report the port picked by the OS", proto);
+        assert!(stdout.contains(&addr.to_string()), "Expected {} in the log:\n{}", addr, stdout);
+    }
+}
+
+#[test]
+fn test_client_commands_logged() {
+    let stdout = capture_startup_output(&["--headless", "--http=0", "--tftp=0", "--bind-ip=127.0.0.1", "--example-file=boot/Image"]);
+    let line = stdout.lines().find(|l| l.starts_with("READY "))
+        .unwrap_or_else(|| panic!("Expected a READY line in output:\n{}", stdout));
+    let ready: serde_json::Value = serde_json::from_str(&line["READY ".len()..]).unwrap();
+
+    let http = ready["http"].as_str().unwrap();
+    assert!(stdout.contains(&format!("HTTP with wget: wget http://{}/boot/Image", http)), "{}", stdout);
+    let tftp: std::net::SocketAddr = ready["tftp"].as_str().unwrap().parse().unwrap();
+    assert!(stdout.contains(&format!("TFTP with U-Boot: setenv serverip 127.0.0.1; setenv tftpdstp {}; tftpboot 0x80000000 boot/Image", tftp.port())),
+        "{}", stdout);
+}
+
+// ── Configuration file ────────────────────────────────────────────────────────
+
+#[test]
+fn test_servers_enabled_in_config_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("quick-serve.toml");
+    std::fs::write(&config, format!(
+        "serve_dir = \"{}\"\n[tftp]\nenabled = true\nport = 0\n[http]\nport = 1\n",
+        dir.path().display(),
+    )).unwrap();
+
+    let config_arg = format!("--config={}", config.display());
+    // HTTP is not enabled in the file, and its port is overridden from the command line
+    let stdout = capture_startup_output(&["--headless", &config_arg, "--http=0"]);
+
+    let line = stdout.lines().find(|l| l.starts_with("READY "))
+        .unwrap_or_else(|| panic!("Expected a READY line in output:\n{}", stdout));
+    let ready: serde_json::Value = serde_json::from_str(&line["READY ".len()..]).unwrap();
+    for proto in ["http", "tftp"] {
+        assert!(ready[proto].is_string(), "{} should be started: {}", proto, ready);
+    }
+    assert!(ready["ftp"].is_null(), "FTP is not enabled: {}", ready);
+}
+
+#[test]
+fn test_invalid_config_file_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("quick-serve.toml");
+    std::fs::write(&config, "[http]\nprot = 8080\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("quick-serve").unwrap();
+    cmd.arg("--headless").arg(format!("--config={}", config.display()));
+    cmd.assert()
+        .code(2)
+        .stdout(predicate::str::contains("Invalid config file"));
+}
+
+// ── Exit conditions ───────────────────────────────────────────────────────────
+
+/// Spawns quick-serve with the given args and waits for it to report the
+/// address its HTTP server is bound to
+fn spawn_http(args: &[&str]) -> (std::process::Child, std::net::SocketAddr) {
+    let (child, ready) = spawn_ready(args);
+    (child, ready["http"].as_str().unwrap().parse().unwrap())
+}
+
+/// Spawns quick-serve, returning the addresses of its servers once they are all listening
+fn spawn_ready(args: &[&str]) -> (std::process::Child, serde_json::Value) {
+    use std::io::{BufRead, BufReader};
+
+    let bin = std::env::var("CARGO_BIN_EXE_quick-serve")
+        .unwrap_or_else(|_| "target/debug/quick-serve".into());
+    let mut child = std::process::Command::new(&bin)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to spawn quick-serve");
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let ready = lines.by_ref().map_while(Result::ok)
+        .find_map(|l| l.strip_prefix("READY ").map(str::to_string))
+        .expect("Expected a READY line");
+    // Keep draining the log, so that it never blocks on a full pipe
+    thread::spawn(move || lines.for_each(drop));
+    (child, serde_json::from_str(&ready).unwrap())
+}
+
+/// Downloads a file over HTTP, returning the whole response
+fn http_get(addr: std::net::SocketAddr, path: &str) -> String {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET /{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Waits for the process to exit on its own, killing it if it takes too long
+fn wait_exit(mut child: std::process::Child) -> Option<i32> {
+    for _ in 0..100 {
+        if let Some(status) = child.try_wait().unwrap() {
+            return status.code();
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    child.kill().ok();
+    panic!("quick-serve did not exit on its own");
+}
+
+#[test]
+fn test_exits_after_max_transfers() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"a").unwrap();
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, "--max-transfers=2"]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    assert!(http_get(addr, "missing.bin").starts_with("HTTP/1.1 404"), "failed requests are not counted");
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(child.try_wait().ok().flatten(), None, "should still be serving");
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    assert_eq!(wait_exit(child), Some(0));
+}
+
+#[test]
+fn test_exits_after_given_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("fw")).unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"a").unwrap();
+    std::fs::write(dir.path().join("fw/b.bin"), b"b").unwrap();
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let (child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, "--exit-after=/fw/b.bin"]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    assert!(http_get(addr, "fw/b.bin").starts_with("HTTP/1.1 200"));
+    assert_eq!(wait_exit(child), Some(0));
+}
+
+#[test]
+fn test_exit_status_on_timeout() {
+    let (child, _) = spawn_http(&["--headless", "--http=0", "--timeout=300ms"]);
+    assert_eq!(wait_exit(child), Some(0), "serving for a while is a success");
+
+    let (child, _) = spawn_http(&["--headless", "--http=0", "--timeout=300ms", "--max-transfers=1"]);
+    assert_eq!(wait_exit(child), Some(124), "the download never happened");
+}
+
+#[test]
+fn test_invalid_timeout_rejected() {
+    let mut cmd = Command::cargo_bin("quick-serve").unwrap();
+    cmd.arg("--headless").arg("--http=0").arg("--timeout=5 days");
+    cmd.assert().failure().stderr(predicate::str::contains("Invalid duration"));
+}
+
+// ── Graceful shutdown ─────────────────────────────────────────────────────────
+
+/// Size of the file downloaded while stopping, large enough not to fit in the socket buffers
+const LARGE_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Starts downloading a large file over HTTP, reading only the beginning of it
+fn start_download(dir: &std::path::Path, addr: std::net::SocketAddr) -> std::net::TcpStream {
+    use std::io::{Read, Write};
+
+    std::fs::write(dir.join("large.bin"), vec![7u8; LARGE_FILE_SIZE]).unwrap();
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET /large.bin HTTP/1.1\r\nHost: {}\r\n\r\n", addr).unwrap();
+    stream.read_exact(&mut [0u8; 65536]).unwrap();
+    stream
+}
+
+#[cfg(unix)]
+fn signal(child: &std::process::Child, name: &str) {
+    Command::new("kill").args([&format!("-{}", name), &child.id().to_string()]).assert().success();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctrlc_lets_transfers_complete() {
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (child, addr) = spawn_http(&["--headless", "--http=0", &format!("-d={}", dir.path().display()), "--grace-period=10s"]);
+    let mut stream = start_download(dir.path(), addr);
+
+    signal(&child, "INT");
+    thread::sleep(Duration::from_millis(300));
+    assert!(std::net::TcpStream::connect(addr).is_err(), "no new connection should be accepted once stopping");
+
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.len() >= LARGE_FILE_SIZE - 65536, "the download should complete, got {} bytes", rest.len());
+    assert_eq!(wait_exit(child), Some(0));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_transfers_cut_off_after_grace_period() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("quick-serve.log");
+    let (child, addr) = spawn_http(&["--headless", "--http=0", &format!("-d={}", dir.path().display()),
+        "--grace-period=1s", &format!("--log-file={}", log.display())]);
+    let _stream = start_download(dir.path(), addr);
+
+    signal(&child, "INT");
+    assert_eq!(wait_exit(child), Some(0));
+    let logs = std::fs::read_to_string(&log).unwrap();
+    assert!(logs.contains("HTTP server waiting up to 1s for 1 transfer(s) to complete"), "{}", logs);
+    assert!(logs.contains("HTTP transfer of large.bin with 127.0.0.1:"), "{}", logs);
+    assert!(logs.contains("cut off after"), "{}", logs);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_second_ctrlc_exits_now() {
+    let dir = tempfile::tempdir().unwrap();
+    let (child, addr) = spawn_http(&["--headless", "--http=0", &format!("-d={}", dir.path().display()), "--grace-period=1h"]);
+    let _stream = start_download(dir.path(), addr);
+
+    signal(&child, "INT");
+    thread::sleep(Duration::from_millis(300));
+    signal(&child, "INT");
+    assert_eq!(wait_exit(child), Some(130));
+}
+
+// ── Metrics ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_metrics_endpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"abc").unwrap();
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, "--metrics=17911"]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    let metrics = http_get("127.0.0.1:17911".parse().unwrap(), "metrics");
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(metrics.starts_with("HTTP/1.1 200"), "unexpected response: {}", metrics);
+    for line in [
+        "quick_serve_up{protocol=\"http\"} 1",
+        "quick_serve_up{protocol=\"ftp\"} 0",
+        "quick_serve_downloads_total{protocol=\"http\"} 1",
+        "quick_serve_sent_bytes_total{protocol=\"http\"} 3",
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "missing {} in:\n{}", line, metrics);
+    }
+}
+
+// ── Logs ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_json_log_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"abc").unwrap();
+    let log = tempfile::tempdir().unwrap();
+    let log_path = log.path().join("quick-serve.log");
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let log_arg = format!("--log-file={}", log_path.display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, "--log-format=json", &log_arg]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    child.kill().ok();
+    child.wait().ok();
+
+    let logs = std::fs::read_to_string(&log_path).unwrap();
+    let records: Vec<serde_json::Value> = logs.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let download = records.iter().find(|r| r["event"] == "download").expect("no download record");
+    assert_eq!(download["protocol"], "http");
+    assert_eq!(download["path"], "a.bin");
+    assert_eq!(download["size"], 3);
+    assert!(download["client"].as_str().unwrap().starts_with("127.0.0.1:"));
+}
+
+#[test]
+fn test_log_filter() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"abc").unwrap();
+    let log = tempfile::tempdir().unwrap();
+    let log_path = log.path().join("quick-serve.log");
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let log_arg = format!("--log-file={}", log_path.display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, "--log-format=json", "--log-filter=tftp:debug", &log_arg]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    child.kill().ok();
+    child.wait().ok();
+
+    let logs = std::fs::read_to_string(&log_path).unwrap();
+    assert!(!logs.is_empty(), "general records are kept");
+    for line in logs.lines() {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_ne!(record["protocol"], "http", "unexpected record: {}", line);
+    }
+}
+
+#[test]
+fn test_invalid_log_filter_rejected() {
+    let mut cmd = Command::cargo_bin("quick-serve").unwrap();
+    cmd.arg("--headless").arg("--http=0").arg("--log-filter=smtp");
+    cmd.assert().failure().stderr(predicate::str::contains("Unknown protocol"));
+}
+
+#[test]
+fn test_access_log() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"abc").unwrap();
+    let log = tempfile::tempdir().unwrap();
+    let log_path = log.path().join("access.log");
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let log_arg = format!("--access-log={}", log_path.display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &dir_arg, &log_arg]);
+
+    assert!(http_get(addr, "a.bin").starts_with("HTTP/1.1 200"));
+    assert!(http_get(addr, "missing.bin").starts_with("HTTP/1.1 404"));
+
+    // Lines are written once the responses are done with, possibly after the client got them
+    let mut logs = String::new();
+    for _ in 0..50 {
+        logs = std::fs::read_to_string(&log_path).unwrap();
+        if logs.lines().count() >= 2 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    child.kill().ok();
+    child.wait().ok();
+
+    let lines: Vec<&str> = logs.lines().collect();
+    assert_eq!(lines.len(), 2, "unexpected access log:\n{}", logs);
+    assert!(lines[0].starts_with("127.0.0.1 - - ["), "unexpected line: {}", lines[0]);
+    assert!(lines[0].contains("\"GET /a.bin HTTP/1.1\" 200 3 "), "unexpected line: {}", lines[0]);
+    assert!(lines[1].contains("\"GET /missing.bin HTTP/1.1\" 404 "), "unexpected line: {}", lines[1]);
+}
+
+#[test]
+fn test_ftp_xferlog() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("a.bin"), b"abc").unwrap();
+    let etc = tempfile::tempdir().unwrap();
+    let config = etc.path().join("quick-serve.toml");
+    std::fs::write(&config, "[ftp]\nauth = { username = \"user\", password = \"secret\" }\n").unwrap();
+    let log_path = etc.path().join("access.log");
+    let (mut child, ready) = spawn_ready(&["--headless", "-b=127.0.0.1", "--ftp=0", &format!("--config={}", config.display()),
+        &format!("--serve-dir={}", dir.path().display()), &format!("--access-log={}", log_path.display())]);
+
+    Command::cargo_bin("quick-serve").unwrap()
+        .args(["client", "ftp", "get", ready["ftp"].as_str().unwrap(), "a.bin", "-", "--user=user:secret"])
+        .assert().success().stdout("abc");
+
+    let mut logs = String::new();
+    for _ in 0..50 {
+        logs = std::fs::read_to_string(&log_path).unwrap_or_default();
+        if !logs.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    child.kill().ok();
+    child.wait().ok();
+
+    // The remote host and the user the session logged in as
+    assert!(logs.contains(" 127.0.0.1 3 /a.bin b _ o r user ftp 0 * c"), "unexpected access log:\n{}", logs);
+}
+
+// ── Reload ────────────────────────────────────────────────────────────────────
+
+/// Polls a file over HTTP until served, as reloads are handed over asynchronously
+fn http_get_eventually(addr: std::net::SocketAddr, path: &str) -> String {
+    for _ in 0..100 {
+        let response = http_get(addr, path);
+        if response.starts_with("HTTP/1.1 200") {
+            return response;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("{} was never served", path);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reload_config() {
+    let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+    for (i, dir) in dirs.iter().enumerate() {
+        std::fs::write(dir.path().join(format!("{}.txt", i)), format!("dir {}", i)).unwrap();
+    }
+    let config_dir = tempfile::tempdir().unwrap();
+    let config = config_dir.path().join("quick-serve.toml");
+    let write_config = |i: usize| std::fs::write(&config, format!("serve_dir = \"{}\"\n", dirs[i].path().display())).unwrap();
+    write_config(0);
+
+    let (mut child, addr) = spawn_http(&["--headless", &format!("--config={}", config.display()), "--http=0", "--watch-config"]);
+    assert!(http_get(addr, "0.txt").ends_with("dir 0"));
+
+    // On SIGHUP, the same server serves the new directory
+    write_config(1);
+    Command::new("kill").args(["-HUP", &child.id().to_string()]).assert().success();
+    assert!(http_get_eventually(addr, "1.txt").ends_with("dir 1"));
+    assert!(http_get(addr, "0.txt").starts_with("HTTP/1.1 404"));
+
+    // Then as soon as the watched file changes, an invalid one being ignored
+    std::fs::write(&config, "serve_dir = [").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    assert!(http_get(addr, "1.txt").ends_with("dir 1"));
+    write_config(2);
+    assert!(http_get_eventually(addr, "2.txt").ends_with("dir 2"));
+    child.kill().ok();
+    child.wait().ok();
+}
+
+// ── Web interface ─────────────────────────────────────────────────────────────
+
+/// Sends a request to the web interface, returning the full response
+fn admin_request(addr: std::net::SocketAddr, method: &str, path: &str, token: Option<&str>, body: &str) -> String {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    let auth = token.map(|t| format!("Authorization: Bearer {}\r\n", t)).unwrap_or_default();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method, path, addr, auth, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_web_interface() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = format!("--serve-dir={}", dir.path().display());
+    let (mut child, _) = spawn_http(&["--headless", "--http=0", &dir_arg, "--admin=17912", "--admin-token=secret"]);
+    let admin: std::net::SocketAddr = "127.0.0.1:17912".parse().unwrap();
+
+    assert!(admin_request(admin, "GET", "/", None, "").starts_with("HTTP/1.1 200"), "the page needs no token");
+    assert!(admin_request(admin, "GET", "/api/state", None, "").starts_with("HTTP/1.1 401"));
+    assert!(admin_request(admin, "GET", "/api/state", Some("wrong"), "").starts_with("HTTP/1.1 401"));
+
+    let state = admin_request(admin, "GET", "/api/state?token=secret", None, "");
+    assert!(state.starts_with("HTTP/1.1 200"), "unexpected response: {}", state);
+    assert!(state.contains("\"protocol\":\"http\""));
+
+    let started = admin_request(admin, "POST", "/api/servers/tftp", Some("secret"), "{\"start\":true,\"port\":0}");
+    assert!(started.starts_with("HTTP/1.1 200"), "unexpected response: {}", started);
+    let mut listening = false;
+    for _ in 0..50 {
+        let state = admin_request(admin, "GET", "/api/state", Some("secret"), "");
+        let json: serde_json::Value = serde_json::from_str(state.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let tftp = json["servers"].as_array().unwrap().iter().find(|s| s["protocol"] == "tftp").unwrap().clone();
+        if tftp["status"] == "listening" {
+            listening = true;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(admin_request(admin, "POST", "/api/servers/smtp", Some("secret"), "{}").starts_with("HTTP/1.1 404"));
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(listening, "TFTP was not started from the web interface");
+}
+
+// ── Control API ───────────────────────────────────────────────────────────────
+
+#[cfg(unix)]
+#[test]
+fn test_control_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("qs.sock");
+    let socket_arg = format!("--socket={}", socket.display());
+    let (mut child, addr) = spawn_http(&["--headless", "--http=0", &format!("--control={}", socket.display())]);
+    let ctl = |args: &[&str]| Command::cargo_bin("quick-serve").unwrap().arg("ctl").arg(&socket_arg).args(args).assert();
+
+    ctl(&["status"]).success().stdout(predicate::str::contains(format!("HTTP  listening {}", addr)));
+    ctl(&["start", "tftp", "--port=0"]).success().stdout(predicate::str::contains("TFTP  listening 127.0.0.1:"));
+    ctl(&["start", "ftp", "--bind-ip=nope"]).failure().stderr(predicate::str::contains("400 Bad Request"));
+    ctl(&["start", "smtp"]).failure().code(2);
+    ctl(&["stop", "tftp"]).success().stdout(predicate::str::contains("TFTP  stopped"));
+    ctl(&["--json", "stats"]).success().stdout(predicate::str::contains("\"summary\""));
+    ctl(&["logs"]).success().stdout(predicate::str::contains("[INFO] tftp:"));
+    child.kill().ok();
+    child.wait().ok();
+
+    Command::cargo_bin("quick-serve").unwrap().args(["ctl", &socket_arg, "status"])
+        .assert().failure().stderr(predicate::str::contains("Failed to connect"));
+}
+
+// ── Client ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_client_ftp_put_ls_get() {
+    let dir = tempfile::tempdir().unwrap();
+    let local = dir.path().join("up.bin");
+    std::fs::write(&local, b"firmware").unwrap();
+    let served = tempfile::tempdir().unwrap();
+    let (mut child, ready) = spawn_ready(&["--headless", "-b=127.0.0.1", "--ftp=0", "--http=0",
+        &format!("-d={}", served.path().display())]);
+    let ftp = ready["ftp"].as_str().unwrap().to_string();
+    let client = |args: &[&str]| Command::cargo_bin("quick-serve").unwrap().arg("client").args(args).assert();
+
+    client(&["ftp", "put", &ftp, local.to_str().unwrap(), "fw.bin"]).success()
+        .stderr(predicate::str::contains("Uploaded fw.bin (8 B)"));
+    client(&["ftp", "ls", &ftp]).success().stdout(predicate::str::contains("fw.bin"));
+    client(&["ftp", "get", &ftp, "fw.bin", "-"]).success().stdout("firmware");
+    client(&["http", "get", &format!("http://{}/fw.bin", ready["http"].as_str().unwrap()), "-"]).success()
+        .stdout("firmware");
+    client(&["ftp", "get", &ftp, "nope.bin", dir.path().join("nope.bin").to_str().unwrap()]).failure()
+        .stderr(predicate::str::contains("550"));
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(!dir.path().join("nope.bin").exists() && !dir.path().join("nope.bin.part").exists());
+}
@@ -33,6 +33,45 @@ pub fn test_server_e2e(
     dl_cmd: String,
     file_in: &str,
     file_out: &str,
+) -> Result<bool, String> {
+    serve_and_download(proto, port, file_in, file_out, move || {
+        let mut cmd = Command::new("sh");
+        cmd.timeout(Duration::from_secs(3));
+        cmd.arg("-c");
+        cmd.arg(&dl_cmd);
+        cmd.env("PATH", "/bin");
+        cmd.unwrap();
+    })
+}
+
+/// Same as [`test_server_e2e`], downloading with `quick-serve client` and
+/// the given arguments instead of an external tool
+pub fn test_client_e2e(
+    proto: &str,
+    port: u16,
+    client: &[&str],
+    file_in: &str,
+    file_out: &str,
+) -> Result<bool, String> {
+    let client: Vec<String> = client.iter().map(|arg| arg.to_string()).collect();
+    // Not to pass on the output of a previous run
+    let _ = fs::remove_file(file_out);
+
+    serve_and_download(proto, port, file_in, file_out, move || {
+        let mut cmd = Command::cargo_bin("quick-serve").unwrap();
+        cmd.timeout(Duration::from_secs(3));
+        cmd.arg("client").args(&client);
+        // Whether the download succeeded is told by the output file
+        cmd.output().unwrap();
+    })
+}
+
+fn serve_and_download(
+    proto: &str,
+    port: u16,
+    file_in: &str,
+    file_out: &str,
+    download: impl FnOnce() + Send + 'static,
 ) -> Result<bool, String> {
     let dir_path = make_tmp(file_in)
         .map_err(|e| format!("Failed to create temp directory: {}", e))?;
@@ -55,12 +94,7 @@ pub fn test_server_e2e(
 
     let client = thread::spawn(move || {
         thread::sleep(Duration::from_millis(700));
-        let mut cmd = Command::new("sh");
-        cmd.timeout(Duration::from_secs(3));
-        cmd.arg("-c");
-        cmd.arg(&dl_cmd);
-        cmd.env("PATH", "/bin");
-        cmd.unwrap()
+        download()
     });
 
     let out_client = client.join();
@@ -10,7 +10,7 @@ fn build_images() {
         .arg("build")
         .current_dir(format!("{cwd}/docker/"))
         .output()
-        .expect(&format!("Failed to execute command. Check directory {}", cwd));
+        .unwrap_or_else(|_| panic!("Failed to execute command. Check directory {}", cwd));
 }
 
 fn run_command(args: &str, wait_for: &str) -> (String, String) {
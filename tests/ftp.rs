@@ -1,6 +1,6 @@
 mod common;
 
-use common::test_server_e2e;
+use common::{test_client_e2e, test_server_e2e};
 
 #[test]
 fn test_file_download_success() {
@@ -47,3 +47,47 @@ fn test_path_traversal_blocked() {
     assert!(err_msg.contains("does not exist") || err_msg.contains("empty"),
         "Expected file not found or empty file error, got: {}", err_msg);
 }
+
+#[test]
+fn test_client_download_success() {
+    let port = 2323u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-ftp-client.bin";
+    let server = format!("127.0.0.1:{}", port);
+    let client = ["ftp", "get", server.as_str(), file_in, file_out];
+    let result = test_client_e2e("ftp", port, &client, file_in, file_out);
+    assert!(result.is_ok(), "Test failed: {:?}", result.err());
+}
+
+#[test]
+fn test_client_file_not_found() {
+    let port = 2324u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-ftp-client-404.bin";
+    let server = format!("127.0.0.1:{}", port);
+    let client = ["ftp", "get", server.as_str(), "nonexistent.bin", file_out];
+    let result = test_client_e2e("ftp", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for non-existent file");
+}
+
+#[test]
+fn test_client_path_is_directory() {
+    let port = 2325u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-ftp-client-dir.bin";
+    let server = format!("127.0.0.1:{}", port);
+    let client = ["ftp", "get", server.as_str(), "nonexistent_subdir/file.txt", file_out];
+    let result = test_client_e2e("ftp", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for non-existent directory path");
+}
+
+#[test]
+fn test_client_path_traversal_blocked() {
+    let port = 2326u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-ftp-client-traversal.bin";
+    let server = format!("127.0.0.1:{}", port);
+    let client = ["ftp", "get", server.as_str(), "../../etc/passwd", file_out];
+    let result = test_client_e2e("ftp", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for path traversal attempt");
+}
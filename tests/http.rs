@@ -1,6 +1,6 @@
 mod common;
 
-use common::test_server_e2e;
+use common::{test_client_e2e, test_server_e2e};
 
 #[test]
 fn test_file_download_success() {
@@ -49,3 +49,49 @@ fn test_path_traversal_blocked() {
     assert!(err_msg.contains("empty") || err_msg.contains("does not exist"),
         "Expected empty file or non-existent error, got: {}", err_msg);
 }
+
+#[test]
+fn test_client_download_success() {
+    let port = 8179u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-http-client.bin";
+    let url = format!("http://127.0.0.1:{}/{}", port, file_in);
+    let client = ["http", "get", url.as_str(), file_out];
+    let result = test_client_e2e("http", port, &client, file_in, file_out);
+    assert!(result.is_ok(), "Test failed: {:?}", result.err());
+}
+
+#[test]
+fn test_client_file_not_found() {
+    let port = 8180u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-http-client-404.bin";
+    let url = format!("http://127.0.0.1:{}/nonexistent.bin", port);
+    let client = ["http", "get", url.as_str(), file_out];
+    let result = test_client_e2e("http", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for non-existent file");
+    let err_msg = result.unwrap_err();
+    assert!(err_msg.contains("does not exist"), "Expected no output file on error, got: {}", err_msg);
+}
+
+#[test]
+fn test_client_path_is_directory() {
+    let port = 8181u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-http-client-dir.bin";
+    let url = format!("http://127.0.0.1:{}/", port);
+    let client = ["http", "get", url.as_str(), file_out];
+    let result = test_client_e2e("http", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for directory path");
+}
+
+#[test]
+fn test_client_path_traversal_blocked() {
+    let port = 8182u16;
+    let file_in = "data.bin";
+    let file_out = "/tmp/data-out-http-client-traversal.bin";
+    let url = format!("http://127.0.0.1:{}/../../etc/passwd", port);
+    let client = ["http", "get", url.as_str(), file_out];
+    let result = test_client_e2e("http", port, &client, file_in, file_out);
+    assert!(result.is_err(), "Expected failure for path traversal attempt");
+}
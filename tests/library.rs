@@ -0,0 +1,354 @@
+#[allow(dead_code)]
+mod common;
+
+use common::{compare_files, make_tmp};
+use quick_serve::{AccessRules, Config, Credentials, Protocol, QuickServe, ServerRegistry, SymlinkPolicy, TransferState};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Minimal TFTP client, fetching a whole file in octet mode
+fn tftp_get(server: SocketAddr, filename: &str) -> std::io::Result<Vec<u8>> {
+    let local = if server.is_ipv6() { "[::1]:0" } else { "127.0.0.1:0" };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    let mut rrq = vec![0, 1];
+    rrq.extend_from_slice(filename.as_bytes());
+    rrq.push(0);
+    rrq.extend_from_slice(b"octet");
+    rrq.push(0);
+    socket.send_to(&rrq, server)?;
+
+    let mut data = Vec::new();
+    let mut buf = [0u8; 516];
+    let mut expected = 1u16;
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf)?;
+        let opcode = u16::from_be_bytes([buf[0], buf[1]]);
+        let block = u16::from_be_bytes([buf[2], buf[3]]);
+        if opcode == 5 {
+            return Err(std::io::Error::other(format!("TFTP error {}", block)));
+        }
+        if block == expected {
+            data.extend_from_slice(&buf[4..len]);
+            expected = expected.wrapping_add(1);
+        }
+        socket.send_to(&[0, 4, buf[2], buf[3]], peer)?;
+        if len < 516 {
+            return Ok(data);
+        }
+    }
+}
+
+async fn http_get(server: SocketAddr, path: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(server).await?;
+    let req = format!("GET /{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, server);
+    stream.write_all(req.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_embedded_http_and_tftp() {
+    let dir = make_tmp("data.bin").unwrap();
+
+    let handle = QuickServe::builder()
+        .dir(&dir)
+        .http(0)
+        .tftp(0)
+        .spawn()
+        .await
+        .expect("servers should start");
+
+    let http_addr = handle.bound_addr(&Protocol::Http).expect("HTTP should be bound");
+    let tftp_addr = handle.bound_addr(&Protocol::Tftp).expect("TFTP should be bound");
+    assert_ne!(http_addr.port(), 0, "the ephemeral port picked should be reported");
+    assert_ne!(tftp_addr.port(), 0, "the ephemeral port picked should be reported");
+    assert_eq!(handle.bound_addr(&Protocol::Ftp), None);
+
+    let response = http_get(http_addr, "data.bin").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "unexpected response: {}", response);
+
+    let data = tokio::task::spawn_blocking(move || tftp_get(tftp_addr, "data.bin"))
+        .await
+        .unwrap()
+        .expect("TFTP download should succeed");
+    let file_out = dir.join("data-out.bin");
+    std::fs::write(&file_out, data).unwrap();
+    assert!(compare_files(&dir.join("data.bin"), &file_out).unwrap());
+
+    let stats = handle.stats();
+    assert_eq!(stats[&Protocol::Http].requests, 1);
+    assert_eq!(stats[&Protocol::Http].bytes_sent, 1000);
+    assert_eq!(stats[&Protocol::Tftp].requests, 1);
+
+    handle.shutdown().await;
+    assert!(TcpStream::connect(http_addr).await.is_err(), "HTTP should be stopped after shutdown");
+}
+
+#[tokio::test]
+async fn test_embedded_ftp_on_ephemeral_port() {
+    let dir = make_tmp("data.bin").unwrap();
+    let handle = QuickServe::builder().dir(&dir).ftp(0).spawn().await.expect("FTP should start");
+
+    let addr = handle.bound_addr(&Protocol::Ftp).expect("FTP should be bound");
+    assert_ne!(addr.port(), 0);
+
+    let mut stream = TcpStream::connect(addr).await.expect("FTP should accept connections");
+    let mut greeting = [0u8; 3];
+    stream.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(&greeting, b"220");
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_spawn_fails_on_invalid_dir() {
+    let result = QuickServe::builder()
+        .dir("/this/path/should/not/exist/at/all")
+        .http(0)
+        .spawn()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_http_basic_auth_from_config() {
+    let dir = make_tmp("data.bin").unwrap();
+    let mut config = Config::default();
+    config.http.auth = Some(Credentials { username: "user".into(), password: "secret".into() });
+
+    let handle = QuickServe::builder()
+        .dir(&dir)
+        .http(0)
+        .registry(ServerRegistry::from_config(&config))
+        .spawn()
+        .await
+        .expect("HTTP should start");
+    let addr = handle.bound_addr(&Protocol::Http).unwrap();
+
+    let response = http_get(addr, "data.bin").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 401"), "unexpected response: {}", response);
+
+    // "user:secret", base64 encoded
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let req = format!("GET /data.bin HTTP/1.1\r\nHost: {}\r\nAuthorization: Basic dXNlcjpzZWNyZXQ=\r\nConnection: close\r\n\r\n", addr);
+    stream.write_all(req.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200"), "unexpected response: {}", response);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_multiple_listeners_share_root_and_stats() {
+    let dir = make_tmp("data.bin").unwrap();
+    let handle = QuickServe::builder()
+        .dir(&dir)
+        .bind_ip("127.0.0.1, [::1]")
+        .http(0)
+        .tftp(0)
+        .spawn()
+        .await
+        .expect("servers should start on both addresses");
+
+    let http_addrs = handle.bound_addrs(&Protocol::Http);
+    assert_eq!(http_addrs.len(), 2, "one listener per bind address: {:?}", http_addrs);
+    assert!(http_addrs[0].is_ipv4() && http_addrs[1].is_ipv6());
+    for addr in &http_addrs {
+        let response = http_get(*addr, "data.bin").await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "unexpected response from {}: {}", addr, response);
+    }
+
+    let tftp_addrs = handle.bound_addrs(&Protocol::Tftp);
+    assert_eq!(tftp_addrs.len(), 2);
+    for addr in tftp_addrs {
+        let data = tokio::task::spawn_blocking(move || tftp_get(addr, "data.bin"))
+            .await
+            .unwrap()
+            .unwrap_or_else(|e| panic!("TFTP download from {} should succeed: {}", addr, e));
+        assert_eq!(data.len(), 1000);
+    }
+
+    let stats = handle.stats();
+    assert_eq!(stats[&Protocol::Http].requests, 2);
+    assert_eq!(stats[&Protocol::Tftp].requests, 2);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mounts_shared_by_http_and_tftp() {
+    let root = make_tmp("data.bin").unwrap();
+    let firmware = make_tmp("fw.bin").unwrap();
+    let kernel = make_tmp("Image").unwrap().join("Image");
+
+    let handle = QuickServe::builder()
+        .dir(&root)
+        .mount(format!("/fw={}", firmware.display()).parse().unwrap())
+        .mount(format!("/boot/kernel={}", kernel.display()).parse().unwrap())
+        .http(0)
+        .tftp(0)
+        .spawn()
+        .await
+        .expect("servers should start");
+    let http_addr = handle.bound_addr(&Protocol::Http).unwrap();
+    let tftp_addr = handle.bound_addr(&Protocol::Tftp).unwrap();
+
+    for path in ["data.bin", "fw/fw.bin", "boot/kernel"] {
+        let response = http_get(http_addr, path).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "unexpected response for {}: {}", path, response);
+
+        let path = path.to_string();
+        let data = tokio::task::spawn_blocking(move || tftp_get(tftp_addr, &path))
+            .await
+            .unwrap()
+            .expect("TFTP download should succeed");
+        assert_eq!(data.len(), 1000);
+    }
+
+    let response = http_get(http_addr, "fw/../data.bin").await.unwrap();
+    assert!(!response.starts_with("HTTP/1.1 200"), "traversal out of a mount should fail: {}", response);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_spawn_fails_on_missing_mount_source() {
+    let dir = make_tmp("data.bin").unwrap();
+    let result = QuickServe::builder()
+        .dir(&dir)
+        .mount("/fw=/this/path/should/not/exist/at/all".parse().unwrap())
+        .http(0)
+        .spawn()
+        .await;
+    assert!(result.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_symlink_policy_applies_to_http_and_tftp() {
+    let root = make_tmp("data.bin").unwrap();
+    let outside = make_tmp("secret.bin").unwrap();
+    std::os::unix::fs::symlink(outside.join("secret.bin"), root.join("escape.bin")).unwrap();
+    std::os::unix::fs::symlink("data.bin", root.join("alias.bin")).unwrap();
+
+    for (policy, served) in [
+        (SymlinkPolicy::Follow, ["data.bin", "alias.bin", "escape.bin"].as_slice()),
+        (SymlinkPolicy::FollowWithinRoot, ["data.bin", "alias.bin"].as_slice()),
+        (SymlinkPolicy::Deny, ["data.bin"].as_slice()),
+    ] {
+        let handle = QuickServe::builder()
+            .dir(&root)
+            .symlinks(policy)
+            .http(0)
+            .tftp(0)
+            .spawn()
+            .await
+            .expect("servers should start");
+        let http_addr = handle.bound_addr(&Protocol::Http).unwrap();
+        let tftp_addr = handle.bound_addr(&Protocol::Tftp).unwrap();
+
+        for path in ["data.bin", "alias.bin", "escape.bin"] {
+            let expected = served.contains(&path);
+            let response = http_get(http_addr, path).await.unwrap();
+            assert_eq!(response.starts_with("HTTP/1.1 200"), expected, "HTTP {} with {:?}: {}", path, policy, response);
+
+            let name = path.to_string();
+            let data = tokio::task::spawn_blocking(move || tftp_get(tftp_addr, &name)).await.unwrap();
+            assert_eq!(data.is_ok(), expected, "TFTP {} with {:?}: {:?}", path, policy, data.err());
+        }
+
+        handle.shutdown().await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_access_rules_apply_to_http_and_tftp() {
+    let root = make_tmp("data.bin").unwrap();
+    std::fs::write(root.join(".env"), "SECRET=1").unwrap();
+    std::fs::create_dir(root.join(".git")).unwrap();
+    std::fs::write(root.join(".git/config"), "[core]").unwrap();
+
+    for (rules, hidden_served) in [
+        (AccessRules::default(), false),
+        (AccessRules { unrestricted: true, ..Default::default() }, true),
+    ] {
+        let handle = QuickServe::builder()
+            .dir(&root)
+            .access(rules)
+            .http(0)
+            .tftp(0)
+            .spawn()
+            .await
+            .expect("servers should start");
+        let http_addr = handle.bound_addr(&Protocol::Http).unwrap();
+        let tftp_addr = handle.bound_addr(&Protocol::Tftp).unwrap();
+
+        for (path, expected) in [("data.bin", true), (".env", hidden_served), (".git/config", hidden_served)] {
+            let response = http_get(http_addr, path).await.unwrap();
+            assert_eq!(response.starts_with("HTTP/1.1 200"), expected, "HTTP {}: {}", path, response);
+
+            let name = path.to_string();
+            let data = tokio::task::spawn_blocking(move || tftp_get(tftp_addr, &name)).await.unwrap();
+            assert_eq!(data.is_ok(), expected, "TFTP {}: {:?}", path, data.err());
+        }
+
+        handle.shutdown().await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_completed_downloads_are_counted() {
+    let dir = make_tmp("data.bin").unwrap();
+    let handle = QuickServe::builder()
+        .dir(&dir)
+        .http(0)
+        .tftp(0)
+        .spawn()
+        .await
+        .expect("servers should start");
+
+    let http_addr = handle.bound_addr(&Protocol::Http).unwrap();
+    let tftp_addr = handle.bound_addr(&Protocol::Tftp).unwrap();
+    http_get(http_addr, "data.bin").await.unwrap();
+    http_get(http_addr, "missing.bin").await.unwrap();
+    tokio::task::spawn_blocking(move || tftp_get(tftp_addr, "data.bin")).await.unwrap().unwrap();
+
+    // The TFTP transfer is over once the server handles the last acknowledgement
+    let mut stats = handle.stats();
+    for _ in 0..50 {
+        if stats[&Protocol::Tftp].transfers > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        stats = handle.stats();
+    }
+    assert_eq!(stats[&Protocol::Http].transfers, 1, "failed requests are not downloads");
+    assert_eq!(stats[&Protocol::Tftp].transfers, 1);
+    assert_eq!(stats[&Protocol::Http].bytes_sent, 1000);
+
+    let finished = handle.finished_transfers();
+    assert_eq!(finished.len(), 2);
+    for transfer in &finished {
+        assert_eq!(transfer.path, "data.bin");
+        assert_eq!(transfer.state, TransferState::Completed);
+        assert_eq!(transfer.bytes, 1000);
+        assert_eq!(transfer.progress(), Some(1.0));
+        assert!(transfer.client.is_some_and(|c| c.ip().is_loopback()), "unexpected client: {:?}", transfer.client);
+    }
+    assert!(handle.active_transfers().is_empty());
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_spawn_fails_on_denied_dir() {
+    let result = QuickServe::builder().dir("/etc").http(0).spawn().await;
+    assert!(result.is_err(), "system directories are denied by default");
+}